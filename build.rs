@@ -0,0 +1,4 @@
+// sqlx::migrate! 在编译时嵌入迁移文件，迁移文件变化时需要重新编译
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,141 @@
+-- weihuda 初始表结构，表名不带库名前缀，由 migrate 命令连接到对应的库后执行
+
+CREATE TABLE IF NOT EXISTS announcement (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    title VARCHAR(128) NOT NULL,
+    content TEXT NOT NULL,
+    url VARCHAR(512) NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS notices (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    content TEXT NOT NULL,
+    url VARCHAR(512) NULL,
+    stuId VARCHAR(32) NOT NULL,
+    -- 0 未读，1 已读
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    isShow INT UNSIGNED NOT NULL DEFAULT 1,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_notices_stu_id (stuId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS zhihus (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    title VARCHAR(128) NOT NULL,
+    -- article 或 link
+    typ VARCHAR(16) NOT NULL,
+    content MEDIUMTEXT NOT NULL,
+    tags VARCHAR(255) NOT NULL DEFAULT '',
+    cover VARCHAR(512) NULL,
+    -- 0 待审核，1 已通过，2 已拒绝
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    stuId VARCHAR(32) NOT NULL,
+    top INT UNSIGNED NOT NULL DEFAULT 0,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS mini_configs (
+    `key` VARCHAR(64) NOT NULL,
+    `value` TEXT NOT NULL,
+    PRIMARY KEY (`key`)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS feedbacks (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    contact VARCHAR(128) NULL,
+    `desc` TEXT NOT NULL,
+    imgUrl VARCHAR(1024) NULL,
+    stuId VARCHAR(32) NULL,
+    -- 0 待处理，1 处理中，2 解决中，3 已关闭
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS feedback_msg (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    typ VARCHAR(16) NOT NULL,
+    msg TEXT NULL,
+    stuId VARCHAR(32) NOT NULL,
+    feedbackId INT UNSIGNED NOT NULL,
+    createdAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_feedback_msg_feedback_id (feedbackId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+-- 小程序用户的绑定信息，这里只用到积分
+CREATE TABLE IF NOT EXISTS mini_bind (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    stuId VARCHAR(32) NOT NULL,
+    jifen INT NOT NULL DEFAULT 0,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_mini_bind_stu_id (stuId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS jifen_goods (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(128) NOT NULL,
+    cover VARCHAR(512) NOT NULL,
+    count INT UNSIGNED NOT NULL DEFAULT 0,
+    price INT NOT NULL,
+    description TEXT NULL,
+    enabled INT UNSIGNED NOT NULL DEFAULT 1,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS jifen_exchange (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    stuId VARCHAR(32) NOT NULL,
+    goodsId INT UNSIGNED NOT NULL,
+    -- 0 待确认，1 待领取，2 已领取
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    receiveTime DATETIME NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_jifen_exchange_stu_id (stuId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS jifen_records (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `key` VARCHAR(64) NOT NULL,
+    `param` VARCHAR(255) NOT NULL DEFAULT '',
+    stuId VARCHAR(32) NOT NULL,
+    `desc` VARCHAR(255) NOT NULL,
+    jifen INT NOT NULL,
+    createdAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_jifen_records_stu_id (stuId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS jifen_rules (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `key` VARCHAR(64) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    jifen INT NOT NULL,
+    cycle INT UNSIGNED NOT NULL DEFAULT 0,
+    maxCount INT UNSIGNED NOT NULL DEFAULT 0,
+    isShow INT UNSIGNED NOT NULL DEFAULT 1,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,7 @@
+-- updatedAt 用作乐观锁的版本号，秒级精度时同一秒内的两次修改版本相同，检测不到冲突
+
+ALTER TABLE announcement MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE zhihus MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE feedbacks MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE jifen_goods MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE jifen_rules MODIFY updatedAt DATETIME(6) NOT NULL;
@@ -0,0 +1,105 @@
+-- yqwork 初始表结构，与引入迁移之前线上的表结构一致，已有的库执行时不会有变化
+-- 表名不带库名前缀，由 migrate 命令连接到对应的库后执行
+-- 之后新增的表和列放在后续的迁移中，不要修改这个文件
+
+CREATE TABLE IF NOT EXISTS departments (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(64) NOT NULL,
+    `desc` VARCHAR(255) NOT NULL DEFAULT '',
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS users (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    username VARCHAR(64) NULL,
+    name VARCHAR(64) NOT NULL,
+    stuId VARCHAR(32) NOT NULL,
+    email VARCHAR(128) NULL,
+    xueyuan INT UNSIGNED NOT NULL DEFAULT 0,
+    gangwei VARCHAR(64) NULL,
+    zaiku TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    qingonggang TINYINT UNSIGNED NOT NULL DEFAULT 0,
+    -- 0 未知，1 实习，2 正式，3 退休
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    lastLogin DATETIME NULL,
+    departmentId INT UNSIGNED NOT NULL,
+    password VARCHAR(255) NOT NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_users_stu_id (stuId),
+    KEY idx_users_department_id (departmentId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS roles (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(64) NOT NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS permissions (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(64) NOT NULL,
+    permission VARCHAR(128) NOT NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_permissions_permission (permission)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS system_user_role (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    userId INT UNSIGNED NOT NULL,
+    roleId INT UNSIGNED NOT NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_user_role_role_id (roleId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS system_role_permission (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    roleId INT UNSIGNED NOT NULL,
+    permissionId INT UNSIGNED NOT NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_role_permission_role_id (roleId),
+    KEY idx_role_permission_permission_id (permissionId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS work_hours (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    name VARCHAR(128) NOT NULL,
+    endTime DATETIME NOT NULL,
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    comment VARCHAR(512) NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS work_hours_records (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    workHourId INT UNSIGNED NOT NULL,
+    userId INT UNSIGNED NOT NULL,
+    -- json 数组
+    workDescs TEXT NOT NULL,
+    includes TEXT NULL,
+    comment VARCHAR(512) NULL,
+    status INT UNSIGNED NOT NULL DEFAULT 0,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    deletedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_work_hours_records_work_hour (workHourId, userId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,8 @@
+-- updatedAt 用作乐观锁的版本号，秒级精度时同一秒内的两次修改版本相同，检测不到冲突
+
+ALTER TABLE users MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE departments MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE roles MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE permissions MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE work_hours MODIFY updatedAt DATETIME(6) NOT NULL;
+ALTER TABLE work_hours_records MODIFY updatedAt DATETIME(6) NOT NULL;
@@ -0,0 +1,104 @@
+-- 会话、操作日志、登录限制、两步验证、密码重置和 API token
+
+CREATE TABLE IF NOT EXISTS user_sessions (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    userId INT UNSIGNED NOT NULL,
+    -- refresh token 的哈希
+    refreshToken VARCHAR(128) NOT NULL,
+    generation INT UNSIGNED NOT NULL DEFAULT 0,
+    ip VARCHAR(64) NOT NULL,
+    userAgent VARCHAR(512) NULL,
+    createdAt DATETIME NOT NULL,
+    lastActiveAt DATETIME NOT NULL,
+    expiresAt DATETIME NOT NULL,
+    revokedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    KEY idx_user_sessions_user_id (userId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS audit_logs (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    -- 系统任务的操作人为 0
+    actorId INT UNSIGNED NOT NULL,
+    actorName VARCHAR(64) NOT NULL,
+    impersonatedUserId INT UNSIGNED NULL,
+    action VARCHAR(64) NOT NULL,
+    entityType VARCHAR(64) NOT NULL,
+    entityId VARCHAR(64) NULL,
+    `before` JSON NULL,
+    `after` JSON NULL,
+    ip VARCHAR(64) NOT NULL,
+    createdAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_audit_logs_actor_id (actorId),
+    KEY idx_audit_logs_entity (entityType, entityId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS login_attempts (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    stuId VARCHAR(32) NOT NULL,
+    userId INT UNSIGNED NULL,
+    method VARCHAR(32) NOT NULL,
+    ip VARCHAR(64) NOT NULL,
+    userAgent VARCHAR(512) NULL,
+    success TINYINT(1) NOT NULL,
+    createdAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    KEY idx_login_attempts_stu_id (stuId),
+    KEY idx_login_attempts_user_id (userId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS login_lockouts (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    scope VARCHAR(16) NOT NULL,
+    value VARCHAR(64) NOT NULL,
+    failures INT UNSIGNED NOT NULL DEFAULT 0,
+    lockedUntil DATETIME NULL,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_login_lockouts (scope, value)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS user_totp (
+    userId INT UNSIGNED NOT NULL,
+    secret VARCHAR(128) NOT NULL,
+    enabled TINYINT(1) NOT NULL DEFAULT 0,
+    recoveryCodes TEXT NOT NULL,
+    lastUsedStep BIGINT UNSIGNED NOT NULL DEFAULT 0,
+    createdAt DATETIME NOT NULL,
+    updatedAt DATETIME NOT NULL,
+    PRIMARY KEY (userId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS password_resets (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    userId INT UNSIGNED NOT NULL,
+    -- 重置 token 的哈希
+    token VARCHAR(128) NOT NULL,
+    ip VARCHAR(64) NOT NULL,
+    createdAt DATETIME NOT NULL,
+    expiresAt DATETIME NOT NULL,
+    usedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_password_resets_token (token),
+    KEY idx_password_resets_user_id (userId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    userId INT UNSIGNED NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    -- token 的哈希
+    token VARCHAR(128) NOT NULL,
+    prefix VARCHAR(16) NOT NULL,
+    scopes TEXT NOT NULL,
+    expiresAt DATETIME NULL,
+    lastUsedAt DATETIME NULL,
+    lastUsedIp VARCHAR(64) NULL,
+    createdAt DATETIME NOT NULL,
+    revokedAt DATETIME NULL,
+    PRIMARY KEY (id),
+    UNIQUE KEY uk_api_tokens_token (token),
+    KEY idx_api_tokens_user_id (userId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
@@ -0,0 +1,5 @@
+-- Argon2id 的哈希比 MD5 长，旧表的 password 列可能放不下
+ALTER TABLE users MODIFY password VARCHAR(255) NOT NULL;
+
+-- 服务账号只能通过 API token 访问
+ALTER TABLE users ADD COLUMN serviceAccount TINYINT UNSIGNED NOT NULL DEFAULT 0 AFTER password;
@@ -0,0 +1,13 @@
+-- 临时角色授予，validFrom 和 validUntil 都为空时是永久授予
+
+ALTER TABLE system_user_role
+    ADD COLUMN validFrom DATETIME NULL AFTER roleId,
+    ADD COLUMN validUntil DATETIME NULL AFTER validFrom;
+
+-- 旧数据中同一用户的同一角色可能有多条，只保留 id 最小的一条，否则无法添加唯一索引
+DELETE a
+FROM system_user_role a
+INNER JOIN system_user_role b
+ON a.userId = b.userId AND a.roleId = b.roleId AND a.id > b.id;
+
+ALTER TABLE system_user_role ADD UNIQUE KEY uk_user_role (userId, roleId);
@@ -0,0 +1,19 @@
+-- 角色继承和只在某个部门内生效的角色
+
+CREATE TABLE IF NOT EXISTS role_parents (
+    roleId INT UNSIGNED NOT NULL,
+    parentId INT UNSIGNED NOT NULL,
+    createdAt DATETIME NOT NULL,
+    PRIMARY KEY (roleId, parentId),
+    KEY idx_role_parents_parent_id (parentId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
+
+CREATE TABLE IF NOT EXISTS system_user_department_role (
+    userId INT UNSIGNED NOT NULL,
+    roleId INT UNSIGNED NOT NULL,
+    departmentId INT UNSIGNED NOT NULL,
+    createdAt DATETIME NOT NULL,
+    PRIMARY KEY (userId, roleId, departmentId),
+    KEY idx_department_role_role_id (roleId),
+    KEY idx_department_role_department_id (departmentId)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4;
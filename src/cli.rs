@@ -0,0 +1,89 @@
+use anyhow::anyhow;
+
+use crate::config::CFG;
+use crate::result::AppResult;
+use crate::service::qnxg::user::{UserBasicInfo, UserStatus};
+use crate::{infra, service, utils};
+
+/// 执行命令行子命令，执行完后直接退出，不启动服务
+pub async fn run(args: &[String]) -> AppResult<()> {
+    match args[0].as_str() {
+        // 新环境初始化：建库、建表，并写入权限目录
+        "migrate" => {
+            infra::mysql::migrate().await?;
+            let report = service::qnxg::permission_catalog::sync_catalog().await?;
+            println!("迁移完成，新增 {} 个权限", report.added.len());
+            Ok(())
+        }
+        // 新环境中还没有任何用户时创建第一个管理员
+        "init-admin" => {
+            let (Some(stu_id), Some(name)) = (args.get(1), args.get(2)) else {
+                return Err(anyhow!("用法: init-admin <学号> <姓名>").into());
+            };
+            let (count, _) =
+                service::qnxg::user::get_user_list(1, 1, None, None, None, None).await?;
+            if count > 0 {
+                return Err(anyhow!("已经存在用户，请使用 grant-admin").into());
+            }
+            let department_id = match service::qnxg::department::get_department_list()
+                .await?
+                .first()
+            {
+                Some(d) => d.id,
+                None => service::qnxg::department::add_department("默认部门", "").await?,
+            };
+            let info = UserBasicInfo {
+                username: None,
+                name: name.clone(),
+                stu_id: stu_id.clone(),
+                email: None,
+                xueyuan: 0,
+                gangwei: None,
+                zaiku: false,
+                qingonggang: false,
+                status: UserStatus::Formal,
+                department_id,
+            };
+            let password = utils::random_token(16);
+            let id = service::qnxg::user::add_user(&info, &password, &[]).await?;
+            service::qnxg::role::grant_admin(id).await?;
+            println!("已创建管理员 {} ({})，初始密码: {}", name, stu_id, password);
+            println!("请登录后立即修改密码");
+            Ok(())
+        }
+        // 把权限目录中缺少的权限写入数据库
+        "sync-permissions" => {
+            let report = service::qnxg::permission_catalog::sync_catalog().await?;
+            for e in &report.added {
+                println!("新增权限 {} ({})", e.permission, e.name);
+            }
+            for p in &report.orphaned {
+                println!("没有被代码检查的权限 {} ({})", p.permission, p.name);
+            }
+            println!(
+                "同步完成，新增 {} 个，{} 个没有被代码检查",
+                report.added.len(),
+                report.orphaned.len()
+            );
+            Ok(())
+        }
+        // 紧急恢复，所有管理员都无法使用时把指定用户设为管理员
+        "grant-admin" => {
+            let Some(stu_id) = args.get(1) else {
+                return Err(anyhow!("用法: grant-admin <学号>").into());
+            };
+            let Some(user) = service::qnxg::user::get_user_by_stu_id(stu_id).await? else {
+                return Err(anyhow!("用户 {} 不存在", stu_id).into());
+            };
+            service::qnxg::role::grant_admin(user.id).await?;
+            println!("已将 {} ({}) 设为管理员", user.info.name, stu_id);
+            if user.service_account || !service::qnxg::user::can_login(user.info.status) {
+                println!("注意：该用户当前不能登录，需要先修改其状态");
+            }
+            // 服务中的权限缓存无法从这里清除
+            println!("正在运行的服务最多在 {} 秒后生效", CFG.permission.cache_ttl);
+            Ok(())
+        }
+        cmd => Err(anyhow!("未知的命令: {}", cmd).into()),
+    }
+}
@@ -5,22 +5,113 @@ pub struct Configs {
     pub jwt: Jwt,
     pub log: Log,
     pub weihuda: Weihuda,
+    #[serde(default)]
+    pub password: Password,
+    #[serde(default)]
+    pub permission: Permission,
+    #[serde(default)]
+    pub recycle_bin: RecycleBin,
+    #[serde(default)]
+    pub login: Login,
+    #[serde(default)]
+    pub totp: Totp,
+    #[serde(default)]
+    pub mail: Mail,
+    #[serde(default)]
+    pub password_reset: PasswordReset,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Server {
     pub address: String,
+    /// 反向代理的地址（IP 或 CIDR），只有来自这些地址的请求才会使用 X-Forwarded-For 和 X-Real-IP
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Database {
+    pub yqwork: DatabaseTarget,
+    pub weihuda: DatabaseTarget,
+}
+
+/// 一个业务库的连接配置，每个库使用单独的连接池，可以在不同的服务器上
+#[derive(serde::Deserialize, Debug)]
+pub struct DatabaseTarget {
+    /// 数据库服务器地址，不需要带库名
+    pub url: String,
+    /// 库名
+    pub database: String,
+    #[serde(default = "default_max_connections")]
     pub max_connections: u32,
-    pub database_url: String,
+    /// 获取连接的超时时间，单位秒
+    #[serde(default = "default_acquire_timeout")]
+    pub acquire_timeout: u64,
+    /// 空闲连接的回收时间，单位秒，为 0 时不回收
+    #[serde(default = "default_idle_timeout")]
+    pub idle_timeout: u64,
+}
+
+fn default_max_connections() -> u32 {
+    100
+}
+
+fn default_acquire_timeout() -> u64 {
+    3
+}
+
+fn default_idle_timeout() -> u64 {
+    60 * 10
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct Jwt {
+    /// 旧的 HS256 密钥，没有配置 keys 时用于签发，也用于校验没有 kid 的 token
+    #[serde(default)]
     pub secret: String,
+    /// 签发 token 使用的密钥 id，为空时使用 secret
+    #[serde(default)]
+    pub active_kid: String,
+    /// 所有可用于校验的密钥，轮换时先加入新密钥并切换 active_kid，旧 token 过期后再移除旧密钥
+    #[serde(default)]
+    pub keys: Vec<JwtKey>,
+    /// access token 有效期，单位秒
+    #[serde(default = "default_access_token_ttl")]
+    pub access_token_ttl: u64,
+    /// refresh token 有效期，单位秒
+    #[serde(default = "default_refresh_token_ttl")]
+    pub refresh_token_ttl: u64,
+    /// 模拟登录 token 有效期，单位秒，到期后需要重新发起
+    #[serde(default = "default_impersonation_ttl")]
+    pub impersonation_ttl: u64,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct JwtKey {
+    pub kid: String,
+    /// 可用的算法："HS256", "RS256", "EdDSA"
+    pub algorithm: jsonwebtoken::Algorithm,
+    /// HS256 的密钥
+    #[serde(default)]
+    pub secret: String,
+    /// RS256 和 EdDSA 的私钥 PEM 文件路径，只有签发 token 的密钥需要
+    #[serde(default)]
+    pub private_key: String,
+    /// RS256 和 EdDSA 的公钥 PEM 文件路径，其他服务只需要公钥就能校验 token
+    #[serde(default)]
+    pub public_key: String,
+}
+
+fn default_access_token_ttl() -> u64 {
+    60 * 15
+}
+
+fn default_refresh_token_ttl() -> u64 {
+    60 * 60 * 24 * 30
+}
+
+fn default_impersonation_ttl() -> u64 {
+    60 * 15
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -38,6 +129,177 @@ pub struct Weihuda {
     pub api_url: String,
 }
 
+#[derive(serde::Deserialize, Debug)]
+pub struct Password {
+    /// Argon2id 内存开销，单位 KiB
+    pub memory_cost: u32,
+    /// Argon2id 迭代次数
+    pub time_cost: u32,
+    /// Argon2id 并行度
+    pub parallelism: u32,
+}
+
+impl Default for Password {
+    fn default() -> Self {
+        Self {
+            memory_cost: argon2::Params::DEFAULT_M_COST,
+            time_cost: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct Permission {
+    /// 用户权限缓存的有效期，单位秒，为 0 时不缓存
+    pub cache_ttl: u64,
+    /// 拥有其中所有权限的用户视为管理员
+    pub admin: Vec<String>,
+    /// 清理过期的临时角色授予的间隔，单位秒
+    pub grant_sweep_interval: u64,
+    /// 启动时把权限目录中缺少的权限写入数据库
+    pub sync_catalog: bool,
+}
+
+impl Default for Permission {
+    fn default() -> Self {
+        Self {
+            cache_ttl: 60,
+            admin: vec!["system".into(), "yq".into(), "hdwsh".into()],
+            grant_sweep_interval: 60,
+            sync_catalog: true,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct RecycleBin {
+    /// 软删除的数据保留的天数，超过后可以彻底删除
+    pub retention_days: u32,
+}
+
+impl Default for RecycleBin {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct Login {
+    /// 同一学号连续失败多少次后锁定，为 0 时不锁定
+    pub max_failures: u32,
+    /// 同一 IP 连续失败多少次后锁定，为 0 时不锁定
+    pub ip_max_failures: u32,
+    /// 第一次锁定的时长，之后每多失败一次翻倍，单位秒
+    pub lockout_base: u64,
+    /// 锁定时长的上限，单位秒
+    pub lockout_max: u64,
+    /// 距离上次失败超过该时长后重新计数，单位秒
+    pub failure_window: u64,
+    /// 允许登录的用户状态：0 未知，1 实习，2 正式，3 退休
+    pub allowed_status: Vec<u32>,
+    /// 认证时用户和会话缓存的有效期，单位秒，为 0 时不缓存
+    /// 多实例部署时，其他实例上撤销会话或禁用用户最多延迟这么久生效
+    pub auth_cache_ttl: u64,
+}
+
+impl Default for Login {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            // 校园网出口 IP 会被很多人共用，需要比学号宽松
+            ip_max_failures: 30,
+            lockout_base: 60,
+            lockout_max: 60 * 60,
+            failure_window: 60 * 15,
+            allowed_status: vec![1, 2],
+            auth_cache_ttl: 10,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct Totp {
+    /// 显示在验证器应用中的发行方名称
+    pub issuer: String,
+    /// 拥有其中任一权限前缀下权限的用户必须启用两步验证，管理员总是需要
+    pub enforce: Vec<String>,
+    /// 密码验证通过后等待输入验证码的有效期，单位秒
+    pub ticket_ttl: u64,
+}
+
+impl Default for Totp {
+    fn default() -> Self {
+        Self {
+            issuer: "易千工作台".into(),
+            enforce: vec!["system".into()],
+            ticket_ttl: 60 * 5,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    Smtp,
+    /// 写入本地文件，用于本地开发
+    File,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct Mail {
+    /// 发送方式："smtp", "file"
+    pub transport: MailTransport,
+    /// 发件人，例如 `易千工作台 <noreply@example.com>`
+    pub from: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: String,
+    pub smtp_password: String,
+    /// transport 为 file 时邮件保存的目录
+    pub file_directory: String,
+}
+
+impl Default for Mail {
+    fn default() -> Self {
+        Self {
+            transport: MailTransport::File,
+            from: "易千工作台 <noreply@localhost>".into(),
+            smtp_host: String::new(),
+            smtp_port: 465,
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            file_directory: "./mails".into(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct PasswordReset {
+    /// 重置链接的有效期，单位秒
+    pub token_ttl: u64,
+    /// 同一用户两次申请之间的最小间隔，单位秒
+    pub cooldown: u64,
+    /// 前端重置密码页面的地址，`{token}` 会被替换为重置凭据
+    pub reset_url: String,
+}
+
+impl Default for PasswordReset {
+    fn default() -> Self {
+        Self {
+            token_ttl: 60 * 30,
+            cooldown: 60,
+            reset_url: "http://localhost:5173/reset-password?token={token}".into(),
+        }
+    }
+}
+
 pub static CFG: once_cell::sync::Lazy<Configs> = once_cell::sync::Lazy::new(|| {
     let s = std::fs::read_to_string("config/config.toml").expect("读取配置文件失败");
     toml::from_str(&s).expect("解析配置文件失败")
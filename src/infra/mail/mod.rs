@@ -0,0 +1,64 @@
+use anyhow::anyhow;
+use lettre::message::{Mailbox, header::ContentType};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::{CFG, MailTransport};
+use crate::result::AppResult;
+
+static SMTP: tokio::sync::OnceCell<AsyncSmtpTransport<Tokio1Executor>> =
+    tokio::sync::OnceCell::const_new();
+
+async fn get_smtp() -> AppResult<&'static AsyncSmtpTransport<Tokio1Executor>> {
+    let smtp = SMTP
+        .get_or_try_init(|| async {
+            let smtp = AsyncSmtpTransport::<Tokio1Executor>::relay(&CFG.mail.smtp_host)
+                .map_err(|e| anyhow!("SMTP 配置错误: {:?}", e))?
+                .port(CFG.mail.smtp_port)
+                .credentials(Credentials::new(
+                    CFG.mail.smtp_username.clone(),
+                    CFG.mail.smtp_password.clone(),
+                ))
+                .build();
+            Ok::<_, anyhow::Error>(smtp)
+        })
+        .await?;
+    Ok(smtp)
+}
+
+/// 发送纯文本邮件
+pub async fn send_mail(to: &str, subject: &str, body: &str) -> AppResult<()> {
+    let from: Mailbox = CFG
+        .mail
+        .from
+        .parse()
+        .map_err(|e| anyhow!("发件人格式错误: {:?}", e))?;
+    let to: Mailbox = to.parse().map_err(|e| anyhow!("收件人格式错误: {:?}", e))?;
+    let message = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.to_string())
+        .map_err(|e| anyhow!("生成邮件失败: {:?}", e))?;
+    match CFG.mail.transport {
+        MailTransport::Smtp => {
+            get_smtp()
+                .await?
+                .send(message)
+                .await
+                .map_err(|e| anyhow!("发送邮件失败: {:?}", e))?;
+        }
+        MailTransport::File => {
+            tokio::fs::create_dir_all(&CFG.mail.file_directory)
+                .await
+                .map_err(|e| anyhow!("创建邮件目录失败: {:?}", e))?;
+            let id = AsyncFileTransport::<Tokio1Executor>::new(&CFG.mail.file_directory)
+                .send(message)
+                .await
+                .map_err(|e| anyhow!("保存邮件失败: {:?}", e))?;
+            tracing::info!("邮件已保存到 {}/{}.eml", CFG.mail.file_directory, id);
+        }
+    }
+    Ok(())
+}
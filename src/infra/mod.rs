@@ -1,2 +1,3 @@
+pub mod mail;
 pub mod mysql;
 pub mod weihuda;
@@ -1,4 +1,4 @@
-use super::get_db_pool;
+use super::{ensure_version_matched, get_weihuda_pool};
 use crate::{result::AppResult, utils};
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -8,6 +8,7 @@ pub struct Announcement {
     pub title: String,
     pub content: String,
     pub url: Option<String>,
+    pub updated_at: chrono::NaiveDateTime,
     pub deleted_at: Option<chrono::NaiveDateTime>,
 }
 
@@ -19,7 +20,7 @@ pub async fn get_announcement_list(
     let res = sqlx::query_as!(
         Announcement,
         r#"
-        SELECT id, title, content, url, deletedAt as deleted_at FROM weihuda_new.announcement
+        SELECT id, title, content, url, updatedAt as updated_at, deletedAt as deleted_at FROM announcement
         ORDER BY 
             CASE WHEN deletedAt IS NULL THEN 0 ELSE 1 END, 
             id DESC
@@ -28,15 +29,15 @@ pub async fn get_announcement_list(
         page_size,
         (page - 1) * page_size,
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_weihuda_pool().await)
     .await?;
 
     let total: i64 = sqlx::query_scalar!(
         r#"
-        SELECT COUNT(*) as count FROM weihuda_new.announcement
+        SELECT COUNT(*) as count FROM announcement
         "#
     )
-    .fetch_one(get_db_pool().await)
+    .fetch_one(get_weihuda_pool().await)
     .await?;
 
     Ok((total as u32, res))
@@ -46,12 +47,12 @@ pub async fn get_announcement(id: u32) -> AppResult<Option<Announcement>> {
     let res = sqlx::query_as!(
         Announcement,
         r#"
-        SELECT id, title, content, url, deletedAt as deleted_at FROM weihuda_new.announcement
+        SELECT id, title, content, url, updatedAt as updated_at, deletedAt as deleted_at FROM announcement
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id,
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_weihuda_pool().await)
     .await?;
     Ok(res)
 }
@@ -60,7 +61,7 @@ pub async fn add_announcement(title: &str, content: &str, url: Option<&str>) ->
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.announcement (title, content, url, createdAt, updatedAt)
+        INSERT INTO announcement (title, content, url, createdAt, updatedAt)
         VALUES (?, ?, ?, ?, ?)
         "#,
         title,
@@ -69,47 +70,50 @@ pub async fn add_announcement(title: &str, content: &str, url: Option<&str>) ->
         now,
         now,
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
 
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
 pub async fn update_announcement(
     id: u32,
     title: &str,
     content: &str,
     url: Option<&str>,
+    version: chrono::NaiveDateTime,
 ) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE weihuda_new.announcement
+        UPDATE announcement
         SET title = ?, content = ?, url = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         title,
         content,
         url,
         now,
         id,
+        version,
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
 pub async fn delete_announcement(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.announcement
+        UPDATE announcement
         SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         id,
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
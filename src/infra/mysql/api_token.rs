@@ -0,0 +1,193 @@
+use super::get_yqwork_pool;
+use crate::{result::AppResult, utils};
+
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub id: u32,
+    pub user_id: u32,
+    pub name: String,
+    // 明文的前几位，方便用户区分不同的 token
+    pub prefix: String,
+    // 授权范围，只能使用其中覆盖的权限
+    pub scopes: Vec<String>,
+    // 为空时永不过期
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+    pub last_used_ip: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    #[serde(skip)]
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl ApiToken {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|t| t > utils::now_time())
+    }
+}
+
+// scopes 在数据库中是 json 数组
+struct ApiTokenRow {
+    id: u32,
+    user_id: u32,
+    name: String,
+    prefix: String,
+    scopes: String,
+    expires_at: Option<chrono::NaiveDateTime>,
+    last_used_at: Option<chrono::NaiveDateTime>,
+    last_used_ip: Option<String>,
+    created_at: chrono::NaiveDateTime,
+    revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<ApiTokenRow> for ApiToken {
+    fn from(r: ApiTokenRow) -> Self {
+        ApiToken {
+            id: r.id,
+            user_id: r.user_id,
+            name: r.name,
+            prefix: r.prefix,
+            scopes: serde_json::from_str(&r.scopes).unwrap_or_default(),
+            expires_at: r.expires_at,
+            last_used_at: r.last_used_at,
+            last_used_ip: r.last_used_ip,
+            created_at: r.created_at,
+            revoked_at: r.revoked_at,
+        }
+    }
+}
+
+/// token 参数为哈希后的值
+pub async fn add_api_token(
+    user_id: u32,
+    name: &str,
+    token: &str,
+    prefix: &str,
+    scopes: &[String],
+    expires_at: Option<chrono::NaiveDateTime>,
+) -> AppResult<u32> {
+    let now = utils::now_time();
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (userId, name, token, prefix, scopes, expiresAt, createdAt)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        user_id,
+        name,
+        token,
+        prefix,
+        serde_json::to_string(scopes).unwrap_or_default(),
+        expires_at,
+        now
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.last_insert_id() as u32)
+}
+
+pub async fn get_api_token(id: u32) -> AppResult<Option<ApiToken>> {
+    let res = sqlx::query_as!(
+        ApiTokenRow,
+        r#"
+        SELECT id, userId AS user_id, name, prefix, scopes, expiresAt AS expires_at, lastUsedAt AS last_used_at,
+            lastUsedIp AS last_used_ip, createdAt AS created_at, revokedAt AS revoked_at
+        FROM api_tokens
+        WHERE id = ?
+        "#,
+        id
+    )
+    .fetch_optional(get_yqwork_pool().await)
+    .await?
+    .map(ApiToken::from);
+    Ok(res)
+}
+
+/// token 参数为哈希后的值
+pub async fn get_api_token_by_token(token: &str) -> AppResult<Option<ApiToken>> {
+    let res = sqlx::query_as!(
+        ApiTokenRow,
+        r#"
+        SELECT id, userId AS user_id, name, prefix, scopes, expiresAt AS expires_at, lastUsedAt AS last_used_at,
+            lastUsedIp AS last_used_ip, createdAt AS created_at, revokedAt AS revoked_at
+        FROM api_tokens
+        WHERE token = ?
+        "#,
+        token
+    )
+    .fetch_optional(get_yqwork_pool().await)
+    .await?
+    .map(ApiToken::from);
+    Ok(res)
+}
+
+/// 获取用户未撤销的 token
+pub async fn get_user_api_tokens(user_id: u32) -> AppResult<Vec<ApiToken>> {
+    let res = sqlx::query_as!(
+        ApiTokenRow,
+        r#"
+        SELECT id, userId AS user_id, name, prefix, scopes, expiresAt AS expires_at, lastUsedAt AS last_used_at,
+            lastUsedIp AS last_used_ip, createdAt AS created_at, revokedAt AS revoked_at
+        FROM api_tokens
+        WHERE userId = ? AND revokedAt IS NULL
+        ORDER BY id DESC
+        "#,
+        user_id
+    )
+    .fetch_all(get_yqwork_pool().await)
+    .await?
+    .into_iter()
+    .map(ApiToken::from)
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
+/// 记录最近一次使用，距离上次记录不到一分钟时跳过，避免每个请求都写数据库
+pub async fn touch_api_token(id: u32, ip: &str) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET lastUsedAt = ?, lastUsedIp = ?
+        WHERE id = ? AND (lastUsedAt IS NULL OR lastUsedAt < ?)
+        "#,
+        now,
+        ip,
+        id,
+        now - chrono::Duration::minutes(1)
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+pub async fn revoke_api_token(id: u32) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revokedAt = ?
+        WHERE id = ? AND revokedAt IS NULL
+        "#,
+        now,
+        id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+pub async fn revoke_user_api_tokens(user_id: u32) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revokedAt = ?
+        WHERE userId = ? AND revokedAt IS NULL
+        "#,
+        now,
+        user_id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use sqlx::Row;
+
+use super::{get_yqwork_pool, limit_offset};
+use crate::{result::AppResult, utils};
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLog {
+    pub id: u32,
+    pub actor_id: u32,
+    // 冗余保存操作人姓名，操作人被删除后仍然可以查看
+    pub actor_name: String,
+    // 模拟登录时被模拟的用户，操作人为发起模拟的管理员
+    pub impersonated_user_id: Option<u32>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Option<String>,
+    // 操作前后的数据快照
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+pub struct AuditLogBasicInfo {
+    pub actor_id: u32,
+    pub actor_name: String,
+    pub impersonated_user_id: Option<u32>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip: String,
+}
+
+#[derive(Debug, Default)]
+pub struct AuditLogFilter {
+    pub actor_id: Option<u32>,
+    pub action: Option<String>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+pub async fn add_audit_log(info: &AuditLogBasicInfo) -> AppResult<u32> {
+    let now = utils::now_time();
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO audit_logs (actorId, actorName, impersonatedUserId, action, entityType, entityId, `before`, `after`, ip, createdAt)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        info.actor_id,
+        &info.actor_name,
+        info.impersonated_user_id,
+        &info.action,
+        &info.entity_type,
+        &info.entity_id,
+        &info.before,
+        &info.after,
+        &info.ip,
+        now
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.last_insert_id() as u32)
+}
+
+fn push_filter(query: &mut sqlx::QueryBuilder<sqlx::MySql>, filter: &AuditLogFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(actor_id) = filter.actor_id {
+        query.push(" AND actorId = ").push_bind(actor_id);
+    }
+    if let Some(action) = &filter.action {
+        query.push(" AND action = ").push_bind(action.clone());
+    }
+    if let Some(entity_type) = &filter.entity_type {
+        query
+            .push(" AND entityType = ")
+            .push_bind(entity_type.clone());
+    }
+    if let Some(entity_id) = &filter.entity_id {
+        query.push(" AND entityId = ").push_bind(entity_id.clone());
+    }
+    if let Some(from) = &filter.from {
+        query.push(" AND createdAt >= ").push_bind(from.clone());
+    }
+    if let Some(to) = &filter.to {
+        query.push(" AND createdAt <= ").push_bind(to.clone());
+    }
+}
+
+pub async fn get_audit_log_list(
+    page: u32,
+    page_size: u32,
+    filter: &AuditLogFilter,
+) -> AppResult<(u32, Vec<AuditLog>)> {
+    let mut main_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
+        r#"
+        SELECT id, actorId, actorName, impersonatedUserId, action, entityType, entityId, `before`, `after`, ip, createdAt
+        FROM audit_logs
+    "#,
+    );
+    let mut count_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
+        r#"
+        SELECT COUNT(*) AS count
+        FROM audit_logs
+    "#,
+    );
+    push_filter(&mut main_query, filter);
+    push_filter(&mut count_query, filter);
+
+    let (limit, offset) = limit_offset(page, page_size);
+    main_query.push(" ORDER BY id DESC");
+    main_query.push(" LIMIT ");
+    main_query.push_bind(limit);
+    main_query.push(" OFFSET ");
+    main_query.push_bind(offset);
+
+    let res = main_query
+        .build()
+        .fetch_all(get_yqwork_pool().await)
+        .await?
+        .into_iter()
+        .map(|r| AuditLog {
+            id: r.get("id"),
+            actor_id: r.get("actorId"),
+            actor_name: r.get("actorName"),
+            impersonated_user_id: r.get("impersonatedUserId"),
+            action: r.get("action"),
+            entity_type: r.get("entityType"),
+            entity_id: r.get("entityId"),
+            before: r.get("before"),
+            after: r.get("after"),
+            ip: r.get("ip"),
+            created_at: r.get("createdAt"),
+        })
+        .collect::<Vec<_>>();
+    let count: i64 = count_query
+        .build_query_scalar()
+        .fetch_one(get_yqwork_pool().await)
+        .await?;
+    Ok((count as u32, res))
+}
@@ -1,28 +1,31 @@
-use super::get_db_pool;
+use super::{Dependents, count, ensure_version_matched, get_yqwork_pool};
 use crate::{result::AppResult, utils};
 
 #[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct Department {
     pub id: u32,
     pub name: String,
     pub desc: String,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 pub async fn get_department_list() -> AppResult<Vec<Department>> {
     let departments = sqlx::query!(
         r#"
-        SELECT id, name, `desc`
-        FROM yqwork_new.departments
+        SELECT id, name, `desc`, updatedAt
+        FROM departments
         WHERE deletedAt IS NULL
         "#,
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_yqwork_pool().await)
     .await?
     .into_iter()
     .map(|r| Department {
         id: r.id,
         name: r.name,
         desc: r.desc,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
 
@@ -33,7 +36,7 @@ pub async fn add_department(name: &str, desc: &str) -> AppResult<u32> {
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO yqwork_new.departments (name, `desc`, createdAt, updatedAt)
+        INSERT INTO departments (name, `desc`, createdAt, updatedAt)
         VALUES (?, ?, ?, ?)
         "#,
         name,
@@ -41,41 +44,99 @@ pub async fn add_department(name: &str, desc: &str) -> AppResult<u32> {
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_yqwork_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
 
-pub async fn update_department(id: u32, name: &str, desc: &str) -> AppResult<()> {
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+pub async fn update_department(
+    id: u32,
+    name: &str,
+    desc: &str,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE yqwork_new.departments
+        UPDATE departments
         SET name = ?, `desc` = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         name,
         desc,
         now,
-        id
+        id,
+        version
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    ensure_version_matched(&res)
+}
+
+/// 锁定部门下的用户后统计，之后的处理需要在同一个事务中进行
+pub async fn get_department_dependents(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+) -> AppResult<Dependents> {
+    Ok(Dependents {
+        users: count(
+            tx,
+            r#"
+            SELECT COUNT(*) FROM users
+            WHERE departmentId = ? AND deletedAt IS NULL
+            FOR UPDATE
+            "#,
+            id,
+        )
+        .await?,
+        department_roles: count(
+            tx,
+            "SELECT COUNT(*) FROM system_user_department_role WHERE departmentId = ?",
+            id,
+        )
+        .await?,
+        roles: 0,
+    })
+}
+
+/// 把部门下的用户转移到另一个部门，已删除的用户也一并转移，以便之后恢复
+pub async fn reassign_department_users(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    from: u32,
+    to: u32,
+) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET departmentId = ?, updatedAt = ?
+        WHERE departmentId = ?
+        "#,
+        to,
+        now,
+        from
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
     Ok(())
 }
 
-pub async fn delete_department(id: u32) -> AppResult<()> {
+pub async fn delete_department(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.departments
+        UPDATE departments
         SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
     Ok(())
 }
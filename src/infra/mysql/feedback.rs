@@ -1,6 +1,6 @@
 use sqlx::Row;
 
-use super::get_db_pool;
+use super::{ensure_version_matched, get_weihuda_pool};
 use crate::{result::AppResult, utils};
 
 #[derive(serde::Serialize, Debug)]
@@ -69,13 +69,13 @@ pub async fn get_feedback_list(
     let mut main_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
         r#"
         SELECT id, contact, createdAt, `desc`, imgUrl, stuId, updatedAt, status
-        FROM weihuda_new.feedbacks
+        FROM feedbacks
     "#,
     );
     let mut count_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
         r#"
         SELECT COUNT(*) AS count
-        FROM weihuda_new.feedbacks
+        FROM feedbacks
     "#,
     );
 
@@ -136,7 +136,7 @@ pub async fn get_feedback_list(
 
     let res = main_query
         .build()
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_weihuda_pool().await)
         .await?
         .into_iter()
         .map(|r| Feedback {
@@ -152,7 +152,7 @@ pub async fn get_feedback_list(
         .collect::<Vec<_>>();
     let count: i64 = count_query
         .build_query_scalar()
-        .fetch_one(get_db_pool().await)
+        .fetch_one(get_weihuda_pool().await)
         .await?;
     Ok((count as u32, res))
 }
@@ -161,12 +161,12 @@ pub async fn get_feedback(id: u32) -> AppResult<Option<Feedback>> {
     let r = sqlx::query!(
         r#"
         SELECT id, contact, createdAt, `desc`, imgUrl, stuId, updatedAt, status
-        FROM weihuda_new.feedbacks
+        FROM feedbacks
         WHERE id = ?
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_weihuda_pool().await)
     .await?
     .map(|r| Feedback {
         id: r.id,
@@ -181,33 +181,39 @@ pub async fn get_feedback(id: u32) -> AppResult<Option<Feedback>> {
     Ok(r)
 }
 
-pub async fn update_feedback(id: u32, status: FeedbackStatus) -> AppResult<()> {
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+pub async fn update_feedback(
+    id: u32,
+    status: FeedbackStatus,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE weihuda_new.feedbacks
+        UPDATE feedbacks
         SET status = ?, updatedAt = ?
-        WHERE id = ?
+        WHERE id = ? AND updatedAt = ?
         "#,
         u32::from(status),
         now,
-        id
+        id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
-// weihuda_new.feedbacks 并没有设计伪删除
+// feedbacks 并没有设计伪删除
 pub async fn delete_feedback(id: u32) -> AppResult<()> {
     sqlx::query!(
         r#"
-        DELETE FROM weihuda_new.feedbacks
+        DELETE FROM feedbacks
         WHERE id = ?
         "#,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
@@ -259,13 +265,13 @@ pub async fn get_feedback_msg_list(id: u32) -> AppResult<Vec<FeedbackMsg>> {
     let res = sqlx::query!(
         r#"
         SELECT id, typ, msg, stuId, feedbackId, createdAt
-        FROM weihuda_new.feedback_msg
+        FROM feedback_msg
         WHERE feedbackId = ? AND deletedAt IS NULL
         ORDER BY id DESC
         "#,
         id
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_weihuda_pool().await)
     .await?
     .into_iter()
     .map(|r| FeedbackMsg {
@@ -289,7 +295,7 @@ pub async fn add_feedback_msg(
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.feedback_msg (typ, msg, stuId, feedbackId, createdAt)
+        INSERT INTO feedback_msg (typ, msg, stuId, feedbackId, createdAt)
         VALUES (?, ?, ?, ?, ?)
         "#,
         String::from(typ),
@@ -298,7 +304,7 @@ pub async fn add_feedback_msg(
         feedback_id,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
@@ -307,14 +313,14 @@ pub async fn delete_feedback_msg(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.feedback_msg
+        UPDATE feedback_msg
         SET deletedAt = ?
         WHERE id = ?
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
@@ -1,6 +1,6 @@
 use sqlx::Row;
 
-use super::get_db_pool;
+use super::{ensure_version_matched, get_weihuda_pool};
 use crate::{result::AppResult, utils};
 
 #[derive(serde::Serialize, Debug)]
@@ -62,14 +62,14 @@ pub async fn get_goods_record_list(
     let mut main_query = sqlx::QueryBuilder::new(
         r#"
         SELECT id, stuId, goodsId, status, receiveTime, createdAt
-        FROM weihuda_new.jifen_exchange
+        FROM jifen_exchange
         WHERE deletedAt IS NULL
         "#,
     );
     let mut count_query = sqlx::QueryBuilder::new(
         r#"
         SELECT COUNT(*) AS count
-        FROM weihuda_new.jifen_exchange
+        FROM jifen_exchange
         WHERE deletedAt IS NULL
         "#,
     );
@@ -105,7 +105,7 @@ pub async fn get_goods_record_list(
 
     let res = main_query
         .build()
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_weihuda_pool().await)
         .await?
         .into_iter()
         .map(|r| GoodsRecord {
@@ -120,7 +120,7 @@ pub async fn get_goods_record_list(
 
     let count: i64 = count_query
         .build_query_scalar()
-        .fetch_one(get_db_pool().await)
+        .fetch_one(get_weihuda_pool().await)
         .await?;
 
     Ok((count as u32, res))
@@ -130,12 +130,12 @@ pub async fn get_goods_record(id: u32) -> AppResult<Option<GoodsRecord>> {
     let res = sqlx::query!(
         r#"
         SELECT id, stuId, goodsId, status, receiveTime, createdAt
-        FROM weihuda_new.jifen_exchange
+        FROM jifen_exchange
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_weihuda_pool().await)
     .await?
     .map(|r| GoodsRecord {
         id: r.id,
@@ -156,7 +156,7 @@ pub async fn update_goods_record(
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.jifen_exchange
+        UPDATE jifen_exchange
         SET status = ?, receiveTime = ?, updatedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
@@ -165,7 +165,7 @@ pub async fn update_goods_record(
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
@@ -174,19 +174,20 @@ pub async fn delete_goods_record(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.jifen_exchange
+        UPDATE jifen_exchange
         SET deletedAt = ?
         WHERE id = ?
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
 
 #[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct JifenGoods {
     pub id: u32,
     pub name: String,
@@ -195,18 +196,19 @@ pub struct JifenGoods {
     pub price: i32,
     pub description: Option<String>,
     pub enabled: bool,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 pub async fn get_goods_list() -> AppResult<Vec<JifenGoods>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, name, cover, count, price, description, enabled
-        FROM weihuda_new.jifen_goods
+        SELECT id, name, cover, count, price, description, enabled, updatedAt
+        FROM jifen_goods
         WHERE deletedAt IS NULL
         ORDER BY id DESC
         "#,
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_weihuda_pool().await)
     .await?
     .into_iter()
     .map(|r| JifenGoods {
@@ -217,6 +219,7 @@ pub async fn get_goods_list() -> AppResult<Vec<JifenGoods>> {
         price: r.price,
         description: r.description,
         enabled: r.enabled != 0,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
     Ok(res)
@@ -233,7 +236,7 @@ pub async fn add_goods(
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.jifen_goods (name, cover, count, price, description, enabled, createdAt, updatedAt)
+        INSERT INTO jifen_goods (name, cover, count, price, description, enabled, createdAt, updatedAt)
         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         name,
@@ -245,11 +248,13 @@ pub async fn add_goods(
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
 
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+#[allow(clippy::too_many_arguments)]
 pub async fn update_goods(
     id: u32,
     name: &str,
@@ -258,13 +263,14 @@ pub async fn update_goods(
     price: i32,
     description: Option<&str>,
     enabled: bool,
+    version: chrono::NaiveDateTime,
 ) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE weihuda_new.jifen_goods
+        UPDATE jifen_goods
         SET name = ?, cover = ?, count = ?, price = ?, description = ?, enabled = ?, updatedAt = ?
-        WHERE id = ?
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         name,
         cover,
@@ -273,25 +279,26 @@ pub async fn update_goods(
         description,
         enabled as u32,
         now,
-        id
+        id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
 pub async fn delete_goods(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.jifen_goods
+        UPDATE jifen_goods
         SET deletedAt = ?
         WHERE id = ?
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
@@ -318,13 +325,13 @@ pub async fn get_record_list(
     let mut main_query = sqlx::QueryBuilder::new(
         r#"
         SELECT id, `key`, `param`, stuId, `desc`, jifen, createdAt
-        FROM weihuda_new.jifen_records
+        FROM jifen_records
         "#,
     );
     let mut count_query = sqlx::QueryBuilder::new(
         r#"
         SELECT COUNT(*) AS count
-        FROM weihuda_new.jifen_records
+        FROM jifen_records
         "#,
     );
 
@@ -388,7 +395,7 @@ pub async fn get_record_list(
 
     let res = main_query
         .build()
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_weihuda_pool().await)
         .await?
         .into_iter()
         .map(|r| JifenRecord {
@@ -404,7 +411,7 @@ pub async fn get_record_list(
 
     let count: i64 = count_query
         .build_query_scalar()
-        .fetch_one(get_db_pool().await)
+        .fetch_one(get_weihuda_pool().await)
         .await?;
 
     Ok((count as u32, res))
@@ -414,12 +421,12 @@ pub async fn get_record(id: u32) -> AppResult<Option<JifenRecord>> {
     let res = sqlx::query!(
         r#"
         SELECT id, `key`, `param`, stuId, `desc`, jifen, createdAt
-        FROM weihuda_new.jifen_records
+        FROM jifen_records
         WHERE id = ?
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_weihuda_pool().await)
     .await?
     .map(|r| JifenRecord {
         id: r.id,
@@ -444,7 +451,7 @@ pub async fn add_record(
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.jifen_records (`key`, `param`, stuId, `desc`, jifen, createdAt)
+        INSERT INTO jifen_records (`key`, `param`, stuId, `desc`, jifen, createdAt)
         VALUES (?, ?, ?, ?, ?, ?)
         "#,
         key,
@@ -467,7 +474,7 @@ pub async fn update_jifen(
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.mini_bind
+        UPDATE mini_bind
         SET jifen = jifen + ?, updatedAt = ?
         WHERE stuId = ?
         "#,
@@ -490,18 +497,19 @@ pub struct JifenRule {
     pub cycle: u32,
     pub max_count: u32,
     pub is_show: bool,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 pub async fn get_rule_list() -> AppResult<Vec<JifenRule>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, `key`, name, jifen, cycle, maxCount, isShow
-        FROM weihuda_new.jifen_rules
+        SELECT id, `key`, name, jifen, cycle, maxCount, isShow, updatedAt
+        FROM jifen_rules
         WHERE deletedAt IS NULL
         ORDER BY id DESC
         "#,
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_weihuda_pool().await)
     .await?
     .into_iter()
     .map(|r| JifenRule {
@@ -512,6 +520,7 @@ pub async fn get_rule_list() -> AppResult<Vec<JifenRule>> {
         cycle: r.cycle,
         max_count: r.maxCount,
         is_show: r.isShow != 0,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
     Ok(res)
@@ -528,7 +537,7 @@ pub async fn add_rule(
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.jifen_rules (`key`, name, jifen, cycle, maxCount, isShow, createdAt, updatedAt)
+        INSERT INTO jifen_rules (`key`, name, jifen, cycle, maxCount, isShow, createdAt, updatedAt)
         VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         key,
@@ -540,11 +549,13 @@ pub async fn add_rule(
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
 
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+#[allow(clippy::too_many_arguments)]
 pub async fn update_rule(
     id: u32,
     key: &str,
@@ -553,13 +564,14 @@ pub async fn update_rule(
     cycle: u32,
     max_count: u32,
     is_show: bool,
+    version: chrono::NaiveDateTime,
 ) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE weihuda_new.jifen_rules
+        UPDATE jifen_rules
         SET `key` = ?, name = ?, jifen = ?, cycle = ?, maxCount = ?, isShow = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         key,
         name,
@@ -568,25 +580,26 @@ pub async fn update_rule(
         max_count,
         is_show as u32,
         now,
-        id
+        id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
 pub async fn delete_rule(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.jifen_rules
+        UPDATE jifen_rules
         SET deletedAt = ?
         WHERE id = ?
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
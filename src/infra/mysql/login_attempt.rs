@@ -0,0 +1,314 @@
+use sqlx::Row;
+
+use super::{get_yqwork_pool, limit_offset};
+use crate::{result::AppResult, utils};
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginAttempt {
+    pub id: u32,
+    // 登录时填写的学号，不一定对应存在的用户
+    pub stu_id: String,
+    // 学号对应的用户，用户不存在时为空
+    pub user_id: Option<u32>,
+    pub method: String,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub success: bool,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct LoginAttemptFilter {
+    pub user_id: Option<u32>,
+    pub stu_id: Option<String>,
+    pub method: Option<String>,
+    pub ip: Option<String>,
+    pub success: Option<bool>,
+}
+
+/// 登录方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginMethod {
+    Password,
+    // 密码验证通过后的两步验证
+    Totp,
+    // 微生活扫码登录
+    QrCode,
+}
+impl From<LoginMethod> for &str {
+    fn from(value: LoginMethod) -> Self {
+        match value {
+            LoginMethod::Password => "password",
+            LoginMethod::Totp => "totp",
+            LoginMethod::QrCode => "qrcode",
+        }
+    }
+}
+
+/// 登录失败计数的维度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutScope {
+    StuId,
+    Ip,
+}
+impl From<LockoutScope> for &str {
+    fn from(value: LockoutScope) -> Self {
+        match value {
+            LockoutScope::StuId => "stuId",
+            LockoutScope::Ip => "ip",
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginLockout {
+    pub id: u32,
+    pub scope: String,
+    pub value: String,
+    // 当前统计周期内连续失败的次数
+    pub failures: u32,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+pub async fn add_login_attempt(
+    stu_id: &str,
+    user_id: Option<u32>,
+    method: LoginMethod,
+    ip: &str,
+    user_agent: Option<&str>,
+    success: bool,
+) -> AppResult<u32> {
+    let now = utils::now_time();
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO login_attempts (stuId, userId, method, ip, userAgent, success, createdAt)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        stu_id,
+        user_id,
+        <&str>::from(method),
+        ip,
+        user_agent,
+        success,
+        now
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.last_insert_id() as u32)
+}
+
+fn push_filter(query: &mut sqlx::QueryBuilder<sqlx::MySql>, filter: &LoginAttemptFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(user_id) = filter.user_id {
+        query.push(" AND userId = ").push_bind(user_id);
+    }
+    if let Some(stu_id) = &filter.stu_id {
+        query.push(" AND stuId = ").push_bind(stu_id.clone());
+    }
+    if let Some(method) = &filter.method {
+        query.push(" AND method = ").push_bind(method.clone());
+    }
+    if let Some(ip) = &filter.ip {
+        query.push(" AND ip = ").push_bind(ip.clone());
+    }
+    if let Some(success) = filter.success {
+        query.push(" AND success = ").push_bind(success);
+    }
+}
+
+pub async fn get_login_attempt_list(
+    page: u32,
+    page_size: u32,
+    filter: &LoginAttemptFilter,
+) -> AppResult<(u32, Vec<LoginAttempt>)> {
+    let mut main_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
+        r#"
+        SELECT id, stuId, userId, method, ip, userAgent, success, createdAt
+        FROM login_attempts
+    "#,
+    );
+    let mut count_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
+        r#"
+        SELECT COUNT(*) AS count
+        FROM login_attempts
+    "#,
+    );
+    push_filter(&mut main_query, filter);
+    push_filter(&mut count_query, filter);
+
+    let (limit, offset) = limit_offset(page, page_size);
+    main_query.push(" ORDER BY id DESC");
+    main_query.push(" LIMIT ");
+    main_query.push_bind(limit);
+    main_query.push(" OFFSET ");
+    main_query.push_bind(offset);
+
+    let res = main_query
+        .build()
+        .fetch_all(get_yqwork_pool().await)
+        .await?
+        .into_iter()
+        .map(|r| LoginAttempt {
+            id: r.get("id"),
+            stu_id: r.get("stuId"),
+            user_id: r.get("userId"),
+            method: r.get("method"),
+            ip: r.get("ip"),
+            user_agent: r.get("userAgent"),
+            success: r.get("success"),
+            created_at: r.get("createdAt"),
+        })
+        .collect::<Vec<_>>();
+    let count: i64 = count_query
+        .build_query_scalar()
+        .fetch_one(get_yqwork_pool().await)
+        .await?;
+    Ok((count as u32, res))
+}
+
+/// 获取仍在锁定中的截止时间
+pub async fn get_locked_until(
+    scope: LockoutScope,
+    value: &str,
+) -> AppResult<Option<chrono::NaiveDateTime>> {
+    let now = utils::now_time();
+    let res = sqlx::query_scalar!(
+        r#"
+        SELECT lockedUntil
+        FROM login_lockouts
+        WHERE scope = ? AND value = ? AND lockedUntil > ?
+        "#,
+        <&str>::from(scope),
+        value,
+        now
+    )
+    .fetch_optional(get_yqwork_pool().await)
+    .await?
+    .flatten();
+    Ok(res)
+}
+
+/// 记录一次失败，返回当前统计周期内的失败次数
+/// 上次失败早于 window_start 时重新计数
+pub async fn add_failure(
+    scope: LockoutScope,
+    value: &str,
+    window_start: chrono::NaiveDateTime,
+) -> AppResult<u32> {
+    let now = utils::now_time();
+    let mut tx = get_yqwork_pool().await.begin().await?;
+    // failures 需要在 updatedAt 之前赋值，才能读到上次失败的时间
+    sqlx::query!(
+        r#"
+        INSERT INTO login_lockouts (scope, value, failures, lockedUntil, createdAt, updatedAt)
+        VALUES (?, ?, 1, NULL, ?, ?)
+        ON DUPLICATE KEY UPDATE
+            failures = IF(updatedAt < ?, 1, failures + 1),
+            updatedAt = VALUES(updatedAt)
+        "#,
+        <&str>::from(scope),
+        value,
+        now,
+        now,
+        window_start
+    )
+    .execute(&mut *tx)
+    .await?;
+    let failures: u32 = sqlx::query_scalar!(
+        r#"
+        SELECT failures
+        FROM login_lockouts
+        WHERE scope = ? AND value = ?
+        "#,
+        <&str>::from(scope),
+        value
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(failures)
+}
+
+pub async fn set_locked_until(
+    scope: LockoutScope,
+    value: &str,
+    locked_until: chrono::NaiveDateTime,
+) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        UPDATE login_lockouts
+        SET lockedUntil = ?
+        WHERE scope = ? AND value = ?
+        "#,
+        locked_until,
+        <&str>::from(scope),
+        value
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+/// 清除失败计数和锁定
+pub async fn clear_failures(scope: LockoutScope, value: &str) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM login_lockouts
+        WHERE scope = ? AND value = ?
+        "#,
+        <&str>::from(scope),
+        value
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+/// 获取仍在锁定中的记录
+pub async fn get_lockout_list() -> AppResult<Vec<LoginLockout>> {
+    let now = utils::now_time();
+    let res = sqlx::query_as!(
+        LoginLockout,
+        r#"
+        SELECT id, scope, value, failures, lockedUntil AS locked_until, updatedAt AS updated_at
+        FROM login_lockouts
+        WHERE lockedUntil > ?
+        ORDER BY lockedUntil DESC
+        "#,
+        now
+    )
+    .fetch_all(get_yqwork_pool().await)
+    .await?;
+    Ok(res)
+}
+
+pub async fn get_lockout(id: u32) -> AppResult<Option<LoginLockout>> {
+    let res = sqlx::query_as!(
+        LoginLockout,
+        r#"
+        SELECT id, scope, value, failures, lockedUntil AS locked_until, updatedAt AS updated_at
+        FROM login_lockouts
+        WHERE id = ?
+        "#,
+        id
+    )
+    .fetch_optional(get_yqwork_pool().await)
+    .await?;
+    Ok(res)
+}
+
+pub async fn delete_lockout(id: u32) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM login_lockouts
+        WHERE id = ?
+        "#,
+        id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use super::get_db_pool;
+use super::get_weihuda_pool;
 use crate::result::AppResult;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
@@ -11,10 +11,10 @@ pub async fn get_mini_config() -> AppResult<Vec<MiniConfig>> {
     let res = sqlx::query_as!(
         MiniConfig,
         r#"
-        SELECT `key`, `value` FROM weihuda_new.mini_configs
+        SELECT `key`, `value` FROM mini_configs
         "#
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_weihuda_pool().await)
     .await?;
     Ok(res)
 }
@@ -22,12 +22,12 @@ pub async fn get_mini_config() -> AppResult<Vec<MiniConfig>> {
 pub async fn update_mini_config(key: &str, value: &str) -> AppResult<()> {
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.mini_configs SET `value` = ? WHERE `key` = ? 
+        UPDATE mini_configs SET `value` = ? WHERE `key` = ? 
         "#,
         value,
         key,
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
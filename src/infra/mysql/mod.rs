@@ -1,29 +1,153 @@
 pub mod announcement;
+pub mod api_token;
+pub mod audit_log;
 pub mod department;
 pub mod feedback;
 pub mod jifen;
+pub mod login_attempt;
 pub mod mini_config;
 pub mod notice;
+pub mod password_reset;
 pub mod permission;
+pub mod recycle_bin;
 pub mod role;
+pub mod session;
+pub mod totp;
 pub mod user;
 pub mod work_hour;
 pub mod zhihu;
 
+use std::str::FromStr;
 use std::time::Duration;
 
-use crate::config::CFG;
-
-static DB_POOL: tokio::sync::OnceCell<sqlx::MySqlPool> = tokio::sync::OnceCell::const_new();
-pub async fn get_db_pool() -> &'static sqlx::MySqlPool {
-    DB_POOL
-        .get_or_init(|| async {
-            sqlx::mysql::MySqlPoolOptions::new()
-                .max_connections(CFG.database.max_connections)
-                .acquire_timeout(Duration::from_secs(3))
-                .connect(&CFG.database.database_url)
-                .await
-                .expect("连接数据库失败")
-        })
+use anyhow::anyhow;
+use sqlx::ConnectOptions;
+use sqlx::mysql::MySqlConnectOptions;
+
+use crate::config::{CFG, DatabaseTarget};
+use crate::result::{AppError, AppResult};
+
+fn connect_options(target: &DatabaseTarget) -> Result<MySqlConnectOptions, sqlx::Error> {
+    Ok(MySqlConnectOptions::from_str(&target.url)?.database(&target.database))
+}
+
+async fn connect(target: &DatabaseTarget) -> sqlx::MySqlPool {
+    let options = connect_options(target).expect("数据库地址格式错误");
+    sqlx::mysql::MySqlPoolOptions::new()
+        .max_connections(target.max_connections)
+        .acquire_timeout(Duration::from_secs(target.acquire_timeout))
+        .idle_timeout((target.idle_timeout > 0).then_some(Duration::from_secs(target.idle_timeout)))
+        .connect_with(options)
+        .await
+        .unwrap_or_else(|e| panic!("连接数据库 {} 失败: {}", target.database, e))
+}
+
+static YQWORK_POOL: tokio::sync::OnceCell<sqlx::MySqlPool> = tokio::sync::OnceCell::const_new();
+static WEIHUDA_POOL: tokio::sync::OnceCell<sqlx::MySqlPool> = tokio::sync::OnceCell::const_new();
+
+/// yqwork 库：用户、权限、工时等后台数据
+pub async fn get_yqwork_pool() -> &'static sqlx::MySqlPool {
+    YQWORK_POOL
+        .get_or_init(|| connect(&CFG.database.yqwork))
+        .await
+}
+
+/// weihuda 库：小程序的数据
+pub async fn get_weihuda_pool() -> &'static sqlx::MySqlPool {
+    WEIHUDA_POOL
+        .get_or_init(|| connect(&CFG.database.weihuda))
         .await
 }
+
+/// 创建数据库并执行 migrations 目录中的迁移，每个库单独记录迁移版本
+/// 两个库的迁移版本号不重叠（weihuda 从 1001 开始），所以也可以执行到同一个库中，用于编译时校验 SQL
+pub async fn migrate() -> AppResult<()> {
+    let migrations = [
+        (&CFG.database.yqwork, sqlx::migrate!("./migrations/yqwork")),
+        (
+            &CFG.database.weihuda,
+            sqlx::migrate!("./migrations/weihuda"),
+        ),
+    ];
+    for (target, mut migrator) in migrations {
+        // 同一个库中会有另一组的迁移记录
+        migrator.set_ignore_missing(true);
+        let mut conn = MySqlConnectOptions::from_str(&target.url)?
+            .connect()
+            .await?;
+        sqlx::query(&format!(
+            "CREATE DATABASE IF NOT EXISTS `{}` DEFAULT CHARACTER SET utf8mb4",
+            target.database
+        ))
+        .execute(&mut conn)
+        .await?;
+        let mut conn = connect_options(target)?.connect().await?;
+        migrator
+            .run(&mut conn)
+            .await
+            .map_err(|e| anyhow!("{} 执行迁移失败: {}", target.database, e))?;
+        tracing::info!("{} 迁移完成", target.database);
+    }
+    Ok(())
+}
+
+/// 引用某条数据的其他数据的数量，删除前用于决定如何处理
+#[derive(serde::Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependents {
+    // 未删除的用户
+    pub users: u32,
+    // 部门角色授予
+    pub department_roles: u32,
+    // 继承该角色或者包含该权限的角色
+    pub roles: u32,
+}
+
+impl Dependents {
+    pub fn is_empty(&self) -> bool {
+        self.users == 0 && self.department_roles == 0 && self.roles == 0
+    }
+}
+
+async fn count(tx: &mut sqlx::Transaction<'_, sqlx::MySql>, sql: &str, id: u32) -> AppResult<u32> {
+    let count: i64 = sqlx::query_scalar(sql)
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await?;
+    Ok(count as u32)
+}
+
+/// 每页最多返回的条数
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// 分页参数转换为 LIMIT 和 OFFSET，page 从 1 开始，为 0 时视为第一页
+fn limit_offset(page: u32, page_size: u32) -> (u32, u32) {
+    let limit = page_size.min(MAX_PAGE_SIZE);
+    (limit, page.saturating_sub(1).saturating_mul(limit))
+}
+
+/// 检查带版本条件的更新是否命中
+/// 没有更新到数据说明数据在读取之后已经被修改或删除
+fn ensure_version_matched(res: &sqlx::mysql::MySqlQueryResult) -> AppResult<()> {
+    if res.rows_affected() == 0 {
+        return Err(AppError::Conflict);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_offset() {
+        assert_eq!(limit_offset(0, 10), (10, 0));
+        assert_eq!(limit_offset(1, 10), (10, 0));
+        assert_eq!(limit_offset(3, 10), (10, 20));
+        assert_eq!(limit_offset(2, u32::MAX), (MAX_PAGE_SIZE, MAX_PAGE_SIZE));
+        assert_eq!(
+            limit_offset(u32::MAX, MAX_PAGE_SIZE),
+            (MAX_PAGE_SIZE, u32::MAX)
+        );
+    }
+}
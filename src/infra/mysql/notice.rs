@@ -2,7 +2,7 @@ use sqlx::Row;
 
 use crate::{result::AppResult, utils};
 
-use super::get_db_pool;
+use super::get_weihuda_pool;
 
 #[derive(serde::Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -61,14 +61,14 @@ pub async fn get_notice_list(
     let mut main_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
         r#"
         SELECT id, content, createdAt, url, stuId, status, isShow
-        FROM weihuda_new.notices
+        FROM notices
         WHERE deletedAt IS NULL
     "#,
     );
     let mut count_query: sqlx::QueryBuilder<sqlx::MySql> = sqlx::QueryBuilder::new(
         r#"
         SELECT COUNT(*) AS count
-        FROM weihuda_new.notices
+        FROM notices
         WHERE deletedAt IS NULL
     "#,
     );
@@ -106,7 +106,7 @@ pub async fn get_notice_list(
 
     let res = main_query
         .build()
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_weihuda_pool().await)
         .await?
         .into_iter()
         .map(|r| Notice {
@@ -121,7 +121,7 @@ pub async fn get_notice_list(
         .collect::<Vec<_>>();
     let count: i64 = count_query
         .build_query_scalar()
-        .fetch_one(get_db_pool().await)
+        .fetch_one(get_weihuda_pool().await)
         .await?;
     Ok((count as u32, res))
 }
@@ -130,12 +130,12 @@ pub async fn get_notice(id: u32) -> AppResult<Option<Notice>> {
     let res = sqlx::query!(
         r#"
         SELECT id, content, createdAt, url, stuId, status, isShow
-        FROM weihuda_new.notices
+        FROM notices
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_weihuda_pool().await)
     .await?
     .map(|r| Notice {
         id: r.id,
@@ -158,7 +158,7 @@ pub async fn add_notice(
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.notices
+        INSERT INTO notices
         (stuId, content, isShow, status, url, createdAt, updatedAt)
         VALUES
         (?, ?, ?, ?, ?, ?, ?)
@@ -171,7 +171,7 @@ pub async fn add_notice(
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
@@ -180,13 +180,13 @@ pub async fn delete_notice(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.notices SET deletedAt = ?
+        UPDATE notices SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
     Ok(())
 }
@@ -0,0 +1,94 @@
+use super::get_yqwork_pool;
+use crate::{result::AppResult, utils};
+
+/// token 参数为哈希后的值
+pub async fn add_password_reset(
+    user_id: u32,
+    token: &str,
+    ip: &str,
+    expires_at: chrono::NaiveDateTime,
+) -> AppResult<u32> {
+    let now = utils::now_time();
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO password_resets (userId, token, ip, createdAt, expiresAt)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+        user_id,
+        token,
+        ip,
+        now,
+        expires_at
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.last_insert_id() as u32)
+}
+
+/// 获取用户最近一次申请重置的时间
+pub async fn get_last_request_time(user_id: u32) -> AppResult<Option<chrono::NaiveDateTime>> {
+    let res = sqlx::query_scalar!(
+        r#"
+        SELECT MAX(createdAt) AS last_request_time
+        FROM password_resets
+        WHERE userId = ?
+        "#,
+        user_id
+    )
+    .fetch_one(get_yqwork_pool().await)
+    .await?;
+    Ok(res)
+}
+
+/// 使该用户所有未使用的重置凭据失效
+pub async fn invalidate_user_password_resets(user_id: u32) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE password_resets
+        SET usedAt = ?
+        WHERE userId = ? AND usedAt IS NULL
+        "#,
+        now,
+        user_id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+/// 使用重置凭据，返回对应的用户 id
+/// 凭据不存在、已过期或已使用时返回 None，token 参数为哈希后的值
+pub async fn use_password_reset(token: &str) -> AppResult<Option<u32>> {
+    let now = utils::now_time();
+    let mut tx = get_yqwork_pool().await.begin().await?;
+    let reset = sqlx::query!(
+        r#"
+        SELECT id, userId
+        FROM password_resets
+        WHERE token = ? AND usedAt IS NULL AND expiresAt > ?
+        FOR UPDATE
+        "#,
+        token,
+        now
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|r| (r.id, r.userId));
+    let Some((id, user_id)) = reset else {
+        return Ok(None);
+    };
+    sqlx::query!(
+        r#"
+        UPDATE password_resets
+        SET usedAt = ?
+        WHERE id = ?
+        "#,
+        now,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some(user_id))
+}
@@ -1,84 +1,175 @@
-use super::get_db_pool;
+use super::{Dependents, count, ensure_version_matched, get_yqwork_pool};
+use crate::config::CFG;
 use crate::{result::AppResult, utils};
 
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct PermissionItem {
     pub id: u32,
     // 权限名称
     pub name: String,
     // 权限标识
     pub permission: String,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
+#[derive(Debug, Clone)]
 pub struct Permission {
     items: Vec<PermissionItem>,
+    // 使用 API token 访问时 token 的授权范围，只能使用其中覆盖的权限
+    scopes: Option<Vec<String>>,
+}
+
+/// 判断权限规则是否覆盖某个权限，按 `:` 分段逐段比较
+/// 规则是权限的前缀时即覆盖，例如 a:b 覆盖 a:b 和 a:b:c，但不覆盖 a:bc
+/// 规则中的 `*` 可以匹配任意一段，单独的 `*` 覆盖所有权限
+pub fn rule_matches(rule: &str, permission: &str) -> bool {
+    let mut permission = permission.split(':');
+    rule.split(':')
+        .all(|seg| permission.next().is_some_and(|p| seg == "*" || seg == p))
 }
 
 impl Permission {
     // 判断是否拥有某个权限
+    // 以 `!` 开头的是拒绝规则，优先于授予规则，例如同时拥有 hdwsh:* 和 !hdwsh:feedback:delete 时不能删除问题反馈
     pub fn has(&self, permission: &str) -> bool {
-        // 判断 * 权限是否拥有时，永远为 true
-        // 如果某个人拥有了 * 权限，则拥有所有权限
-        // 其他情况下，我们需要逐级判断权限。例如对于 a:b:c 权限，需要判断是否拥有 a、a:b、a:b:c 其中之一
-        permission == "*"
-            || self.items.iter().any(|item| {
-                item.permission == "*" || permission.starts_with(item.permission.as_str())
+        let rules = self.items.iter().map(|item| item.permission.as_str());
+        let denied = rules
+            .clone()
+            .filter_map(|rule| rule.strip_prefix('!'))
+            .any(|rule| rule_matches(rule, permission));
+        let granted = rules
+            .filter(|rule| !rule.starts_with('!'))
+            .any(|rule| rule_matches(rule, permission));
+        !denied && granted && self.in_scope(permission)
+    }
+    fn in_scope(&self, permission: &str) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.iter().any(|s| rule_matches(s, permission)))
+    }
+    // 列出覆盖某个权限的授予规则和拒绝规则，用于排查权限问题
+    pub fn matched_rules(&self, permission: &str) -> (Vec<&PermissionItem>, Vec<&PermissionItem>) {
+        self.items
+            .iter()
+            .filter(|item| match item.permission.strip_prefix('!') {
+                Some(rule) => rule_matches(rule, permission),
+                None => rule_matches(&item.permission, permission),
             })
+            .partition(|item| !item.permission.starts_with('!'))
     }
+    // 判断是否拥有某个权限前缀下的任一权限，例如拥有 system:role:query 或 * 时都算拥有 system 下的权限
+    // 授予规则在该前缀下的部分被拒绝规则整体覆盖时不算，例如 * 和 !system 不算拥有 system 下的权限
+    // 拒绝规则只覆盖其中一部分时仍然算拥有，带 `*` 的规则按可能拥有处理
+    pub fn has_any_under(&self, prefix: &str) -> bool {
+        let rules = self.items.iter().map(|item| item.permission.as_str());
+        let denied = |permission: &str| {
+            rules
+                .clone()
+                .filter_map(|rule| rule.strip_prefix('!'))
+                .any(|rule| rule_matches(rule, permission))
+        };
+        rules
+            .clone()
+            .filter(|rule| !rule.starts_with('!'))
+            // 授予规则和前缀中更具体的一个，即该规则在前缀下授予的范围
+            .filter_map(|rule| {
+                if rule_matches(prefix, rule) {
+                    Some(rule)
+                } else if rule_matches(rule, prefix) {
+                    Some(prefix)
+                } else {
+                    None
+                }
+            })
+            .any(|granted| !denied(granted))
+            && self.scopes.as_ref().is_none_or(|scopes| {
+                scopes
+                    .iter()
+                    .any(|s| rule_matches(prefix, s) || rule_matches(s, prefix))
+            })
+    }
+    // 管理员需要拥有配置中列出的所有权限
     pub fn is_admin(&self) -> bool {
-        self.has("*") || ["system", "yq", "hdwsh"].iter().all(|v| self.has(v))
+        !CFG.permission.admin.is_empty() && CFG.permission.admin.iter().all(|v| self.has(v))
     }
     pub fn into_inner(self) -> Vec<PermissionItem> {
         self.items
     }
     pub fn new(items: Vec<PermissionItem>) -> Self {
-        Self { items }
+        Self {
+            items,
+            scopes: None,
+        }
+    }
+    // 使用另一个权限的授权范围，用于部门角色的权限也受 API token 的限制
+    pub fn with_scopes_of(self, other: &Permission) -> Self {
+        Self {
+            scopes: other.scopes.clone(),
+            ..self
+        }
+    }
+    // 限制在授权范围之内，范围本身不授予任何权限
+    pub fn restrict(self, scopes: Vec<String>) -> Self {
+        Self {
+            scopes: Some(scopes),
+            ..self
+        }
     }
 }
 
 pub async fn get_permission_list() -> AppResult<Vec<PermissionItem>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, name, permission
-        FROM yqwork_new.permissions
+        SELECT id, name, permission, updatedAt
+        FROM permissions
         WHERE deletedAt IS NULL
         "#
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_yqwork_pool().await)
     .await?
     .into_iter()
     .map(|r| PermissionItem {
         id: r.id,
         name: r.name,
         permission: r.permission,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
     Ok(res)
 }
 
-pub async fn update_permission(id: u32, name: &str, permission: &str) -> AppResult<()> {
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+pub async fn update_permission(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+    name: &str,
+    permission: &str,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE yqwork_new.permissions
+        UPDATE permissions
         SET name = ?, permission = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         name,
         permission,
         now,
-        id
+        id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
 pub async fn add_permission(name: &str, permission: &str) -> AppResult<u32> {
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO yqwork_new.permissions (name, permission, createdAt, updatedAt)
+        INSERT INTO permissions (name, permission, createdAt, updatedAt)
         VALUES (?, ?, ?, ?)
         "#,
         name,
@@ -86,23 +177,143 @@ pub async fn add_permission(name: &str, permission: &str) -> AppResult<u32> {
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_yqwork_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
 
-pub async fn delete_permission(id: u32) -> AppResult<()> {
+pub async fn get_permission_dependents(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+) -> AppResult<Dependents> {
+    Ok(Dependents {
+        roles: count(
+            tx,
+            r#"
+            SELECT COUNT(*) FROM system_role_permission rp
+            INNER JOIN roles r
+            ON r.id = rp.roleId
+            WHERE rp.permissionId = ? AND r.deletedAt IS NULL
+            FOR UPDATE
+            "#,
+            id,
+        )
+        .await?,
+        ..Default::default()
+    })
+}
+
+/// 从所有角色中移除该权限
+pub async fn detach_permission(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM system_role_permission
+        WHERE permissionId = ?
+        "#,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_permission(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.permissions
+        UPDATE permissions
         SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(items: &[&str]) -> Permission {
+        Permission::new(
+            items
+                .iter()
+                .enumerate()
+                .map(|(id, p)| PermissionItem {
+                    id: id as u32,
+                    name: p.to_string(),
+                    permission: p.to_string(),
+                    updated_at: chrono::NaiveDateTime::default(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_has() {
+        let p = permission(&["yq:user", "hdwsh:*:query"]);
+        assert!(p.has("yq:user"));
+        assert!(p.has("yq:user:edit"));
+        assert!(!p.has("yq:userExport"));
+        assert!(!p.has("yq"));
+        assert!(p.has("hdwsh:feedback:query"));
+        assert!(!p.has("hdwsh:feedback:delete"));
+        assert!(!p.has("*"));
+        assert!(permission(&["*"]).has("yq:user"));
+    }
+
+    #[test]
+    fn test_has_any_under() {
+        let p = permission(&["yq:user:query", "!system"]);
+        assert!(p.has_any_under("yq"));
+        assert!(p.has_any_under("yq:user:query:self"));
+        assert!(!p.has_any_under("system"));
+        assert!(permission(&["*"]).has_any_under("system"));
+        // 拒绝规则覆盖了整个前缀
+        let p = permission(&["*", "!system"]);
+        assert!(!p.has_any_under("system"));
+        assert!(!p.has_any_under("system:role"));
+        assert!(p.has_any_under("yq"));
+        // 拒绝规则覆盖了前缀下唯一的授予规则
+        assert!(!permission(&["system:role:query", "!system:role"]).has_any_under("system"));
+        // 拒绝规则只覆盖前缀下的一部分
+        assert!(permission(&["*", "!system:role"]).has_any_under("system"));
+        assert!(permission(&["system", "!system:role"]).has_any_under("system"));
+    }
+
+    #[test]
+    fn test_deny() {
+        let p = permission(&["hdwsh:*", "!hdwsh:feedback:delete"]);
+        assert!(p.has("hdwsh:feedback:edit"));
+        assert!(!p.has("hdwsh:feedback:delete"));
+        let p = permission(&["*", "!system"]);
+        assert!(p.has("yq:user:query"));
+        assert!(!p.has("system:role:edit"));
+    }
+
+    #[test]
+    fn test_restrict() {
+        let p = permission(&["yq:*", "!yq:user:delete"]).restrict(vec![
+            "yq:jifen".into(),
+            "yq:user:query".into(),
+            "hdwsh".into(),
+        ]);
+        assert!(p.has("yq:jifen:add"));
+        assert!(p.has("yq:user:query"));
+        assert!(!p.has("yq:user:edit"));
+        assert!(!p.has("yq:user:delete"));
+        assert!(!p.has("hdwsh:feedback:query"));
+        assert!(p.has_any_under("yq"));
+        assert!(!p.has_any_under("hdwsh"));
+        assert!(!p.has_any_under("system"));
+    }
+}
@@ -0,0 +1,296 @@
+use sqlx::Row;
+
+use super::{get_weihuda_pool, get_yqwork_pool, limit_offset};
+use crate::{result::AppResult, utils};
+
+/// 回收站中支持的实体类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecycleEntity {
+    User,
+    Role,
+    Permission,
+    Department,
+    WorkHour,
+    Zhihu,
+    Notice,
+    Goods,
+    JifenRule,
+    Announcement,
+}
+
+impl RecycleEntity {
+    pub const ALL: [RecycleEntity; 10] = [
+        RecycleEntity::User,
+        RecycleEntity::Role,
+        RecycleEntity::Permission,
+        RecycleEntity::Department,
+        RecycleEntity::WorkHour,
+        RecycleEntity::Zhihu,
+        RecycleEntity::Notice,
+        RecycleEntity::Goods,
+        RecycleEntity::JifenRule,
+        RecycleEntity::Announcement,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RecycleEntity::User => "user",
+            RecycleEntity::Role => "role",
+            RecycleEntity::Permission => "permission",
+            RecycleEntity::Department => "department",
+            RecycleEntity::WorkHour => "workHour",
+            RecycleEntity::Zhihu => "zhihu",
+            RecycleEntity::Notice => "notice",
+            RecycleEntity::Goods => "goods",
+            RecycleEntity::JifenRule => "jifenRule",
+            RecycleEntity::Announcement => "announcement",
+        }
+    }
+
+    // 实体所在的库
+    async fn pool(self) -> &'static sqlx::MySqlPool {
+        match self {
+            RecycleEntity::User
+            | RecycleEntity::Role
+            | RecycleEntity::Permission
+            | RecycleEntity::Department
+            | RecycleEntity::WorkHour => get_yqwork_pool().await,
+            RecycleEntity::Zhihu
+            | RecycleEntity::Notice
+            | RecycleEntity::Goods
+            | RecycleEntity::JifenRule
+            | RecycleEntity::Announcement => get_weihuda_pool().await,
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            RecycleEntity::User => "users",
+            RecycleEntity::Role => "roles",
+            RecycleEntity::Permission => "permissions",
+            RecycleEntity::Department => "departments",
+            RecycleEntity::WorkHour => "work_hours",
+            RecycleEntity::Zhihu => "zhihus",
+            RecycleEntity::Notice => "notices",
+            RecycleEntity::Goods => "jifen_goods",
+            RecycleEntity::JifenRule => "jifen_rules",
+            RecycleEntity::Announcement => "announcement",
+        }
+    }
+
+    // 列表中用于展示的字段
+    fn label(self) -> &'static str {
+        match self {
+            RecycleEntity::User => "CONCAT(name, ' (', stuId, ')')",
+            RecycleEntity::Permission => "CONCAT(name, ' (', permission, ')')",
+            RecycleEntity::Role
+            | RecycleEntity::Department
+            | RecycleEntity::WorkHour
+            | RecycleEntity::Goods
+            | RecycleEntity::JifenRule => "name",
+            RecycleEntity::Zhihu | RecycleEntity::Announcement => "title",
+            RecycleEntity::Notice => "LEFT(content, 50)",
+        }
+    }
+
+    /// 需要在未删除的数据中保持唯一的字段，恢复时检查冲突
+    pub fn unique_column(self) -> Option<&'static str> {
+        match self {
+            RecycleEntity::User => Some("stuId"),
+            RecycleEntity::Role | RecycleEntity::Department => Some("name"),
+            RecycleEntity::Permission => Some("permission"),
+            RecycleEntity::JifenRule => Some("`key`"),
+            _ => None,
+        }
+    }
+
+    // 彻底删除时需要一并删除的关联数据，(表名, 关联字段)
+    fn dependents(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            RecycleEntity::User => &[
+                ("system_user_role", "userId"),
+                ("system_user_department_role", "userId"),
+                ("user_sessions", "userId"),
+                ("api_tokens", "userId"),
+                ("user_totp", "userId"),
+                ("password_resets", "userId"),
+                ("work_hours_records", "userId"),
+            ],
+            RecycleEntity::Role => &[
+                ("system_user_role", "roleId"),
+                ("system_user_department_role", "roleId"),
+                ("system_role_permission", "roleId"),
+                ("role_parents", "roleId"),
+                ("role_parents", "parentId"),
+            ],
+            RecycleEntity::Permission => &[("system_role_permission", "permissionId")],
+            RecycleEntity::Department => &[("system_user_department_role", "departmentId")],
+            RecycleEntity::WorkHour => &[("work_hours_records", "workHourId")],
+            _ => &[],
+        }
+    }
+
+    // 彻底删除时的额外条件
+    fn purge_condition(self) -> &'static str {
+        match self {
+            // 已删除的用户仍然可能被恢复，部门需要保留
+            RecycleEntity::Department => {
+                " AND NOT EXISTS (SELECT 1 FROM users u WHERE u.departmentId = departments.id)"
+            }
+            _ => "",
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedItem {
+    pub id: u32,
+    pub label: String,
+    pub deleted_at: chrono::NaiveDateTime,
+}
+
+pub async fn get_deleted_list(
+    entity: RecycleEntity,
+    page: u32,
+    page_size: u32,
+) -> AppResult<(u32, Vec<DeletedItem>)> {
+    let (limit, offset) = limit_offset(page, page_size);
+    let res = sqlx::query(&format!(
+        r#"
+        SELECT id, {} AS label, deletedAt
+        FROM {}
+        WHERE deletedAt IS NOT NULL
+        ORDER BY deletedAt DESC
+        LIMIT ? OFFSET ?
+        "#,
+        entity.label(),
+        entity.table()
+    ))
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(entity.pool().await)
+    .await?
+    .into_iter()
+    .map(|r| DeletedItem {
+        id: r.get("id"),
+        label: r.get("label"),
+        deleted_at: r.get("deletedAt"),
+    })
+    .collect::<Vec<_>>();
+    let count: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM {} WHERE deletedAt IS NOT NULL",
+        entity.table()
+    ))
+    .fetch_one(entity.pool().await)
+    .await?;
+    Ok((count as u32, res))
+}
+
+pub async fn get_deleted(entity: RecycleEntity, id: u32) -> AppResult<Option<DeletedItem>> {
+    let res = sqlx::query(&format!(
+        r#"
+        SELECT id, {} AS label, deletedAt
+        FROM {}
+        WHERE id = ? AND deletedAt IS NOT NULL
+        "#,
+        entity.label(),
+        entity.table()
+    ))
+    .bind(id)
+    .fetch_optional(entity.pool().await)
+    .await?
+    .map(|r| DeletedItem {
+        id: r.get("id"),
+        label: r.get("label"),
+        deleted_at: r.get("deletedAt"),
+    });
+    Ok(res)
+}
+
+/// 统计未删除的数据中和该条数据的唯一字段相同的数量
+pub async fn count_unique_conflicts(entity: RecycleEntity, id: u32) -> AppResult<u32> {
+    let Some(column) = entity.unique_column() else {
+        return Ok(0);
+    };
+    let table = entity.table();
+    let count: i64 = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COUNT(*) FROM {table}
+        WHERE deletedAt IS NULL AND {column} = (SELECT {column} FROM {table} WHERE id = ?)
+        "#
+    ))
+    .bind(id)
+    .fetch_one(entity.pool().await)
+    .await?;
+    Ok(count as u32)
+}
+
+/// 已删除用户所在的部门是否仍然存在
+pub async fn user_department_exists(user_id: u32) -> AppResult<bool> {
+    let count: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*)
+        FROM users u
+        INNER JOIN departments d
+        ON d.id = u.departmentId
+        WHERE u.id = ? AND d.deletedAt IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(get_yqwork_pool().await)
+    .await?;
+    Ok(count > 0)
+}
+
+pub async fn restore(entity: RecycleEntity, id: u32) -> AppResult<bool> {
+    let now = utils::now_time();
+    let res = sqlx::query(&format!(
+        r#"
+        UPDATE {}
+        SET deletedAt = NULL, updatedAt = ?
+        WHERE id = ? AND deletedAt IS NOT NULL
+        "#,
+        entity.table()
+    ))
+    .bind(now)
+    .bind(id)
+    .execute(entity.pool().await)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// 彻底删除在 deleted_before 之前删除的数据，返回删除的数量
+pub async fn purge(entity: RecycleEntity, deleted_before: chrono::NaiveDateTime) -> AppResult<u64> {
+    let table = entity.table();
+    let condition = entity.purge_condition();
+    let mut tx = entity.pool().await.begin().await?;
+    for (dependent, column) in entity.dependents() {
+        sqlx::query(&format!(
+            r#"
+            DELETE FROM {dependent}
+            WHERE {column} IN (
+                SELECT id FROM {table} WHERE deletedAt IS NOT NULL AND deletedAt < ?{condition}
+            )
+            "#
+        ))
+        .bind(deleted_before)
+        .execute(&mut *tx)
+        .await?;
+    }
+    let res = sqlx::query(&format!(
+        r#"
+        DELETE FROM {table}
+        WHERE deletedAt IS NOT NULL AND deletedAt < ?{condition}
+        "#
+    ))
+    .bind(deleted_before)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(res.rows_affected())
+}
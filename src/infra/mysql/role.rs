@@ -1,102 +1,435 @@
 use sqlx::Row;
+use std::collections::HashMap;
 
-use super::get_db_pool;
+use super::{Dependents, count, ensure_version_matched, get_yqwork_pool};
 use crate::result::AppResult;
 use crate::service::qnxg::permission::{Permission, PermissionItem};
 use crate::utils;
 
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct Role {
     pub id: u32,
     pub name: String,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+/// 用户的一条角色授予，validFrom 和 validUntil 都为空时为永久授予
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleGrant {
+    pub role: Role,
+    pub valid_from: Option<chrono::NaiveDateTime>,
+    pub valid_until: Option<chrono::NaiveDateTime>,
 }
 
+/// 过期后被清理的临时授予
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiredRoleGrant {
+    pub user_id: u32,
+    pub role_id: u32,
+    pub valid_until: chrono::NaiveDateTime,
+}
+
+/// 限定在某个部门内生效的角色
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DepartmentRole {
+    pub role: Role,
+    pub department_id: u32,
+}
+
+/// 获取用户当前生效的角色，未开始或已过期的临时授予不计入
 pub async fn get_user_roles(user_id: u32) -> AppResult<Vec<Role>> {
+    let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        SELECT id, name
-        FROM yqwork_new.system_user_role ur
-        INNER JOIN yqwork_new.roles r
+        SELECT r.id, r.name, r.updatedAt
+        FROM system_user_role ur
+        INNER JOIN roles r
         ON r.id = ur.roleId
         WHERE ur.userId = ? AND r.deletedAt IS NULL
+            AND (ur.validFrom IS NULL OR ur.validFrom <= ?)
+            AND (ur.validUntil IS NULL OR ur.validUntil > ?)
         "#,
-        user_id
+        user_id,
+        now,
+        now
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_yqwork_pool().await)
     .await?
     .into_iter()
     .map(|r| Role {
         id: r.id,
         name: r.name,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
     Ok(res)
 }
 
-pub async fn update_user_roles(user_id: u32, role_id: &[u32]) -> AppResult<()> {
+/// 替换用户的永久角色，不影响其他角色的临时授予
+/// 已有临时授予的角色会变为永久授予
+pub async fn update_user_roles(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    user_id: u32,
+    role_id: &[u32],
+) -> AppResult<()> {
     let now = utils::now_time();
-    let pool = get_db_pool().await;
-
-    let mut tx = pool.begin().await?;
-
     sqlx::query!(
         r#"
-        DELETE FROM yqwork_new.system_user_role
-        WHERE userId = ?
+        DELETE FROM system_user_role
+        WHERE userId = ? AND validFrom IS NULL AND validUntil IS NULL
         "#,
         user_id
     )
-    .execute(&mut *tx)
+    .execute(&mut **tx)
     .await?;
 
     for r_id in role_id {
         sqlx::query!(
             r#"
-            INSERT INTO yqwork_new.system_user_role (userId, roleId, createdAt, updatedAt)
-            VALUES (?, ?, ?, ?)
+            INSERT INTO system_user_role (userId, roleId, validFrom, validUntil, createdAt, updatedAt)
+            VALUES (?, ?, NULL, NULL, ?, ?)
+            ON DUPLICATE KEY UPDATE validFrom = NULL, validUntil = NULL, updatedAt = VALUES(updatedAt)
             "#,
             user_id,
             r_id,
             now,
             now
         )
-        .execute(&mut *tx)
+        .execute(&mut **tx)
         .await?;
     }
 
-    tx.commit().await?;
     Ok(())
 }
 
+/// 所有用户的永久角色，(userId, roleId)，不包括已删除的角色
+/// 加锁读取，用于在同一个事务中检查并修改
+pub async fn lock_permanent_user_roles(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+) -> AppResult<Vec<(u32, u32)>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT ur.userId, ur.roleId
+        FROM system_user_role ur
+        INNER JOIN roles r
+        ON r.id = ur.roleId
+        WHERE r.deletedAt IS NULL AND ur.validFrom IS NULL AND ur.validUntil IS NULL
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .map(|r| (r.userId, r.roleId))
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
+/// 获取用户所有的角色授予，包括未开始和尚未清理的已过期授予
+pub async fn get_user_role_grants(user_id: u32) -> AppResult<Vec<RoleGrant>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT r.id, r.name, r.updatedAt, ur.validFrom, ur.validUntil
+        FROM system_user_role ur
+        INNER JOIN roles r
+        ON r.id = ur.roleId
+        WHERE ur.userId = ? AND r.deletedAt IS NULL
+        "#,
+        user_id
+    )
+    .fetch_all(get_yqwork_pool().await)
+    .await?
+    .into_iter()
+    .map(|r| RoleGrant {
+        role: Role {
+            id: r.id,
+            name: r.name,
+            updated_at: r.updatedAt,
+        },
+        valid_from: r.validFrom,
+        valid_until: r.validUntil,
+    })
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
+/// 添加或更新临时授予，调用前需要确认该角色不是永久授予
+pub async fn set_role_grant(
+    user_id: u32,
+    role_id: u32,
+    valid_from: Option<chrono::NaiveDateTime>,
+    valid_until: chrono::NaiveDateTime,
+) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        INSERT INTO system_user_role (userId, roleId, validFrom, validUntil, createdAt, updatedAt)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE validFrom = VALUES(validFrom), validUntil = VALUES(validUntil), updatedAt = VALUES(updatedAt)
+        "#,
+        user_id,
+        role_id,
+        valid_from,
+        valid_until,
+        now,
+        now
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_role_grant(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    user_id: u32,
+    role_id: u32,
+) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM system_user_role
+        WHERE userId = ? AND roleId = ?
+        "#,
+        user_id,
+        role_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_user_department_roles(user_id: u32) -> AppResult<Vec<DepartmentRole>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT r.id, r.name, r.updatedAt, udr.departmentId
+        FROM system_user_department_role udr
+        INNER JOIN roles r
+        ON r.id = udr.roleId
+        INNER JOIN departments d
+        ON d.id = udr.departmentId
+        WHERE udr.userId = ? AND r.deletedAt IS NULL AND d.deletedAt IS NULL
+        "#,
+        user_id
+    )
+    .fetch_all(get_yqwork_pool().await)
+    .await?
+    .into_iter()
+    .map(|r| DepartmentRole {
+        role: Role {
+            id: r.id,
+            name: r.name,
+            updated_at: r.updatedAt,
+        },
+        department_id: r.departmentId,
+    })
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
+pub async fn add_user_department_role(
+    user_id: u32,
+    role_id: u32,
+    department_id: u32,
+) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        INSERT IGNORE INTO system_user_department_role (userId, roleId, departmentId, createdAt)
+        VALUES (?, ?, ?, ?)
+        "#,
+        user_id,
+        role_id,
+        department_id,
+        now
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_user_department_role(
+    user_id: u32,
+    role_id: u32,
+    department_id: u32,
+) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM system_user_department_role
+        WHERE userId = ? AND roleId = ? AND departmentId = ?
+        "#,
+        user_id,
+        role_id,
+        department_id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+/// 删除已过期的临时授予，返回被删除的授予
+pub async fn delete_expired_role_grants() -> AppResult<Vec<ExpiredRoleGrant>> {
+    let now = utils::now_time();
+    let mut tx = get_yqwork_pool().await.begin().await?;
+    let expired = sqlx::query!(
+        r#"
+        SELECT userId, roleId, validUntil AS "validUntil!"
+        FROM system_user_role
+        WHERE validUntil IS NOT NULL AND validUntil <= ?
+        FOR UPDATE
+        "#,
+        now
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|r| ExpiredRoleGrant {
+        user_id: r.userId,
+        role_id: r.roleId,
+        valid_until: r.validUntil,
+    })
+    .collect::<Vec<_>>();
+    sqlx::query!(
+        r#"
+        DELETE FROM system_user_role
+        WHERE validUntil IS NOT NULL AND validUntil <= ?
+        "#,
+        now
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(expired)
+}
+
 pub async fn get_role_list() -> AppResult<Vec<Role>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, name
-        FROM yqwork_new.roles
+        SELECT id, name, updatedAt
+        FROM roles
         WHERE deletedAt IS NULL
         "#
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_yqwork_pool().await)
     .await?
     .into_iter()
     .map(|r| Role {
         id: r.id,
         name: r.name,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
     Ok(res)
 }
 
+/// 角色之间的继承关系，(roleId, parentId)，已删除的父角色不计入
+pub async fn get_role_parents() -> AppResult<Vec<(u32, u32)>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT rp.roleId, rp.parentId
+        FROM role_parents rp
+        INNER JOIN roles r
+        ON r.id = rp.parentId
+        WHERE r.deletedAt IS NULL
+        "#
+    )
+    .fetch_all(get_yqwork_pool().await)
+    .await?
+    .into_iter()
+    .map(|r| (r.roleId, r.parentId))
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
+/// 同 get_role_parents，加锁读取，用于在同一个事务中检查并修改
+pub async fn lock_role_parents(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+) -> AppResult<Vec<(u32, u32)>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT rp.roleId, rp.parentId
+        FROM role_parents rp
+        INNER JOIN roles r
+        ON r.id = rp.parentId
+        WHERE r.deletedAt IS NULL
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .map(|r| (r.roleId, r.parentId))
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
+/// 所有未删除角色自身的权限（不含继承），按角色分组
+/// 加锁读取，用于在同一个事务中检查并修改
+pub async fn lock_role_own_permissions(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+) -> AppResult<HashMap<u32, Vec<PermissionItem>>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT rp.roleId, p.id, p.name, p.permission, p.updatedAt
+        FROM system_role_permission rp
+        INNER JOIN permissions p
+        ON p.id = rp.permissionId
+        INNER JOIN roles r
+        ON r.id = rp.roleId
+        WHERE p.deletedAt IS NULL AND r.deletedAt IS NULL
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    let mut res: HashMap<u32, Vec<PermissionItem>> = HashMap::new();
+    for r in rows {
+        res.entry(r.roleId).or_default().push(PermissionItem {
+            id: r.id,
+            name: r.name,
+            permission: r.permission,
+            updated_at: r.updatedAt,
+        });
+    }
+    Ok(res)
+}
+
+/// 展开角色继承的所有祖先角色，结果包含角色本身，遇到环时不会重复展开
+pub fn expand_roles(role_id: &[u32], parents: &[(u32, u32)]) -> Vec<u32> {
+    let mut res = Vec::new();
+    let mut stack = role_id.to_vec();
+    while let Some(id) = stack.pop() {
+        if res.contains(&id) {
+            continue;
+        }
+        res.push(id);
+        stack.extend(parents.iter().filter(|(r, _)| *r == id).map(|(_, p)| *p));
+    }
+    res
+}
+
+/// 角色最终拥有的权限，包括从父角色继承的权限
 pub async fn get_role_permission(role_id: &[u32]) -> AppResult<Permission> {
+    if role_id.is_empty() {
+        return Ok(Permission::new(vec![]));
+    }
+    let parents = get_role_parents().await?;
+    get_role_own_permission(&expand_roles(role_id, &parents)).await
+}
+
+/// 角色自身直接拥有的权限，不包括继承的权限
+pub async fn get_role_own_permission(role_id: &[u32]) -> AppResult<Permission> {
     if role_id.is_empty() {
         return Ok(Permission::new(vec![]));
     }
     let placeholders = vec!["?"; role_id.len()].join(",");
     let query_str = format!(
         r#"
-            SELECT DISTINCT p.id, p.name, p.permission
-            FROM yqwork_new.system_role_permission rp 
-            INNER JOIN yqwork_new.permissions p 
+            SELECT DISTINCT p.id, p.name, p.permission, p.updatedAt
+            FROM system_role_permission rp 
+            INNER JOIN permissions p 
             ON p.id = rp.permissionId 
             WHERE p.deletedAt IS NULL AND rp.roleId IN ({})
             "#,
@@ -107,51 +440,60 @@ pub async fn get_role_permission(role_id: &[u32]) -> AppResult<Permission> {
         query = query.bind(id);
     }
     let res = query
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_yqwork_pool().await)
         .await?
         .into_iter()
         .map(|r| PermissionItem {
             id: r.get("id"),
             name: r.get("name"),
             permission: r.get("permission"),
+            updated_at: r.get("updatedAt"),
         })
         .collect::<Vec<_>>();
     Ok(Permission::new(res))
 }
 
-pub async fn update_role(role_id: u32, name: &str, permission: &[u32]) -> AppResult<()> {
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+/// parent_id 为 None 时不修改继承关系
+pub async fn update_role(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    role_id: u32,
+    name: &str,
+    permission: &[u32],
+    parent_id: Option<&[u32]>,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
     let now = utils::now_time();
-    let pool = get_db_pool().await;
-
-    let mut tx = pool.begin().await?;
 
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE yqwork_new.roles
+        UPDATE roles
         SET name = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         name,
         now,
-        role_id
+        role_id,
+        version
     )
-    .execute(&mut *tx)
+    .execute(&mut **tx)
     .await?;
+    ensure_version_matched(&res)?;
 
     sqlx::query!(
         r#"
-        DELETE FROM yqwork_new.system_role_permission
+        DELETE FROM system_role_permission
         WHERE roleId = ?
         "#,
         role_id
     )
-    .execute(&mut *tx)
+    .execute(&mut **tx)
     .await?;
 
     for perm_id in permission {
         sqlx::query!(
             r#"
-            INSERT INTO yqwork_new.system_role_permission (roleId, permissionId, createdAt, updatedAt)
+            INSERT INTO system_role_permission (roleId, permissionId, createdAt, updatedAt)
             VALUES (?, ?, ?, ?)
             "#,
             role_id,
@@ -159,23 +501,26 @@ pub async fn update_role(role_id: u32, name: &str, permission: &[u32]) -> AppRes
             now,
             now
         )
-        .execute(&mut *tx)
+        .execute(&mut **tx)
         .await?;
     }
 
-    tx.commit().await?;
+    if let Some(parent_id) = parent_id {
+        set_role_parents(tx, role_id, parent_id).await?;
+    }
+
     Ok(())
 }
 
-pub async fn add_role(name: &str, permission: &[u32]) -> AppResult<u32> {
+pub async fn add_role(name: &str, permission: &[u32], parent_id: &[u32]) -> AppResult<u32> {
     let now = utils::now_time();
-    let pool = get_db_pool().await;
+    let pool = get_yqwork_pool().await;
 
     let mut tx = pool.begin().await?;
 
     let res = sqlx::query!(
         r#"
-        INSERT INTO yqwork_new.roles (name, createdAt, updatedAt)
+        INSERT INTO roles (name, createdAt, updatedAt)
         VALUES (?, ?, ?)
         "#,
         name,
@@ -189,7 +534,7 @@ pub async fn add_role(name: &str, permission: &[u32]) -> AppResult<u32> {
     for perm_id in permission {
         sqlx::query!(
             r#"
-            INSERT INTO yqwork_new.system_role_permission (roleId, permissionId, createdAt, updatedAt)
+            INSERT INTO system_role_permission (roleId, permissionId, createdAt, updatedAt)
             VALUES (?, ?, ?, ?)
             "#,
             role_id,
@@ -201,22 +546,122 @@ pub async fn add_role(name: &str, permission: &[u32]) -> AppResult<u32> {
         .await?;
     }
 
+    set_role_parents(&mut tx, role_id, parent_id).await?;
+
     tx.commit().await?;
     Ok(role_id)
 }
 
-pub async fn delete_role(id: u32) -> AppResult<()> {
+/// 替换角色的父角色
+async fn set_role_parents(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    role_id: u32,
+    parent_id: &[u32],
+) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        DELETE FROM role_parents
+        WHERE roleId = ?
+        "#,
+        role_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    for p_id in parent_id {
+        sqlx::query!(
+            r#"
+            INSERT INTO role_parents (roleId, parentId, createdAt)
+            VALUES (?, ?, ?)
+            "#,
+            role_id,
+            p_id,
+            now
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn get_role_dependents(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    id: u32,
+) -> AppResult<Dependents> {
+    Ok(Dependents {
+        users: count(
+            tx,
+            r#"
+            SELECT COUNT(*) FROM system_user_role ur
+            INNER JOIN users u
+            ON u.id = ur.userId
+            WHERE ur.roleId = ? AND u.deletedAt IS NULL
+            FOR UPDATE
+            "#,
+            id,
+        )
+        .await?,
+        department_roles: count(
+            tx,
+            "SELECT COUNT(*) FROM system_user_department_role WHERE roleId = ?",
+            id,
+        )
+        .await?,
+        roles: count(
+            tx,
+            r#"
+            SELECT COUNT(*) FROM role_parents rp
+            INNER JOIN roles r
+            ON r.id = rp.roleId
+            WHERE rp.parentId = ? AND r.deletedAt IS NULL
+            "#,
+            id,
+        )
+        .await?,
+    })
+}
+
+/// 移除用户的该角色、部门角色授予和子角色的继承关系，角色自身的权限和父角色保留，以便之后恢复
+pub async fn detach_role(tx: &mut sqlx::Transaction<'_, sqlx::MySql>, id: u32) -> AppResult<()> {
+    for sql in [
+        "DELETE FROM system_user_role WHERE roleId = ?",
+        "DELETE FROM system_user_department_role WHERE roleId = ?",
+        "DELETE FROM role_parents WHERE parentId = ?",
+    ] {
+        sqlx::query(sql).bind(id).execute(&mut **tx).await?;
+    }
+    Ok(())
+}
+
+/// 删除部门时移除只在该部门内生效的角色授予
+pub async fn delete_department_roles(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    department_id: u32,
+) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM system_user_department_role
+        WHERE departmentId = ?
+        "#,
+        department_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_role(tx: &mut sqlx::Transaction<'_, sqlx::MySql>, id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.roles
+        UPDATE roles
         SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
     Ok(())
 }
@@ -0,0 +1,154 @@
+use super::get_yqwork_pool;
+use crate::{result::AppResult, utils};
+
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: u32,
+    pub user_id: u32,
+    pub ip: String,
+    pub user_agent: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub last_active_at: chrono::NaiveDateTime,
+    pub expires_at: chrono::NaiveDateTime,
+    #[serde(skip)]
+    pub refresh_token: String,
+    // 每次刷新 token 时递增，用于判断 access token 是否已经被新的 token 取代
+    #[serde(skip)]
+    pub generation: u32,
+    #[serde(skip)]
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Session {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > utils::now_time()
+    }
+}
+
+/// refresh_token 参数为哈希后的值
+pub async fn add_session(
+    user_id: u32,
+    refresh_token: &str,
+    ip: &str,
+    user_agent: Option<&str>,
+    expires_at: chrono::NaiveDateTime,
+) -> AppResult<u32> {
+    let now = utils::now_time();
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (userId, refreshToken, generation, ip, userAgent, createdAt, lastActiveAt, expiresAt)
+        VALUES (?, ?, 0, ?, ?, ?, ?, ?)
+        "#,
+        user_id,
+        refresh_token,
+        ip,
+        user_agent,
+        now,
+        now,
+        expires_at
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.last_insert_id() as u32)
+}
+
+pub async fn get_session(id: u32) -> AppResult<Option<Session>> {
+    let res = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, userId AS user_id, refreshToken AS refresh_token, generation, ip, userAgent AS user_agent,
+            createdAt AS created_at, lastActiveAt AS last_active_at, expiresAt AS expires_at, revokedAt AS revoked_at
+        FROM user_sessions
+        WHERE id = ?
+        "#,
+        id
+    )
+    .fetch_optional(get_yqwork_pool().await)
+    .await?;
+    Ok(res)
+}
+
+/// 获取用户所有未撤销且未过期的会话
+pub async fn get_user_sessions(user_id: u32) -> AppResult<Vec<Session>> {
+    let now = utils::now_time();
+    let res = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, userId AS user_id, refreshToken AS refresh_token, generation, ip, userAgent AS user_agent,
+            createdAt AS created_at, lastActiveAt AS last_active_at, expiresAt AS expires_at, revokedAt AS revoked_at
+        FROM user_sessions
+        WHERE userId = ? AND revokedAt IS NULL AND expiresAt > ?
+        ORDER BY lastActiveAt DESC
+        "#,
+        user_id,
+        now
+    )
+    .fetch_all(get_yqwork_pool().await)
+    .await?;
+    Ok(res)
+}
+
+/// 轮换会话的 refresh token，同时递增 generation 使旧的 access token 失效
+/// 只有当前的 refresh token 仍为 old_refresh_token 时才会更新，返回是否更新成功
+pub async fn rotate_session(
+    id: u32,
+    old_refresh_token: &str,
+    refresh_token: &str,
+    ip: &str,
+    user_agent: Option<&str>,
+    expires_at: chrono::NaiveDateTime,
+) -> AppResult<bool> {
+    let now = utils::now_time();
+    let res = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET refreshToken = ?, generation = generation + 1, ip = ?, userAgent = ?, lastActiveAt = ?, expiresAt = ?
+        WHERE id = ? AND refreshToken = ? AND revokedAt IS NULL
+        "#,
+        refresh_token,
+        ip,
+        user_agent,
+        now,
+        expires_at,
+        id,
+        old_refresh_token
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn revoke_session(id: u32) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revokedAt = ?
+        WHERE id = ? AND revokedAt IS NULL
+        "#,
+        now,
+        id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+/// 撤销用户的所有会话，except 为需要保留的会话
+pub async fn revoke_user_sessions(user_id: u32, except: Option<u32>) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revokedAt = ?
+        WHERE userId = ? AND revokedAt IS NULL AND id <> ?
+        "#,
+        now,
+        user_id,
+        except.unwrap_or(0)
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
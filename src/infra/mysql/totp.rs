@@ -0,0 +1,142 @@
+use super::get_yqwork_pool;
+use crate::{result::AppResult, utils};
+
+pub struct UserTotp {
+    // base32 编码的密钥
+    pub secret: String,
+    // 设置密钥后需要输入一次验证码才会启用
+    pub enabled: bool,
+    // 恢复码的 sha256，使用后删除
+    pub recovery_codes: Vec<String>,
+    // 最近一次使用的验证码所在的周期，同一个验证码不能重复使用
+    pub last_used_step: u64,
+}
+
+pub async fn get_user_totp(user_id: u32) -> AppResult<Option<UserTotp>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT secret, enabled, recoveryCodes, lastUsedStep
+        FROM user_totp
+        WHERE userId = ?
+        "#,
+        user_id
+    )
+    .fetch_optional(get_yqwork_pool().await)
+    .await?
+    .map(|r| UserTotp {
+        secret: r.secret,
+        enabled: r.enabled,
+        recovery_codes: serde_json::from_str(&r.recoveryCodes).unwrap_or_default(),
+        last_used_step: r.lastUsedStep,
+    });
+    Ok(res)
+}
+
+/// 设置新的密钥，之前的密钥和恢复码失效，需要重新启用
+pub async fn set_pending_secret(user_id: u32, secret: &str) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_totp (userId, secret, enabled, recoveryCodes, lastUsedStep, createdAt, updatedAt)
+        VALUES (?, ?, 0, '[]', 0, ?, ?)
+        ON DUPLICATE KEY UPDATE
+            secret = VALUES(secret),
+            enabled = 0,
+            recoveryCodes = '[]',
+            lastUsedStep = 0,
+            updatedAt = VALUES(updatedAt)
+        "#,
+        user_id,
+        secret,
+        now,
+        now
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+pub async fn enable_totp(user_id: u32, recovery_codes: &[String]) -> AppResult<()> {
+    let now = utils::now_time();
+    sqlx::query!(
+        r#"
+        UPDATE user_totp
+        SET enabled = 1, recoveryCodes = ?, updatedAt = ?
+        WHERE userId = ?
+        "#,
+        serde_json::to_string(recovery_codes).unwrap_or_default(),
+        now,
+        user_id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
+
+/// 记录使用过的验证码周期，周期不大于已记录的周期时返回 false
+pub async fn use_step(user_id: u32, step: u64) -> AppResult<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE user_totp
+        SET lastUsedStep = ?
+        WHERE userId = ? AND lastUsedStep < ?
+        "#,
+        step,
+        user_id,
+        step
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// 删除一个恢复码，恢复码不存在时返回 false
+pub async fn use_recovery_code(user_id: u32, code_hash: &str) -> AppResult<bool> {
+    let now = utils::now_time();
+    let mut tx = get_yqwork_pool().await.begin().await?;
+    let codes = sqlx::query_scalar!(
+        r#"
+        SELECT recoveryCodes
+        FROM user_totp
+        WHERE userId = ? AND enabled = 1
+        FOR UPDATE
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    let mut codes: Vec<String> = codes
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or_default();
+    let Some(index) = codes.iter().position(|c| c == code_hash) else {
+        return Ok(false);
+    };
+    codes.remove(index);
+    sqlx::query!(
+        r#"
+        UPDATE user_totp
+        SET recoveryCodes = ?, updatedAt = ?
+        WHERE userId = ?
+        "#,
+        serde_json::to_string(&codes).unwrap_or_default(),
+        now,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn delete_user_totp(user_id: u32) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM user_totp
+        WHERE userId = ?
+        "#,
+        user_id
+    )
+    .execute(get_yqwork_pool().await)
+    .await?;
+    Ok(())
+}
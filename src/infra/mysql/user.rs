@@ -1,17 +1,21 @@
 use sqlx::Row;
 
-use super::get_db_pool;
+use super::{ensure_version_matched, get_yqwork_pool};
 use crate::{result::AppResult, utils};
 
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: u32,
     pub last_login: Option<chrono::NaiveDateTime>,
+    // 服务账号只能通过 API token 访问，不能登录
+    pub service_account: bool,
+    // 用作乐观锁的版本号，更新时需要原样传回
+    pub updated_at: chrono::NaiveDateTime,
     pub info: UserBasicInfo,
 }
 
-#[derive(serde::Serialize, Debug)]
+#[derive(serde::Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct UserBasicInfo {
     pub username: Option<String>,
@@ -53,6 +57,16 @@ impl From<UserStatus> for u32 {
         }
     }
 }
+impl UserStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            UserStatus::Unknown => "未知",
+            UserStatus::Intern => "实习",
+            UserStatus::Formal => "正式",
+            UserStatus::Retaired => "退休",
+        }
+    }
+}
 impl serde::Serialize for UserStatus {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -63,25 +77,26 @@ impl serde::Serialize for UserStatus {
     }
 }
 
+/// department_id 为 None 时不限部门
 pub async fn get_user_list(
     page: u32,
     page_size: u32,
     stu_id: Option<&str>,
     name: Option<&str>,
-    department_id: Option<u32>,
+    department_id: Option<&[u32]>,
     status: Option<u32>,
 ) -> AppResult<(u32, Vec<User>)> {
     let mut main_query = sqlx::QueryBuilder::new(
         r#"
-        SELECT id, username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, lastLogin, departmentId
-        FROM yqwork_new.users
+        SELECT id, username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, lastLogin, departmentId, serviceAccount, updatedAt
+        FROM users
         WHERE deletedAt IS NULL
         "#,
     );
     let mut count_query = sqlx::QueryBuilder::new(
         r#"
         SELECT COUNT(*) as count
-        FROM yqwork_new.users
+        FROM users
         WHERE deletedAt IS NULL
         "#,
     );
@@ -101,13 +116,20 @@ pub async fn get_user_list(
             .push(" AND name LIKE ")
             .push_bind(format!("%{}%", name));
     }
+    // 为空时没有匹配的用户
     if let Some(department_id) = department_id {
-        main_query
-            .push(" AND departmentId = ")
-            .push_bind(department_id);
-        count_query
-            .push(" AND departmentId = ")
-            .push_bind(department_id);
+        for query in [&mut main_query, &mut count_query] {
+            if department_id.is_empty() {
+                query.push(" AND FALSE");
+                continue;
+            }
+            query.push(" AND departmentId IN (");
+            let mut separated = query.separated(", ");
+            for id in department_id {
+                separated.push_bind(*id);
+            }
+            query.push(")");
+        }
     }
     if let Some(status) = status {
         main_query.push(" AND status = ").push_bind(status);
@@ -120,7 +142,7 @@ pub async fn get_user_list(
         .push_bind((page - 1) * page_size);
     let res = main_query
         .build()
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_yqwork_pool().await)
         .await?
         .into_iter()
         .map(|r| User {
@@ -138,25 +160,50 @@ pub async fn get_user_list(
                 department_id: r.get("departmentId"),
             },
             last_login: r.get("lastLogin"),
+            service_account: r.get::<u8, _>("serviceAccount") != 0,
+            updated_at: r.get("updatedAt"),
         })
         .collect::<Vec<_>>();
     let count: i64 = count_query
         .build_query_scalar()
-        .fetch_one(get_db_pool().await)
+        .fetch_one(get_yqwork_pool().await)
         .await?;
     Ok((count as u32, res))
 }
 
+/// 状态在 status 之中的普通用户的 id，不包括服务账号
+/// 加锁读取，用于在同一个事务中检查并修改
+pub async fn lock_user_ids_by_status(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    status: &[u32],
+) -> AppResult<Vec<u32>> {
+    let res = sqlx::query!(
+        r#"
+        SELECT id, status
+        FROM users
+        WHERE deletedAt IS NULL AND serviceAccount = 0
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .filter(|r| status.contains(&r.status))
+    .map(|r| r.id)
+    .collect::<Vec<_>>();
+    Ok(res)
+}
+
 pub async fn get_user(user_id: u32) -> AppResult<Option<User>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, lastLogin, departmentId
-        FROM yqwork_new.users
+        SELECT id, username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, lastLogin, departmentId, serviceAccount, updatedAt
+        FROM users
         WHERE id = ? AND deletedAt IS NULL
         "#,
         user_id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| User {
         id: r.id,
@@ -173,16 +220,22 @@ pub async fn get_user(user_id: u32) -> AppResult<Option<User>> {
             department_id: r.departmentId,
         },
         last_login: r.lastLogin,
+        service_account: r.serviceAccount != 0,
+        updated_at: r.updatedAt,
     });
     Ok(res)
 }
 
-pub async fn add_user(info: &UserBasicInfo, password: &str) -> AppResult<u32> {
+pub async fn add_user(
+    info: &UserBasicInfo,
+    password: &str,
+    service_account: bool,
+) -> AppResult<u32> {
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO yqwork_new.users (username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, departmentId, password, createdAt, updatedAt)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO users (username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, departmentId, password, serviceAccount, createdAt, updatedAt)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         info.username,
         info.name,
@@ -195,19 +248,26 @@ pub async fn add_user(info: &UserBasicInfo, password: &str) -> AppResult<u32> {
         u32::from(info.status),
         info.department_id,
         password,
+        service_account,
         now,
         now
-    ).execute(get_db_pool().await).await?;
+    ).execute(get_yqwork_pool().await).await?;
     Ok(res.last_insert_id() as u32)
 }
 
-pub async fn update_user(user_id: u32, info: &UserBasicInfo) -> AppResult<()> {
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+pub async fn update_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    user_id: u32,
+    info: &UserBasicInfo,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE yqwork_new.users
+        UPDATE users
         SET name = ?, stuId = ?, email = ?, xueyuan = ?, gangwei = ?, zaiku = ?, qingonggang = ?, status = ?, departmentId = ?, updatedAt = ?, username = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         info.name,
         info.stu_id,
@@ -220,25 +280,29 @@ pub async fn update_user(user_id: u32, info: &UserBasicInfo) -> AppResult<()> {
         info.department_id,
         now,
         info.username,
-        user_id
+        user_id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
-pub async fn delete_user(user_id: u32) -> AppResult<()> {
+pub async fn delete_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    user_id: u32,
+) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.users
+        UPDATE users
         SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         user_id
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
     .await?;
     Ok(())
 }
@@ -247,7 +311,7 @@ pub async fn delete_user(user_id: u32) -> AppResult<()> {
 //     let now = utils::now_time();
 //     sqlx::query!(
 //         r#"
-//         UPDATE yqwork_new.users
+//         UPDATE users
 //         SET departmentId = ?, updatedAt = ?
 //         WHERE id = ?
 //         "#,
@@ -255,7 +319,7 @@ pub async fn delete_user(user_id: u32) -> AppResult<()> {
 //         now,
 //         user_id
 //     )
-//     .execute(get_db_pool().await)
+//     .execute(get_yqwork_pool().await)
 //     .await?;
 //     Ok(())
 // }
@@ -263,13 +327,13 @@ pub async fn delete_user(user_id: u32) -> AppResult<()> {
 pub async fn get_user_by_stu_id(stu_id: &str) -> AppResult<Option<User>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, lastLogin, departmentId
-        FROM yqwork_new.users
+        SELECT id, username, name, stuId, email, xueyuan, gangwei, zaiku, qingonggang, status, lastLogin, departmentId, serviceAccount, updatedAt
+        FROM users
         WHERE stuId = ? AND deletedAt IS NULL
         "#,
         stu_id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| User {
         id: r.id,
@@ -286,6 +350,8 @@ pub async fn get_user_by_stu_id(stu_id: &str) -> AppResult<Option<User>> {
             department_id: r.departmentId,
         },
         last_login: r.lastLogin,
+        service_account: r.serviceAccount != 0,
+        updated_at: r.updatedAt,
     });
     Ok(res)
 }
@@ -294,22 +360,26 @@ pub async fn get_user_password(user_id: u32) -> AppResult<Option<String>> {
     let res = sqlx::query!(
         r#"
         SELECT password
-        FROM yqwork_new.users
+        FROM users
         WHERE id = ? AND deletedAt IS NULL
         "#,
         user_id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| r.password);
     Ok(res)
 }
 
-pub async fn update_user_password(user_id: u32, password: &str) -> AppResult<()> {
+pub async fn update_user_password(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    user_id: u32,
+    password: &str,
+) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.users
+        UPDATE users
         SET password = ?, updatedAt = ?
         WHERE id = ?
         "#,
@@ -317,7 +387,29 @@ pub async fn update_user_password(user_id: u32, password: &str) -> AppResult<()>
         now,
         user_id
     )
-    .execute(get_db_pool().await)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// 登录时重新哈希旧密码，密码没有变化，不修改用作版本号的 updatedAt
+/// old_password 为校验时读取的哈希，期间密码被修改过时不更新
+pub async fn rehash_user_password(
+    user_id: u32,
+    old_password: &str,
+    password: &str,
+) -> AppResult<()> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password = ?
+        WHERE id = ? AND password = ?
+        "#,
+        password,
+        user_id,
+        old_password
+    )
+    .execute(get_yqwork_pool().await)
     .await?;
     Ok(())
 }
@@ -326,14 +418,14 @@ pub async fn update_user_last_login(user_id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.users
+        UPDATE users
         SET lastLogin = ?
         WHERE id = ?
         "#,
         now,
         user_id
     )
-    .execute(get_db_pool().await)
+    .execute(get_yqwork_pool().await)
     .await?;
     Ok(())
 }
@@ -1,7 +1,10 @@
 use anyhow::anyhow;
 
-use super::get_db_pool;
-use crate::{result::AppResult, utils};
+use super::{ensure_version_matched, get_yqwork_pool};
+use crate::{
+    result::{AppError, AppResult},
+    utils,
+};
 
 #[derive(serde::Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -11,6 +14,7 @@ pub struct WorkHour {
     pub end_time: chrono::NaiveDateTime,
     pub status: WorkHourStatus,
     pub comment: Option<String>,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -58,8 +62,8 @@ impl serde::Serialize for WorkHourStatus {
 pub async fn get_work_hour_list(page: u32, page_size: u32) -> AppResult<(u32, Vec<WorkHour>)> {
     let res = sqlx::query!(
         r#"
-        SELECT id, name, endTime, status, comment
-        FROM yqwork_new.work_hours
+        SELECT id, name, endTime, status, comment, updatedAt
+        FROM work_hours
         WHERE deletedAt IS NULL
         ORDER BY id DESC
         LIMIT ? OFFSET ?
@@ -67,7 +71,7 @@ pub async fn get_work_hour_list(page: u32, page_size: u32) -> AppResult<(u32, Ve
         page_size,
         (page - 1) * page_size,
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_yqwork_pool().await)
     .await?
     .into_iter()
     .map(|r| WorkHour {
@@ -76,16 +80,17 @@ pub async fn get_work_hour_list(page: u32, page_size: u32) -> AppResult<(u32, Ve
         end_time: r.endTime,
         status: WorkHourStatus::from(r.status),
         comment: r.comment,
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
 
     let total: i64 = sqlx::query_scalar!(
         r#"
-        SELECT COUNT(*) as count FROM yqwork_new.work_hours
+        SELECT COUNT(*) as count FROM work_hours
         WHERE deletedAt IS NULL
         "#
     )
-    .fetch_one(get_db_pool().await)
+    .fetch_one(get_yqwork_pool().await)
     .await?;
 
     Ok((total as u32, res))
@@ -94,13 +99,13 @@ pub async fn get_work_hour_list(page: u32, page_size: u32) -> AppResult<(u32, Ve
 pub async fn get_work_hour(id: u32) -> AppResult<Option<WorkHour>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, name, endTime, status, comment
-        FROM yqwork_new.work_hours
+        SELECT id, name, endTime, status, comment, updatedAt
+        FROM work_hours
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| WorkHour {
         id: r.id,
@@ -108,6 +113,7 @@ pub async fn get_work_hour(id: u32) -> AppResult<Option<WorkHour>> {
         end_time: r.endTime,
         status: WorkHourStatus::from(r.status),
         comment: r.comment,
+        updated_at: r.updatedAt,
     });
     Ok(res)
 }
@@ -121,7 +127,7 @@ pub async fn add_work_hour(
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO yqwork_new.work_hours (name, endTime, status, comment, createdAt, updatedAt)
+        INSERT INTO work_hours (name, endTime, status, comment, createdAt, updatedAt)
         VALUES (?, ?, ?, ?, ?, ?)
         "#,
         name,
@@ -131,49 +137,52 @@ pub async fn add_work_hour(
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_yqwork_pool().await)
     .await?;
     Ok(res.last_insert_id() as u32)
 }
 
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
 pub async fn update_work_hour(
     work_hour_id: u32,
     name: &str,
     end_time: &chrono::NaiveDateTime,
     status: WorkHourStatus,
     comment: Option<&str>,
+    version: chrono::NaiveDateTime,
 ) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE yqwork_new.work_hours
+        UPDATE work_hours
         SET name = ?, endTime = ?, status = ?, comment = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         name,
         end_time,
         u32::from(status),
         comment,
         now,
-        work_hour_id
+        work_hour_id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(get_yqwork_pool().await)
     .await?;
-    Ok(())
+    ensure_version_matched(&res)
 }
 
 pub async fn delete_work_hour(work_hour_id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE yqwork_new.work_hours
+        UPDATE work_hours
         SET deletedAt = ?
         WHERE id = ? AND deletedAt IS NULL
         "#,
         now,
         work_hour_id
     )
-    .execute(get_db_pool().await)
+    .execute(get_yqwork_pool().await)
     .await?;
     Ok(())
 }
@@ -187,6 +196,7 @@ pub struct WorkHourRecord {
     pub work_descs: Vec<WorkDesc>,
     pub comment: Option<String>,
     pub status: WorkHourRecordStatus,
+    pub updated_at: chrono::NaiveDateTime,
 }
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct WorkDesc {
@@ -250,14 +260,14 @@ pub async fn get_work_hour_record(
 ) -> AppResult<Option<WorkHourRecord>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, workHourId, userId, workDescs, includes, comment, status
-        FROM yqwork_new.work_hours_records
+        SELECT id, workHourId, userId, workDescs, includes, comment, status, updatedAt
+        FROM work_hours_records
         WHERE workHourId = ? AND userId = ? AND deletedAt IS NULL
         "#,
         work_hour_id,
         user_id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| WorkHourRecord {
         id: r.id,
@@ -266,6 +276,7 @@ pub async fn get_work_hour_record(
         work_descs: serde_json::from_str::<Vec<WorkDesc>>(&r.workDescs).unwrap_or_default(),
         comment: r.comment,
         status: WorkHourRecordStatus::from(r.status),
+        updated_at: r.updatedAt,
     });
     Ok(res)
 }
@@ -273,13 +284,13 @@ pub async fn get_work_hour_record(
 pub async fn get_work_hour_record_by_id(id: u32) -> AppResult<Option<WorkHourRecord>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, workHourId, userId, workDescs, includes, comment, status
-        FROM yqwork_new.work_hours_records
+        SELECT id, workHourId, userId, workDescs, includes, comment, status, updatedAt
+        FROM work_hours_records
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| WorkHourRecord {
         id: r.id,
@@ -288,6 +299,7 @@ pub async fn get_work_hour_record_by_id(id: u32) -> AppResult<Option<WorkHourRec
         work_descs: serde_json::from_str::<Vec<WorkDesc>>(&r.workDescs).unwrap_or_default(),
         comment: r.comment,
         status: WorkHourRecordStatus::from(r.status),
+        updated_at: r.updatedAt,
     });
     Ok(res)
 }
@@ -297,12 +309,12 @@ pub async fn get_work_hour_record_includes(id: u32) -> AppResult<Vec<WorkInclude
     let res = sqlx::query!(
         r#"
         SELECT includes
-        FROM yqwork_new.work_hours_records
+        FROM work_hours_records
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id
     )
-    .fetch_one(get_db_pool().await)
+    .fetch_one(get_yqwork_pool().await)
     .await?
     .includes;
     let includes = if let Some(inc) = res {
@@ -319,14 +331,14 @@ pub async fn get_work_hour_record_includes(id: u32) -> AppResult<Vec<WorkInclude
 pub async fn get_work_hour_record_list(work_hour_id: u32) -> AppResult<Vec<WorkHourRecord>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, workHourId, userId, workDescs, includes, comment, status
-        FROM yqwork_new.work_hours_records
+        SELECT id, workHourId, userId, workDescs, includes, comment, status, updatedAt
+        FROM work_hours_records
         WHERE workHourId = ? AND deletedAt IS NULL AND status >= 2
         ORDER BY status ASC, id DESC
         "#,
         work_hour_id,
     )
-    .fetch_all(get_db_pool().await)
+    .fetch_all(get_yqwork_pool().await)
     .await?
     .into_iter()
     .map(|r| WorkHourRecord {
@@ -336,6 +348,7 @@ pub async fn get_work_hour_record_list(work_hour_id: u32) -> AppResult<Vec<WorkH
         work_descs: serde_json::from_str::<Vec<WorkDesc>>(&r.workDescs).unwrap_or_default(),
         comment: r.comment,
         status: WorkHourRecordStatus::from(r.status),
+        updated_at: r.updatedAt,
     })
     .collect::<Vec<_>>();
     Ok(res)
@@ -348,22 +361,23 @@ pub async fn get_work_hour_record_department_list(
 ) -> AppResult<Vec<WorkHourRecord>> {
     let res = sqlx::query!(
         r#"
-        SELECT whr.id, whr.workHourId, whr.userId, whr.workDescs, whr.includes, whr.comment, whr.status
-        FROM yqwork_new.work_hours_records whr
-        INNER JOIN yqwork_new.users u
+        SELECT whr.id, whr.workHourId, whr.userId, whr.workDescs, whr.includes, whr.comment, whr.status, whr.updatedAt
+        FROM work_hours_records whr
+        INNER JOIN users u
         ON whr.userId = u.id
         WHERE whr.workHourId = ? AND u.departmentId = ? AND whr.deletedAt IS NULL AND whr.status >= 1
         ORDER BY whr.status ASC, whr.id DESC
         "#,
         work_hour_id,
         department_id,
-    ).fetch_all(get_db_pool().await).await?.into_iter().map(|r| WorkHourRecord {
+    ).fetch_all(get_yqwork_pool().await).await?.into_iter().map(|r| WorkHourRecord {
         id: r.id,
         work_hour_id: r.workHourId,
         user_id: r.userId,
         work_descs: serde_json::from_str::<Vec<WorkDesc>>(&r.workDescs).unwrap_or_default(),
         comment: r.comment,
         status: WorkHourRecordStatus::from(r.status),
+        updated_at: r.updatedAt,
     }).collect::<Vec<_>>();
     Ok(res)
 }
@@ -374,14 +388,14 @@ pub async fn get_my_work_hour_record(
 ) -> AppResult<Option<WorkHourRecord>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, workHourId, userId, workDescs, includes, comment, status
-        FROM yqwork_new.work_hours_records
+        SELECT id, workHourId, userId, workDescs, includes, comment, status, updatedAt
+        FROM work_hours_records
         WHERE workHourId = ? AND userId = ? AND deletedAt IS NULL
         "#,
         work_hour_id,
         user_id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_yqwork_pool().await)
     .await?
     .map(|r| WorkHourRecord {
         id: r.id,
@@ -390,11 +404,14 @@ pub async fn get_my_work_hour_record(
         work_descs: serde_json::from_str::<Vec<WorkDesc>>(&r.workDescs).unwrap_or_default(),
         comment: r.comment,
         status: WorkHourRecordStatus::from(r.status),
+        updated_at: r.updatedAt,
     });
     Ok(res)
 }
 
 /// 如果对应的 user_id 和 work_hour_id 的记录已存在，则更新记录，否则新增记录
+/// version 为读取时记录的 updatedAt，读取时记录不存在则为 None
+/// 记录在读取之后被修改、新增或删除时返回 Conflict
 pub async fn update_work_hour_record(
     work_hour_id: u32,
     user_id: u32,
@@ -402,57 +419,64 @@ pub async fn update_work_hour_record(
     includes: Option<&Vec<WorkInclude>>,
     comment: Option<&str>,
     status: WorkHourRecordStatus,
+    version: Option<chrono::NaiveDateTime>,
 ) -> AppResult<u32> {
     let now = utils::now_time();
-    let res = if let Some(id) = sqlx::query_scalar!(
+    let existing = sqlx::query_scalar!(
         r#"
         SELECT id
-        FROM yqwork_new.work_hours_records
+        FROM work_hours_records
         WHERE workHourId = ? AND userId = ? AND deletedAt IS NULL
         "#,
         work_hour_id,
         user_id
     )
-    .fetch_optional(get_db_pool().await)
-    .await?
-    {
-        sqlx::query!(
-            r#"
-            UPDATE yqwork_new.work_hours_records
-            SET workDescs = ?, includes = ?, comment = ?, status = ?, updatedAt = ?
-            WHERE id = ? AND deletedAt IS NULL
-            "#,
-            serde_json::to_string(work_descs)
-                .map_err(|err| anyhow!("更新工时记录时失败：序列化工时明细错误 {:?}", err))?,
-            includes
-                .as_ref()
-                .map(|inc| serde_json::to_string(inc))
-                .transpose()
-                .map_err(|err| anyhow!("更新工时记录时失败：序列化包含错误 {:?}", err))?,
-            comment,
-            u32::from(status),
-            now,
+    .fetch_optional(get_yqwork_pool().await)
+    .await?;
+    let res = match (existing, version) {
+        (Some(id), Some(version)) => {
+            let res = sqlx::query!(
+                r#"
+                UPDATE work_hours_records
+                SET workDescs = ?, includes = ?, comment = ?, status = ?, updatedAt = ?
+                WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
+                "#,
+                serde_json::to_string(work_descs)
+                    .map_err(|err| anyhow!("更新工时记录时失败：序列化工时明细错误 {:?}", err))?,
+                includes
+                    .as_ref()
+                    .map(|inc| serde_json::to_string(inc))
+                    .transpose()
+                    .map_err(|err| anyhow!("更新工时记录时失败：序列化包含错误 {:?}", err))?,
+                comment,
+                u32::from(status),
+                now,
+                id,
+                version
+            )
+            .execute(get_yqwork_pool().await)
+            .await?;
+            ensure_version_matched(&res)?;
             id
-        )
-        .execute(get_db_pool().await)
-        .await?;
-        id
-    } else {
-        let res = sqlx::query!(
-            r#"
-            INSERT INTO yqwork_new.work_hours_records (workHourId, userId, workDescs, includes, comment, status, createdAt, updatedAt)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-            "#,
-            work_hour_id,
-            user_id,
-            serde_json::to_string(work_descs).map_err(|err| anyhow!("新增工时记录时失败：序列化工时明细错误 {:?}", err))?,
-            includes.as_ref().map(|inc| serde_json::to_string(inc)).transpose().map_err(|err| anyhow!("新增工时记录时失败：序列化包含错误 {:?}", err))?,
-            comment,
-            u32::from(status),
-            now,
-            now
-        ).execute(get_db_pool().await).await?;
-        res.last_insert_id() as u32
+        }
+        (None, None) => {
+            let res = sqlx::query!(
+                r#"
+                INSERT INTO work_hours_records (workHourId, userId, workDescs, includes, comment, status, createdAt, updatedAt)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                work_hour_id,
+                user_id,
+                serde_json::to_string(work_descs).map_err(|err| anyhow!("新增工时记录时失败：序列化工时明细错误 {:?}", err))?,
+                includes.as_ref().map(|inc| serde_json::to_string(inc)).transpose().map_err(|err| anyhow!("新增工时记录时失败：序列化包含错误 {:?}", err))?,
+                comment,
+                u32::from(status),
+                now,
+                now
+            ).execute(get_yqwork_pool().await).await?;
+            res.last_insert_id() as u32
+        }
+        _ => return Err(AppError::Conflict),
     };
     Ok(res)
 }
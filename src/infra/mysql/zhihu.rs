@@ -1,12 +1,14 @@
 use sqlx::Row;
 
-use super::get_db_pool;
+use super::{ensure_version_matched, get_weihuda_pool};
 use crate::{result::AppResult, utils};
 
 #[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Zhihu {
     pub id: u32,
     pub info: ZhihuBasicInfo,
+    pub updated_at: chrono::NaiveDateTime,
 }
 #[derive(Debug, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -97,15 +99,15 @@ pub async fn get_zhihu_list(
 ) -> AppResult<(u32, Vec<Zhihu>)> {
     let mut main_query = sqlx::QueryBuilder::new(
         r#"
-        SELECT id, title, content, tags, cover, status, stuId, createdAt, top, typ
-        FROM weihuda_new.zhihus
+        SELECT id, title, content, tags, cover, status, stuId, createdAt, top, typ, updatedAt
+        FROM zhihus
         WHERE deletedAt IS NULL
         "#,
     );
     let mut count_query = sqlx::QueryBuilder::new(
         r#"
         SELECT COUNT(*) AS count
-        FROM weihuda_new.zhihus
+        FROM zhihus
         WHERE deletedAt IS NULL
         "#,
     );
@@ -151,7 +153,7 @@ pub async fn get_zhihu_list(
 
     let res = main_query
         .build()
-        .fetch_all(get_db_pool().await)
+        .fetch_all(get_weihuda_pool().await)
         .await?
         .into_iter()
         .map(|r| Zhihu {
@@ -167,11 +169,12 @@ pub async fn get_zhihu_list(
                 stu_id: r.get("stuId"),
                 top: r.get::<u32, _>("top") != 0,
             },
+            updated_at: r.get("updatedAt"),
         })
         .collect::<Vec<_>>();
     let count: i64 = count_query
         .build_query_scalar()
-        .fetch_one(get_db_pool().await)
+        .fetch_one(get_weihuda_pool().await)
         .await?;
 
     Ok((count as u32, res))
@@ -180,13 +183,13 @@ pub async fn get_zhihu_list(
 pub async fn get_zhihu(id: u32) -> AppResult<Option<Zhihu>> {
     let res = sqlx::query!(
         r#"
-        SELECT id, title, content, tags, cover, status, stuId, createdAt, top, typ
-        FROM weihuda_new.zhihus
+        SELECT id, title, content, tags, cover, status, stuId, createdAt, top, typ, updatedAt
+        FROM zhihus
         WHERE id = ? AND deletedAt IS NULL
         "#,
         id
     )
-    .fetch_optional(get_db_pool().await)
+    .fetch_optional(get_weihuda_pool().await)
     .await?
     .map(|r| Zhihu {
         id: r.id,
@@ -201,6 +204,7 @@ pub async fn get_zhihu(id: u32) -> AppResult<Option<Zhihu>> {
             top: r.top != 0,
             created_at: r.createdAt,
         },
+        updated_at: r.updatedAt,
     });
 
     Ok(res)
@@ -210,7 +214,7 @@ pub async fn add_zhihu(info: &ZhihuBasicInfo) -> AppResult<u32> {
     let now = utils::now_time();
     let res = sqlx::query!(
         r#"
-        INSERT INTO weihuda_new.zhihus (title, content, tags, cover, status, stuId, top, typ, createdAt, updatedAt)
+        INSERT INTO zhihus (title, content, tags, cover, status, stuId, top, typ, createdAt, updatedAt)
         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         info.title,
@@ -224,19 +228,24 @@ pub async fn add_zhihu(info: &ZhihuBasicInfo) -> AppResult<u32> {
         now,
         now
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
 
     Ok(res.last_insert_id() as u32)
 }
 
-pub async fn update_zhihu(id: u32, info: &ZhihuBasicInfo) -> AppResult<()> {
+/// version 为读取时的 updatedAt，数据已经被修改时返回 Conflict
+pub async fn update_zhihu(
+    id: u32,
+    info: &ZhihuBasicInfo,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
     let now = utils::now_time();
-    sqlx::query!(
+    let res = sqlx::query!(
         r#"
-        UPDATE weihuda_new.zhihus
+        UPDATE zhihus
         SET title = ?, content = ?, tags = ?, cover = ?, status = ?, stuId = ?, top = ?, typ = ?, updatedAt = ?
-        WHERE id = ? AND deletedAt IS NULL
+        WHERE id = ? AND deletedAt IS NULL AND updatedAt = ?
         "#,
         info.title,
         info.content,
@@ -247,26 +256,26 @@ pub async fn update_zhihu(id: u32, info: &ZhihuBasicInfo) -> AppResult<()> {
         info.top as u32,
         <&str>::from(info.typ),
         now,
-        id
+        id,
+        version
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
-
-    Ok(())
+    ensure_version_matched(&res)
 }
 
 pub async fn delete_zhihu(id: u32) -> AppResult<()> {
     let now = utils::now_time();
     sqlx::query!(
         r#"
-        UPDATE weihuda_new.zhihus
+        UPDATE zhihus
         SET deletedAt = ?
         WHERE id = ?
         "#,
         now,
         id
     )
-    .execute(get_db_pool().await)
+    .execute(get_weihuda_pool().await)
     .await?;
 
     Ok(())
@@ -1,5 +1,6 @@
 use crate::config::CFG;
 use salvo::prelude::*;
+mod cli;
 mod config;
 mod infra;
 mod middleware;
@@ -24,9 +25,26 @@ async fn main() {
         .init();
     tracing::info!("📓 Log level: {}", &CFG.log.filter_level);
     tracing::info!("🚀 Yqwork is starting");
+    utils::jwt::init();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args).await {
+            tracing::error!("执行命令失败: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if CFG.permission.sync_catalog {
+        let res = service::qnxg::permission_catalog::sync_catalog().await;
+        if let Err(e) = res {
+            tracing::error!("同步权限目录失败: {}", e);
+        }
+    }
+    service::qnxg::role::spawn_grant_sweeper();
+    // 先构建路由，检查权限声明，再开始监听
+    let routers = router::routers();
     tracing::info!("🔄 Listening on port: {}", &CFG.server.address);
     let listener = TcpListener::new(&CFG.server.address).bind().await;
-    let routers = router::routers();
     let service = Service::new(routers)
         .hoop(middleware::default_middleware)
         .hoop(Logger::new())
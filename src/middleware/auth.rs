@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, Router, async_trait};
+use serde_json::json;
+
+use crate::result::{AppError, AppResult};
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::{service, utils};
+
+/// 路由的访问策略，在注册路由时声明
+#[derive(Debug, Clone)]
+enum Policy {
+    /// 无需登录
+    Public,
+    /// 登录即可访问，由处理函数自行判断更细的权限
+    Login,
+    /// 账号自身的操作，例如修改密码、管理会话和 token，不能通过模拟登录或 API token 访问
+    Account,
+    /// 需要拥有指定的权限
+    Permission(String),
+    /// 需要在至少一个部门内拥有指定的权限，由处理函数判断具体的部门
+    DepartmentPermission(String),
+}
+
+#[async_trait]
+impl Handler for Policy {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        // 越靠近处理函数的声明越晚执行，会覆盖外层的声明
+        depot.inject(self.clone());
+    }
+}
+
+/// 无需登录即可访问的路由
+pub fn public() -> Router {
+    Router::new().hoop(Policy::Public)
+}
+
+/// 登录即可访问的路由
+pub fn login_required() -> Router {
+    Router::new().hoop(Policy::Login)
+}
+
+/// 只有本人正常登录时才能访问的路由
+pub fn account() -> Router {
+    Router::new().hoop(Policy::Account)
+}
+
+/// 路由检查的权限必须在权限目录中声明，避免拼写错误
+/// 路由在启动时构建，未声明的权限会直接导致启动失败
+fn declared(permission: impl Into<String>) -> String {
+    let permission = permission.into();
+    assert!(
+        service::qnxg::permission_catalog::contains(&permission),
+        "权限 {} 没有在权限目录中声明",
+        permission
+    );
+    permission
+}
+
+/// 需要拥有指定权限才能访问的路由
+pub fn require(permission: impl Into<String>) -> Router {
+    Router::new().hoop(Policy::Permission(declared(permission)))
+}
+
+/// 需要在某个部门内拥有指定权限才能访问的路由，权限可以来自部门角色
+pub fn require_in_department(permission: impl Into<String>) -> Router {
+    Router::new().hoop(Policy::DepartmentPermission(declared(permission)))
+}
+
+async fn authorize(req: &mut Request, depot: &mut Depot) -> AppResult<()> {
+    // 没有声明访问策略的路由一律拒绝访问
+    let Ok(policy) = depot.obtain::<Policy>().cloned() else {
+        tracing::warn!("路由 {} 没有声明访问策略", req.uri().path());
+        return Err(AppError::PermissionDenied);
+    };
+    if let Policy::Public = policy {
+        return Ok(());
+    }
+    let auth = utils::auth::authenticate(req).await?;
+    let mut permission = service::qnxg::user::get_user_permission(auth.user.id).await?;
+    if let Some(api_token) = &auth.api_token {
+        // API token 只能访问需要权限的路由，避免用来修改密码、创建新的 token 等
+        if !matches!(
+            policy,
+            Policy::Permission(_) | Policy::DepartmentPermission(_)
+        ) {
+            return Err(AppError::PermissionDenied);
+        }
+        permission = permission.restrict(api_token.scopes.clone());
+    }
+    if matches!(policy, Policy::Account) && auth.impersonator.is_some() {
+        return Err(AppError::PermissionDenied);
+    }
+    match &policy {
+        Policy::Permission(required) if !permission.has(required) => {
+            return Err(AppError::PermissionDenied);
+        }
+        Policy::DepartmentPermission(required)
+            if !service::qnxg::permission::has_in_any_department(
+                &auth.user,
+                &permission,
+                required,
+            )
+            .await? =>
+        {
+            return Err(AppError::PermissionDenied);
+        }
+        _ => {}
+    }
+    depot.inject(auth);
+    depot.inject(permission);
+    Ok(())
+}
+
+/// 模拟登录时记录每一个请求
+async fn record_impersonated_request(req: &Request, depot: &Depot) {
+    let Ok(auth) = utils::auth::current_auth(depot) else {
+        return;
+    };
+    if auth.impersonator.is_none() {
+        return;
+    }
+    let entry = AuditEntry::new("request", "impersonation", auth.user.id).after(&json!({
+        "method": req.method().as_str(),
+        "path": req.uri().path(),
+    }));
+    service::qnxg::audit_log::record(auth, entry).await;
+}
+
+/// 在处理函数执行前检查访问策略
+struct Guarded {
+    inner: Arc<dyn Handler>,
+}
+
+#[async_trait]
+impl Handler for Guarded {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        match authorize(req, depot).await {
+            Ok(()) => {
+                record_impersonated_request(req, depot).await;
+                self.inner.handle(req, depot, res, ctrl).await
+            }
+            Err(err) => {
+                res.render(err);
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+/// 为路由树中的所有处理函数加上访问策略检查
+pub fn guard(mut router: Router) -> Router {
+    router.goal = router
+        .goal
+        .take()
+        .map(|inner| Arc::new(Guarded { inner }) as Arc<dyn Handler>);
+    let routers = std::mem::take(router.routers_mut());
+    *router.routers_mut() = routers.into_iter().map(guard).collect();
+    router
+}
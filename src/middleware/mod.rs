@@ -2,6 +2,8 @@ mod cors;
 mod default;
 mod timeout;
 
+pub mod auth;
+
 pub use cors::cors_middleware;
 pub use default::default_middleware;
 pub use timeout::timeout_middleware;
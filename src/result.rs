@@ -13,6 +13,10 @@ pub enum AppError {
     PermissionDenied,
     #[error("没有登录")]
     Unauthorized,
+    #[error("数据已被修改")]
+    Conflict,
+    #[error("{0}")]
+    LoginBlocked(String),
     #[error("数据库错误")]
     DatabaseError(#[from] sqlx::Error),
     #[error("请求超时")]
@@ -78,6 +82,23 @@ impl salvo::Scribe for AppError {
                     "msg": "未登录"
                 })),
             ),
+            // 和未登录一样需要退出登录，但给出具体原因
+            AppError::LoginBlocked(ref reason) => res.stuff(
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "code": 401,
+                    "data": null,
+                    "msg": reason
+                })),
+            ),
+            AppError::Conflict => res.stuff(
+                StatusCode::OK,
+                Json(serde_json::json!({
+                    "code": 409,
+                    "data": null,
+                    "msg": "数据已被其他人修改，请刷新后重试"
+                })),
+            ),
             AppError::TimeoutError => res.stuff(
                 StatusCode::OK,
                 Json(serde_json::json!({
@@ -1,8 +1,22 @@
+use crate::middleware;
+
 mod qnxg;
 mod weihuda;
 
 pub fn routers() -> salvo::Router {
-    salvo::Router::new()
-        .push(qnxg::routers())
-        .push(weihuda::routers())
+    // 所有路由都必须声明访问策略，未声明的一律拒绝访问
+    middleware::auth::guard(
+        salvo::Router::new()
+            .push(qnxg::routers())
+            .push(weihuda::routers()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_routers() {
+        // 路由检查的权限没有在权限目录中声明时构建路由会 panic
+        super::routers();
+    }
 }
@@ -0,0 +1,208 @@
+use anyhow::anyhow;
+use salvo::{handler, macros::Extractible};
+use serde_json::json;
+
+use crate::middleware::auth;
+use crate::result::{AppError, RouterResult};
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::permission_catalog::declared;
+use crate::service::qnxg::user::{UserBasicInfo, UserStatus};
+use crate::{service, utils};
+
+const API_TOKEN_PERMISSION_PREFIX: &str = "system:apiToken";
+const SERVICE_ACCOUNT_PERMISSION_PREFIX: &str = "system:serviceAccount";
+// 撤销其他用户的 token
+const DELETE_API_TOKEN_PERMISSION: &str = declared("system:apiToken:delete");
+
+pub fn routers() -> salvo::Router {
+    salvo::Router::new()
+        .push(
+            salvo::Router::with_path("api-token")
+                .push(auth::account().get(get_api_token_list).post(post_api_token))
+                .push(
+                    salvo::Router::with_path("user/{user_id}")
+                        .push(
+                            auth::require(format!("{}:query", API_TOKEN_PERMISSION_PREFIX))
+                                .get(get_user_api_token_list),
+                        )
+                        // 只能为服务账号创建
+                        .push(
+                            auth::require(format!("{}:add", API_TOKEN_PERMISSION_PREFIX))
+                                .post(post_user_api_token),
+                        ),
+                )
+                // 没有删除权限时只能撤销自己的
+                .push(auth::account().path("{id}").delete(delete_api_token)),
+        )
+        .push(
+            auth::require(format!("{}:add", SERVICE_ACCOUNT_PERMISSION_PREFIX))
+                .path("service-account")
+                .post(post_service_account),
+        )
+}
+
+#[derive(serde::Deserialize, Extractible, Debug)]
+#[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
+struct PostApiTokenReq {
+    name: String,
+    // 权限标识，必须在用户自己的权限之内
+    scopes: Vec<String>,
+    // 为空时永不过期
+    expires_at: Option<chrono::NaiveDateTime>,
+}
+
+#[handler]
+async fn get_api_token_list(depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let res = service::qnxg::api_token::get_user_api_tokens(user.id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn post_api_token(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let PostApiTokenReq {
+        name,
+        scopes,
+        expires_at,
+    } = req.extract().await?;
+    let res =
+        service::qnxg::api_token::create_api_token(user.id, &name, &scopes, expires_at).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("apiToken", res.info.id, &res.info),
+    )
+    .await;
+    Ok(res.into())
+}
+
+#[derive(serde::Deserialize, Extractible, Debug)]
+#[salvo(extract(default_source(from = "param")))]
+struct UserIdReq {
+    user_id: u32,
+}
+
+#[handler]
+async fn get_user_api_token_list(req: &mut salvo::Request) -> RouterResult {
+    let UserIdReq { user_id } = req.extract().await?;
+    let res = service::qnxg::api_token::get_user_api_tokens(user_id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn post_user_api_token(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let UserIdReq { user_id } = req.extract().await?;
+    let PostApiTokenReq {
+        name,
+        scopes,
+        expires_at,
+    } = req.extract().await?;
+    let Some(target) = service::qnxg::user::get_user(user_id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    // 为普通用户创建 token 相当于冒用其身份
+    if !target.service_account {
+        return Err(anyhow!("只能为服务账号创建 API token").into());
+    }
+    let res =
+        service::qnxg::api_token::create_api_token(user_id, &name, &scopes, expires_at).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("apiToken", res.info.id, &res.info),
+    )
+    .await;
+    Ok(res.into())
+}
+
+#[handler]
+async fn delete_api_token(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct DeleteApiTokenReq {
+        id: u32,
+    }
+    let DeleteApiTokenReq { id } = req.extract().await?;
+    let Some(api_token) = service::qnxg::api_token::get_api_token(id).await? else {
+        return Err(anyhow!("API token 不存在").into());
+    };
+    if api_token.user_id != user.id && !permission.has(DELETE_API_TOKEN_PERMISSION) {
+        return Err(AppError::PermissionDenied);
+    }
+    service::qnxg::api_token::revoke_api_token(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("apiToken", id, &api_token),
+    )
+    .await;
+    Ok(().into())
+}
+
+#[handler]
+async fn post_service_account(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
+    struct PostServiceAccountReq {
+        name: String,
+        // 账号标识，和用户的学号共用，不能重复
+        stu_id: String,
+        department_id: u32,
+        role_id: Vec<u32>,
+    }
+    let param: PostServiceAccountReq = req.extract().await?;
+    if service::qnxg::user::get_user_by_stu_id(&param.stu_id)
+        .await?
+        .is_some()
+    {
+        return Err(anyhow!("学号已存在").into());
+    }
+    if !service::qnxg::department::get_department_list()
+        .await?
+        .iter()
+        .any(|v| v.id == param.department_id)
+    {
+        return Err(anyhow!("部门不存在").into());
+    }
+    // 和创建用户一样，非管理员只能创建自己部门的、角色在自己角色之内的服务账号
+    if !permission.is_admin() {
+        let user_roles = service::qnxg::role::get_user_roles(user.id)
+            .await?
+            .into_iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>();
+        if user.info.department_id != param.department_id
+            || !param.role_id.iter().all(|id| user_roles.contains(id))
+        {
+            return Err(AppError::PermissionDenied);
+        }
+    }
+    let info = UserBasicInfo {
+        username: None,
+        name: param.name,
+        stu_id: param.stu_id,
+        email: None,
+        xueyuan: 0,
+        gangwei: None,
+        zaiku: false,
+        qingonggang: false,
+        status: UserStatus::Formal,
+        department_id: param.department_id,
+    };
+    let id = service::qnxg::user::add_service_account(&info, &param.role_id).await?;
+    let new_user = service::qnxg::user::get_user(id)
+        .await?
+        .ok_or(anyhow!("新增服务账号失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create(
+            "user",
+            id,
+            &json!({ "user": new_user, "roleId": param.role_id }),
+        ),
+    )
+    .await;
+    Ok(new_user.into())
+}
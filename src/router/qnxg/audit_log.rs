@@ -0,0 +1,57 @@
+use salvo::{handler, macros::Extractible};
+use serde_json::json;
+
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditLogFilter;
+use crate::{result::RouterResult, service};
+
+const AUDIT_PERMISSION_PREFIX: &str = "system:audit";
+
+pub fn routers() -> salvo::Router {
+    auth::require(format!("{}:query", AUDIT_PERMISSION_PREFIX))
+        .path("audit-log")
+        .get(get_audit_log_list)
+}
+
+#[handler]
+async fn get_audit_log_list(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
+    struct GetAuditLogListReq {
+        page: Option<u32>,
+        page_size: Option<u32>,
+        actor_id: Option<u32>,
+        action: Option<String>,
+        entity_type: Option<String>,
+        entity_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    }
+    let GetAuditLogListReq {
+        page,
+        page_size,
+        actor_id,
+        action,
+        entity_type,
+        entity_id,
+        from,
+        to,
+    } = req.extract().await?;
+    let page = page.unwrap_or(1);
+    let page_size = page_size.unwrap_or(10);
+    let filter = AuditLogFilter {
+        actor_id,
+        action,
+        entity_type,
+        entity_id,
+        from,
+        to,
+    };
+    let (count, rows) =
+        service::qnxg::audit_log::get_audit_log_list(page, page_size, &filter).await?;
+    Ok(json!({
+        "count": count,
+        "rows": rows,
+    })
+    .into())
+}
@@ -1,13 +1,28 @@
 use anyhow::anyhow;
 use salvo::{handler, macros::Extractible};
 
-use crate::{result::RouterResult, service, utils};
+use crate::middleware::auth;
+use crate::service::qnxg::login_attempt::LoginMethod;
+use crate::service::qnxg::session::TokenPair;
+use crate::{
+    result::{AppError, RouterResult},
+    service, utils,
+};
 
 pub fn routers() -> salvo::Router {
     salvo::Router::new()
-        .push(salvo::Router::with_path("login").post(login))
         .push(
-            salvo::Router::with_path("auth_qrcode")
+            auth::public().path("login").post(login).push(
+                salvo::Router::with_path("totp")
+                    .post(login_totp)
+                    .push(salvo::Router::with_path("setup").post(login_totp_setup)),
+            ),
+        )
+        .push(auth::public().path("refresh").post(refresh))
+        .push(auth::account().path("logout").post(logout))
+        .push(
+            auth::public()
+                .path("auth_qrcode")
                 .get(get_auth_qrcode)
                 .push(
                     salvo::Router::with_path("status")
@@ -29,18 +44,155 @@ async fn login(req: &mut salvo::Request) -> RouterResult {
         password: String,
     }
     let LoginReq { username, password } = req.extract().await?;
-    let password = utils::md5_hash(&password);
-    let Some(user) = service::qnxg::user::get_user_by_stu_id(&username).await? else {
-        return Err(anyhow!("用户名或密码错误").into());
+    let client = utils::client_info(req);
+    service::qnxg::login_attempt::ensure_not_locked(&username, &client).await?;
+    // 用户不存在和密码错误同样计入失败次数
+    let user = match service::qnxg::user::get_user_by_stu_id(&username).await? {
+        Some(user) if service::qnxg::user::verify_user_password(user.id, &password).await? => user,
+        found => {
+            service::qnxg::login_attempt::record_failure(
+                &username,
+                found.map(|u| u.id),
+                LoginMethod::Password,
+                &client,
+            )
+            .await?;
+            return Err(anyhow!("用户名或密码错误").into());
+        }
     };
-    let Some(user_password) = service::qnxg::user::get_user_password(user.id).await? else {
-        return Err(anyhow!("用户名或密码错误").into());
+    // 密码正确之后才返回具体原因，避免泄露账号状态
+    if let Err(e) = service::qnxg::user::ensure_can_login(&user) {
+        service::qnxg::login_attempt::record_attempt(
+            &username,
+            Some(user.id),
+            LoginMethod::Password,
+            &client,
+            false,
+        )
+        .await?;
+        return Err(e);
+    }
+    // 需要两步验证时等验证码通过后再记录成功，否则失败计数会被密码登录清零
+    if let Some(challenge) = service::qnxg::totp::challenge(user.id).await? {
+        return Ok(challenge.into());
+    }
+    service::qnxg::login_attempt::record_success(
+        &username,
+        user.id,
+        LoginMethod::Password,
+        &client,
+    )
+    .await?;
+    let tokens = service::qnxg::auth::login(user.id, &client).await?;
+    Ok(tokens.into())
+}
+
+/// 必须启用两步验证但还没有启用时，用登录凭据获取密钥
+#[handler]
+async fn login_totp_setup(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body")))]
+    struct LoginTotpSetupReq {
+        ticket: String,
+    }
+    let LoginTotpSetupReq { ticket } = req.extract().await?;
+    let user_id = utils::auth::parse_totp_ticket(&ticket)?;
+    let Some(user) = service::qnxg::user::get_user(user_id).await? else {
+        return Err(AppError::Unauthorized);
     };
-    if password != user_password {
-        return Err(anyhow!("用户名或密码错误").into());
+    service::qnxg::user::ensure_can_login(&user)?;
+    let setup = service::qnxg::totp::setup(&user).await?;
+    Ok(setup.into())
+}
+
+#[handler]
+async fn login_totp(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body")))]
+    struct LoginTotpReq {
+        ticket: String,
+        // 验证码或恢复码
+        code: String,
     }
-    let token = service::qnxg::auth::login(user.id).await?;
-    Ok(token.into())
+    #[derive(serde::Serialize, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct LoginTotpResp {
+        #[serde(flatten)]
+        tokens: TokenPair,
+        // 首次启用时返回
+        #[serde(skip_serializing_if = "Option::is_none")]
+        recovery_codes: Option<Vec<String>>,
+    }
+    let LoginTotpReq { ticket, code } = req.extract().await?;
+    let user_id = utils::auth::parse_totp_ticket(&ticket)?;
+    let Some(user) = service::qnxg::user::get_user(user_id).await? else {
+        return Err(AppError::Unauthorized);
+    };
+    service::qnxg::user::ensure_can_login(&user)?;
+    let client = utils::client_info(req);
+    service::qnxg::login_attempt::ensure_not_locked(&user.info.stu_id, &client).await?;
+    let recovery_codes = if service::qnxg::totp::get_status(user.id).await?.enabled {
+        if !service::qnxg::totp::verify(&user, &code).await? {
+            service::qnxg::login_attempt::record_failure(
+                &user.info.stu_id,
+                Some(user.id),
+                LoginMethod::Totp,
+                &client,
+            )
+            .await?;
+            return Err(anyhow!("验证码错误").into());
+        }
+        None
+    } else {
+        match service::qnxg::totp::enable(&user, &code).await {
+            Ok(codes) => Some(codes),
+            Err(e) => {
+                service::qnxg::login_attempt::record_failure(
+                    &user.info.stu_id,
+                    Some(user.id),
+                    LoginMethod::Totp,
+                    &client,
+                )
+                .await?;
+                return Err(e);
+            }
+        }
+    };
+    service::qnxg::login_attempt::record_success(
+        &user.info.stu_id,
+        user.id,
+        LoginMethod::Totp,
+        &client,
+    )
+    .await?;
+    let tokens = service::qnxg::auth::login(user.id, &client).await?;
+    Ok(LoginTotpResp {
+        tokens,
+        recovery_codes,
+    }
+    .into())
+}
+
+#[handler]
+async fn refresh(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
+    struct RefreshReq {
+        refresh_token: String,
+    }
+    let RefreshReq { refresh_token } = req.extract().await?;
+    let tokens =
+        service::qnxg::session::refresh_session(&refresh_token, &utils::client_info(req)).await?;
+    Ok(tokens.into())
+}
+
+#[handler]
+async fn logout(depot: &mut salvo::Depot) -> RouterResult {
+    let session_id = utils::auth::current_auth(depot)?
+        .session_id
+        .ok_or(AppError::Unauthorized)?;
+    service::qnxg::session::revoke_session(session_id).await?;
+    Ok(().into())
 }
 
 #[handler]
@@ -70,9 +222,38 @@ async fn get_auth_qrcode_token(req: &mut salvo::Request) -> RouterResult {
     }
     let AuthQrCodeTokenReq { code } = req.extract().await?;
     let stu_id = service::qnxg::auth::get_auth_qrcode_info(&code).await?;
-    let Some(user) = service::qnxg::user::get_user_by_stu_id(&stu_id).await? else {
-        return Err(anyhow!("该用户未被添加到易千工作台").into());
+    let client = utils::client_info(req);
+    // 扫码登录由微生活完成身份验证，失败不计入失败次数
+    let user = match service::qnxg::user::get_user_by_stu_id(&stu_id).await? {
+        Some(user) => user,
+        None => {
+            service::qnxg::login_attempt::record_attempt(
+                &stu_id,
+                None,
+                LoginMethod::QrCode,
+                &client,
+                false,
+            )
+            .await?;
+            return Err(anyhow!("该用户未被添加到易千工作台").into());
+        }
     };
-    let token = service::qnxg::auth::login(user.id).await?;
-    Ok(token.into())
+    if let Err(e) = service::qnxg::user::ensure_can_login(&user) {
+        service::qnxg::login_attempt::record_attempt(
+            &stu_id,
+            Some(user.id),
+            LoginMethod::QrCode,
+            &client,
+            false,
+        )
+        .await?;
+        return Err(e);
+    }
+    if let Some(challenge) = service::qnxg::totp::challenge(user.id).await? {
+        return Ok(challenge.into());
+    }
+    service::qnxg::login_attempt::record_success(&stu_id, user.id, LoginMethod::QrCode, &client)
+        .await?;
+    let tokens = service::qnxg::auth::login(user.id, &client).await?;
+    Ok(tokens.into())
 }
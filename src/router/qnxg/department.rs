@@ -1,8 +1,12 @@
 use anyhow::anyhow;
 use salvo::{handler, macros::Extractible};
+use serde_json::json;
 
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::delete_policy::DeletePolicy;
 use crate::{
-    result::{AppError, RouterResult},
+    result::RouterResult,
     service::{self},
     utils,
 };
@@ -12,12 +16,28 @@ const DEPARTMENT_PERMISSION_PREFIX: &str = "yq:department";
 pub fn routers() -> salvo::Router {
     salvo::Router::new().push(
         salvo::Router::with_path("department")
-            .get(get_department_list)
-            .post(post_department)
+            // 新增和编辑用户时需要选择部门，登录即可查询
+            .push(auth::login_required().get(get_department_list))
+            .push(
+                auth::require(format!("{}:add", DEPARTMENT_PERMISSION_PREFIX))
+                    .post(post_department),
+            )
             .push(
                 salvo::Router::with_path("{id}")
-                    .put(put_department)
-                    .delete(delete_department),
+                    .push(
+                        auth::require(format!("{}:edit", DEPARTMENT_PERMISSION_PREFIX))
+                            .put(put_department),
+                    )
+                    .push(
+                        auth::require(format!("{}:delete", DEPARTMENT_PERMISSION_PREFIX))
+                            .delete(delete_department),
+                    )
+                    // 删除前查看引用情况
+                    .push(
+                        auth::require(format!("{}:delete", DEPARTMENT_PERMISSION_PREFIX))
+                            .path("dependents")
+                            .get(get_department_dependents),
+                    ),
             ),
     )
 }
@@ -29,13 +49,7 @@ async fn get_department_list() -> RouterResult {
 }
 
 #[handler]
-async fn post_department(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", DEPARTMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_department(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body")))]
     struct PostDepartmentReq {
@@ -49,66 +63,100 @@ async fn post_department(req: &mut salvo::Request) -> RouterResult {
         .into_iter()
         .find(|d| d.id == id)
         .ok_or(anyhow!("新增部门失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("department", id, &new_department),
+    )
+    .await;
     Ok(new_department.into())
 }
 
 #[handler]
-async fn put_department(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", DEPARTMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_department(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
-    #[salvo(extract(default_source(from = "body")))]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutDepartmentReq {
         #[salvo(extract(source(from = "param")))]
         id: u32,
         name: String,
         desc: String,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
-    let PutDepartmentReq { id, name, desc } = req.extract().await?;
+    let PutDepartmentReq {
+        id,
+        name,
+        desc,
+        updated_at,
+    } = req.extract().await?;
     // 判断部门是否存在
-    let departments = service::qnxg::department::get_department_list().await?;
-    if !departments.into_iter().any(|d| d.id == id) {
+    let Some(old_department) = service::qnxg::department::get_department_list()
+        .await?
+        .into_iter()
+        .find(|d| d.id == id)
+    else {
         return Err(anyhow!("部门不存在").into());
-    }
+    };
     // 更新部门
-    service::qnxg::department::update_department(id, name.as_str(), desc.as_str()).await?;
+    service::qnxg::department::update_department(id, name.as_str(), desc.as_str(), updated_at)
+        .await?;
     let new_department = service::qnxg::department::get_department_list()
         .await?
         .into_iter()
         .find(|d| d.id == id)
         .ok_or(anyhow!("更新部门失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("department", id, &old_department, &new_department),
+    )
+    .await;
     Ok(new_department.into())
 }
 
 #[handler]
-async fn delete_department(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", DEPARTMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_department_dependents(req: &mut salvo::Request) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
+    struct GetDepartmentDependentsReq {
+        id: u32,
+    }
+    let GetDepartmentDependentsReq { id } = req.extract().await?;
+    let res = service::qnxg::department::get_department_dependents(id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn delete_department(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct DeleteDepartmentReq {
+        #[salvo(extract(source(from = "param")))]
         id: u32,
+        // 部门仍被引用时的处理方式，默认拒绝删除
+        policy: Option<DeletePolicy>,
+        // 转移时用户转移到的部门
+        target_id: Option<u32>,
     }
-    let DeleteDepartmentReq { id } = req.extract().await?;
+    let DeleteDepartmentReq {
+        id,
+        policy,
+        target_id,
+    } = req.extract().await?;
+    let policy = policy.unwrap_or_default();
     // 判断部门是否存在
-    let departments = service::qnxg::department::get_department_list().await?;
-    if !departments.into_iter().any(|d| d.id == id) {
+    let Some(old_department) = service::qnxg::department::get_department_list()
+        .await?
+        .into_iter()
+        .find(|d| d.id == id)
+    else {
         return Err(anyhow!("部门不存在").into());
-    }
-    // 如果部门下有用户，则不能删除
-    let (count, _) = service::qnxg::user::get_user_list(1, 10, None, None, Some(id), None).await?;
-    if count > 0 {
-        return Err(anyhow!("该部门下有用户，无法删除").into());
-    }
-    // 删除部门
-    service::qnxg::department::delete_department(id).await?;
+    };
+    service::qnxg::department::delete_department(id, policy, target_id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("department", id, &old_department)
+            .after(&json!({ "policy": policy, "targetId": target_id })),
+    )
+    .await;
     Ok(().into())
 }
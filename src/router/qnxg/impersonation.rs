@@ -0,0 +1,26 @@
+use salvo::{handler, macros::Extractible};
+
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::impersonation::IMPERSONATE_PERMISSION;
+use crate::{result::RouterResult, service, utils};
+
+pub fn routers() -> salvo::Router {
+    auth::require(IMPERSONATE_PERMISSION)
+        .path("impersonate/{user_id}")
+        .post(post_impersonate)
+}
+
+#[handler]
+async fn post_impersonate(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct PostImpersonateReq {
+        user_id: u32,
+    }
+    let PostImpersonateReq { user_id } = req.extract().await?;
+    let auth = utils::auth::current_auth(depot)?;
+    let res = service::qnxg::impersonation::impersonate(auth, user_id).await?;
+    service::qnxg::audit_log::record(auth, AuditEntry::new("impersonate", "user", user_id)).await;
+    Ok(res.into())
+}
@@ -0,0 +1,128 @@
+use anyhow::anyhow;
+use salvo::{handler, macros::Extractible};
+use serde_json::json;
+
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::login_attempt::LoginAttemptFilter;
+use crate::{result::RouterResult, service, utils};
+
+const LOGIN_ATTEMPT_PERMISSION_PREFIX: &str = "system:loginAttempt";
+const LOGIN_LOCKOUT_PERMISSION_PREFIX: &str = "system:loginLockout";
+
+pub fn routers() -> salvo::Router {
+    salvo::Router::new()
+        // 自己的登录历史
+        .push(
+            auth::login_required()
+                .path("login-history")
+                .get(get_login_history),
+        )
+        .push(
+            auth::require(format!("{}:query", LOGIN_ATTEMPT_PERMISSION_PREFIX))
+                .path("login-attempt")
+                .get(get_login_attempt_list),
+        )
+        .push(
+            salvo::Router::with_path("login-lockout")
+                .push(
+                    auth::require(format!("{}:query", LOGIN_LOCKOUT_PERMISSION_PREFIX))
+                        .get(get_lockout_list),
+                )
+                .push(
+                    auth::require(format!("{}:delete", LOGIN_LOCKOUT_PERMISSION_PREFIX))
+                        .path("{id}")
+                        .delete(delete_lockout),
+                ),
+        )
+}
+
+#[handler]
+async fn get_login_history(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
+    struct GetLoginHistoryReq {
+        page: Option<u32>,
+        page_size: Option<u32>,
+    }
+    let user = utils::auth::current_user(depot)?;
+    let GetLoginHistoryReq { page, page_size } = req.extract().await?;
+    let (count, rows) = service::qnxg::login_attempt::get_user_login_history(
+        user.id,
+        page.unwrap_or(1),
+        page_size.unwrap_or(10),
+    )
+    .await?;
+    Ok(json!({
+        "count": count,
+        "rows": rows,
+    })
+    .into())
+}
+
+#[handler]
+async fn get_login_attempt_list(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
+    struct GetLoginAttemptListReq {
+        page: Option<u32>,
+        page_size: Option<u32>,
+        user_id: Option<u32>,
+        stu_id: Option<String>,
+        // password / totp / qrcode
+        method: Option<String>,
+        ip: Option<String>,
+        success: Option<bool>,
+    }
+    let GetLoginAttemptListReq {
+        page,
+        page_size,
+        user_id,
+        stu_id,
+        method,
+        ip,
+        success,
+    } = req.extract().await?;
+    let page = page.unwrap_or(1);
+    let page_size = page_size.unwrap_or(10);
+    let filter = LoginAttemptFilter {
+        user_id,
+        stu_id,
+        method,
+        ip,
+        success,
+    };
+    let (count, rows) =
+        service::qnxg::login_attempt::get_login_attempt_list(page, page_size, &filter).await?;
+    Ok(json!({
+        "count": count,
+        "rows": rows,
+    })
+    .into())
+}
+
+#[handler]
+async fn get_lockout_list() -> RouterResult {
+    let res = service::qnxg::login_attempt::get_lockout_list().await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn delete_lockout(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct DeleteLockoutReq {
+        id: u32,
+    }
+    let DeleteLockoutReq { id } = req.extract().await?;
+    let Some(old_lockout) = service::qnxg::login_attempt::get_lockout(id).await? else {
+        return Err(anyhow!("锁定记录不存在").into());
+    };
+    service::qnxg::login_attempt::delete_lockout(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("loginLockout", id, &old_lockout),
+    )
+    .await;
+    Ok(().into())
+}
@@ -1,17 +1,33 @@
+mod api_token;
+mod audit_log;
 mod auth;
 mod department;
+mod impersonation;
+mod login_attempt;
+mod password_reset;
 mod permission;
+mod recycle_bin;
 mod role;
+mod session;
 mod statistics;
+mod totp;
 mod user;
 mod work_hour;
 
 pub fn routers() -> salvo::Router {
     salvo::Router::new()
+        .push(api_token::routers())
+        .push(audit_log::routers())
         .push(auth::routers())
         .push(department::routers())
+        .push(impersonation::routers())
+        .push(login_attempt::routers())
+        .push(password_reset::routers())
         .push(permission::routers())
+        .push(recycle_bin::routers())
         .push(role::routers())
+        .push(session::routers())
+        .push(totp::routers())
         .push(user::routers())
         .push(work_hour::routers())
         .push(statistics::routers())
@@ -0,0 +1,39 @@
+use salvo::{handler, macros::Extractible};
+
+use crate::middleware::auth;
+use crate::{result::RouterResult, service, utils};
+
+pub fn routers() -> salvo::Router {
+    auth::public()
+        .path("password-reset")
+        .post(request_password_reset)
+        .push(salvo::Router::with_path("confirm").post(confirm_password_reset))
+}
+
+#[handler]
+async fn request_password_reset(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body")))]
+    struct RequestPasswordResetReq {
+        username: String,
+    }
+    let RequestPasswordResetReq { username } = req.extract().await?;
+    service::qnxg::password_reset::request_reset(&username, &utils::client_info(req)).await?;
+    Ok(().into())
+}
+
+#[handler]
+async fn confirm_password_reset(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
+    struct ConfirmPasswordResetReq {
+        token: String,
+        new_password: String,
+    }
+    let ConfirmPasswordResetReq {
+        token,
+        new_password,
+    } = req.extract().await?;
+    service::qnxg::password_reset::confirm_reset(&token, &new_password).await?;
+    Ok(().into())
+}
@@ -1,41 +1,107 @@
-use crate::utils;
-use crate::{result::RouterResult, service};
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::delete_policy::DeletePolicy;
+use crate::{result::RouterResult, service, utils};
 use anyhow::anyhow;
 use salvo::{handler, macros::Extractible};
+use serde_json::json;
 
 const PERMISSION_PERMISSION_PREFIX: &str = "system:permission";
 
 pub fn routers() -> salvo::Router {
     salvo::Router::with_path("permission")
-        .get(get_permission_list)
-        .post(post_permission)
+        .push(
+            auth::require(format!("{}:query", PERMISSION_PERMISSION_PREFIX))
+                .get(get_permission_list),
+        )
+        .push(auth::require(format!("{}:add", PERMISSION_PERMISSION_PREFIX)).post(post_permission))
+        // 代码中检查的所有权限
+        .push(
+            salvo::Router::with_path("catalog")
+                .push(
+                    auth::require(format!("{}:query", PERMISSION_PERMISSION_PREFIX))
+                        .get(get_catalog),
+                )
+                .push(
+                    auth::require(format!("{}:add", PERMISSION_PERMISSION_PREFIX))
+                        .path("sync")
+                        .post(sync_catalog),
+                ),
+        )
+        .push(
+            auth::require(format!("{}:explain", PERMISSION_PERMISSION_PREFIX))
+                .path("explain")
+                .get(get_explain),
+        )
         .push(
             salvo::Router::with_path("{id}")
-                .put(put_permission)
-                .delete(delete_permission),
+                .push(
+                    auth::require(format!("{}:edit", PERMISSION_PERMISSION_PREFIX))
+                        .put(put_permission),
+                )
+                .push(
+                    auth::require(format!("{}:delete", PERMISSION_PERMISSION_PREFIX))
+                        .delete(delete_permission),
+                )
+                // 删除前查看引用情况
+                .push(
+                    auth::require(format!("{}:delete", PERMISSION_PERMISSION_PREFIX))
+                        .path("dependents")
+                        .get(get_permission_dependents),
+                ),
         )
 }
 
 #[handler]
-async fn get_permission_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", PERMISSION_PERMISSION_PREFIX))
-    {
-        return Err(crate::result::AppError::PermissionDenied);
-    }
+async fn get_permission_list() -> RouterResult {
     let res = service::qnxg::permission::get_permission_list().await?;
     Ok(res.into())
 }
 
 #[handler]
-async fn post_permission(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", PERMISSION_PERMISSION_PREFIX))
-    {
-        return Err(crate::result::AppError::PermissionDenied);
+async fn get_catalog() -> RouterResult {
+    let res = service::qnxg::permission_catalog::get_catalog().await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn sync_catalog(depot: &mut salvo::Depot) -> RouterResult {
+    let res = service::qnxg::permission_catalog::sync_catalog().await?;
+    if !res.added.is_empty() {
+        service::qnxg::audit_log::record(
+            utils::auth::current_auth(depot)?,
+            AuditEntry::batch("sync", "permission").after(&res.added),
+        )
+        .await;
+    }
+    Ok(res.into())
+}
+
+/// 排查某个用户为什么有或者没有某个权限
+#[handler]
+async fn get_explain(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
+    struct GetExplainReq {
+        user_id: u32,
+        permission: String,
+        // 按该 API token 的授权范围判断
+        api_token_id: Option<u32>,
     }
+    let GetExplainReq {
+        user_id,
+        permission,
+        api_token_id,
+    } = req.extract().await?;
+    let Some(user) = service::qnxg::user::get_user(user_id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    let res = service::qnxg::permission::explain_access(&user, &permission, api_token_id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn post_permission(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body")))]
     struct PostPermissionReq {
@@ -53,61 +119,89 @@ async fn post_permission(req: &mut salvo::Request) -> RouterResult {
         .into_iter()
         .find(|p| p.id == res)
         .ok_or(anyhow!("新增权限失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("permission", res, &new_permission),
+    )
+    .await;
     Ok(new_permission.into())
 }
 
 #[handler]
-async fn put_permission(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", PERMISSION_PERMISSION_PREFIX))
-    {
-        return Err(crate::result::AppError::PermissionDenied);
-    }
+async fn put_permission(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
-    #[salvo(extract(default_source(from = "body")))]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutPermissionReq {
         #[salvo(extract(source(from = "param")))]
         id: u32,
         name: String,
         permission: String,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutPermissionReq {
         id,
         name,
         permission,
+        updated_at,
     } = req.extract().await?;
-    let list = service::qnxg::permission::get_permission_list().await?;
-    if !list.iter().any(|p| p.id == id) {
+    let Some(old_permission) = service::qnxg::permission::get_permission_list()
+        .await?
+        .into_iter()
+        .find(|p| p.id == id)
+    else {
         return Err(anyhow!("权限不存在").into());
-    }
-    service::qnxg::permission::update_permission(id, &name, &permission).await?;
+    };
+    service::qnxg::permission::update_permission(id, &name, &permission, updated_at).await?;
     let new_permission = service::qnxg::permission::get_permission_list()
         .await?
         .into_iter()
         .find(|p| p.id == id)
         .ok_or(anyhow!("更新权限失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("permission", id, &old_permission, &new_permission),
+    )
+    .await;
     Ok(new_permission.into())
 }
 
 #[handler]
-async fn delete_permission(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", PERMISSION_PERMISSION_PREFIX))
-    {
-        return Err(crate::result::AppError::PermissionDenied);
-    }
+async fn get_permission_dependents(req: &mut salvo::Request) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
+    struct GetPermissionDependentsReq {
+        id: u32,
+    }
+    let GetPermissionDependentsReq { id } = req.extract().await?;
+    let res = service::qnxg::permission::get_permission_dependents(id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn delete_permission(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query")))]
     struct DeletePermissionReq {
+        #[salvo(extract(source(from = "param")))]
         id: u32,
+        // 权限仍被角色使用时的处理方式，默认拒绝删除
+        policy: Option<DeletePolicy>,
     }
-    let DeletePermissionReq { id } = req.extract().await?;
-    let list = service::qnxg::permission::get_permission_list().await?;
-    if !list.iter().any(|p| p.id == id) {
+    let DeletePermissionReq { id, policy } = req.extract().await?;
+    let policy = policy.unwrap_or_default();
+    let Some(old_permission) = service::qnxg::permission::get_permission_list()
+        .await?
+        .into_iter()
+        .find(|p| p.id == id)
+    else {
         return Err(anyhow!("权限不存在").into());
-    }
-    service::qnxg::permission::delete_permission(id).await?;
+    };
+    service::qnxg::permission::delete_permission(id, policy).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("permission", id, &old_permission).after(&json!({ "policy": policy })),
+    )
+    .await;
     Ok(().into())
 }
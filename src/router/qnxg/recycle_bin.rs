@@ -0,0 +1,104 @@
+use anyhow::anyhow;
+use salvo::{handler, macros::Extractible};
+use serde_json::json;
+
+use crate::middleware::auth;
+use crate::result::AppResult;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::recycle_bin::RecycleEntity;
+use crate::{result::RouterResult, service, utils};
+
+const RECYCLE_BIN_PERMISSION_PREFIX: &str = "system:recycleBin";
+
+pub fn routers() -> salvo::Router {
+    let permission = |action: &str| format!("{}:{}", RECYCLE_BIN_PERMISSION_PREFIX, action);
+    salvo::Router::with_path("recycle-bin")
+        .push(
+            salvo::Router::with_path("purge").push(auth::require(permission("purge")).post(purge)),
+        )
+        .push(
+            salvo::Router::with_path("{entity}")
+                .push(auth::require(permission("query")).get(get_deleted_list))
+                .push(
+                    salvo::Router::with_path("{id}/restore")
+                        .push(auth::require(permission("restore")).post(restore)),
+                ),
+        )
+}
+
+fn parse_entity(name: &str) -> AppResult<RecycleEntity> {
+    RecycleEntity::from_name(name).ok_or(anyhow!("不支持的数据类型: {}", name).into())
+}
+
+#[handler]
+async fn get_deleted_list(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
+    struct GetDeletedListReq {
+        #[salvo(extract(source(from = "param")))]
+        entity: String,
+        page: Option<u32>,
+        page_size: Option<u32>,
+    }
+    let GetDeletedListReq {
+        entity,
+        page,
+        page_size,
+    } = req.extract().await?;
+    let entity = parse_entity(&entity)?;
+    let page = page.unwrap_or(1);
+    let page_size = page_size.unwrap_or(10);
+    let (count, rows) =
+        service::qnxg::recycle_bin::get_deleted_list(entity, page, page_size).await?;
+    Ok(json!({
+        "count": count,
+        "rows": rows,
+    })
+    .into())
+}
+
+#[handler]
+async fn restore(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct RestoreReq {
+        entity: String,
+        id: u32,
+    }
+    let RestoreReq { entity, id } = req.extract().await?;
+    let entity = parse_entity(&entity)?;
+    let item = service::qnxg::recycle_bin::restore(entity, id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("restore", entity.name(), id).before(&item),
+    )
+    .await;
+    Ok(().into())
+}
+
+#[handler]
+async fn purge(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query")))]
+    struct PurgeReq {
+        // 不指定时清理所有类型
+        entity: Option<String>,
+    }
+    let PurgeReq { entity } = req.extract().await?;
+    let entities = match entity {
+        Some(name) => vec![parse_entity(&name)?],
+        None => RecycleEntity::ALL.to_vec(),
+    };
+    let mut purged = serde_json::Map::new();
+    for entity in entities {
+        let count = service::qnxg::recycle_bin::purge(entity).await?;
+        purged.insert(entity.name().to_string(), count.into());
+    }
+    let purged = serde_json::Value::Object(purged);
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::batch("purge", "recycleBin").after(&purged),
+    )
+    .await;
+    Ok(purged.into())
+}
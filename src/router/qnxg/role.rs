@@ -1,67 +1,104 @@
-use crate::result::{AppError, RouterResult};
-use crate::service;
+use crate::middleware::auth;
+use crate::result::{AppResult, RouterResult};
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::delete_policy::DeletePolicy;
 use crate::service::qnxg::permission::PermissionItem;
+use crate::service::qnxg::role::Role;
+use crate::{service, utils};
 use anyhow::anyhow;
 use salvo::handler;
 use salvo::macros::Extractible;
+use serde_json::json;
 
 const ROLE_PERMISSION_PREFIX: &str = "system:role";
 
 pub fn routers() -> salvo::Router {
     salvo::Router::with_path("role")
-        .get(get_role_list)
-        .post(post_role)
+        .push(auth::require(format!("{}:query", ROLE_PERMISSION_PREFIX)).get(get_role_list))
+        .push(auth::require(format!("{}:add", ROLE_PERMISSION_PREFIX)).post(post_role))
         .push(
             salvo::Router::with_path("{id}")
-                .put(put_role)
-                .delete(delete_role),
+                .push(auth::require(format!("{}:edit", ROLE_PERMISSION_PREFIX)).put(put_role))
+                .push(
+                    auth::require(format!("{}:delete", ROLE_PERMISSION_PREFIX)).delete(delete_role),
+                )
+                // 删除前查看引用情况
+                .push(
+                    auth::require(format!("{}:delete", ROLE_PERMISSION_PREFIX))
+                        .path("dependents")
+                        .get(get_role_dependents),
+                ),
         )
 }
 
-#[handler]
-async fn get_role_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(crate::utils::auth::parse_token(req).await?.id)
+/// 角色的父角色和自身直接拥有的权限，用于记录操作日志
+async fn role_snapshot(role: &Role) -> AppResult<serde_json::Value> {
+    let parent_ids = service::qnxg::role::get_role_parents()
         .await?
-        .has(&format!("{}:query", ROLE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+        .into_iter()
+        .filter(|(r, _)| *r == role.id)
+        .map(|(_, p)| p)
+        .collect::<Vec<_>>();
+    let permissions = service::qnxg::role::get_role_own_permission(&[role.id]).await?;
+    Ok(json!({ "role": role, "parentIds": parent_ids, "permissions": permissions.into_inner() }))
+}
+
+#[handler]
+async fn get_role_list() -> RouterResult {
     #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
     struct RoleWithPermission {
         id: u32,
         name: String,
+        parent_ids: Vec<u32>,
+        // 角色自身直接拥有的权限
         permissions: Vec<PermissionItem>,
+        // 从父角色继承的权限，不包括自身已有的
+        inherited_permissions: Vec<PermissionItem>,
     }
     let mut res: Vec<RoleWithPermission> = Vec::new();
     let list = service::qnxg::role::get_role_list().await?;
+    let parents = service::qnxg::role::get_role_parents().await?;
     for role in list {
-        let permissions = service::qnxg::role::get_role_permission(&[role.id]).await?;
+        let permissions = service::qnxg::role::get_role_own_permission(&[role.id])
+            .await?
+            .into_inner();
+        let inherited_permissions = service::qnxg::role::get_role_permission(&[role.id])
+            .await?
+            .into_inner()
+            .into_iter()
+            .filter(|p| !permissions.iter().any(|v| v.id == p.id))
+            .collect();
         res.push(RoleWithPermission {
             id: role.id,
             name: role.name,
-            permissions: permissions.into_inner(),
+            parent_ids: parents
+                .iter()
+                .filter(|(r, _)| *r == role.id)
+                .map(|(_, p)| *p)
+                .collect(),
+            permissions,
+            inherited_permissions,
         });
     }
     Ok(res.into())
 }
 
 #[handler]
-async fn post_role(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(crate::utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", ROLE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_role(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, salvo::macros::Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostRoleReq {
         name: String,
         permission_ids: Vec<u32>,
+        // 继承这些角色的所有权限
+        #[serde(default)]
+        parent_ids: Vec<u32>,
     }
     let PostRoleReq {
         name,
         permission_ids,
+        parent_ids,
     } = req.extract().await?;
     let permission_list = service::qnxg::permission::get_permission_list()
         .await?
@@ -71,23 +108,26 @@ async fn post_role(req: &mut salvo::Request) -> RouterResult {
     if !permission_ids.iter().all(|v| permission_list.contains(v)) {
         return Err(anyhow!("权限不存在").into());
     }
-    let res = service::qnxg::role::add_role(&name, &permission_ids).await?;
+    let res = service::qnxg::role::add_role(&name, &permission_ids, &parent_ids).await?;
     let new_role = service::qnxg::role::get_role_list()
         .await?
         .into_iter()
         .find(|r| r.id == res)
         .ok_or(anyhow!("新增角色失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create(
+            "role",
+            res,
+            &json!({ "role": new_role, "permissionIds": permission_ids, "parentIds": parent_ids }),
+        ),
+    )
+    .await;
     Ok(new_role.into())
 }
 
 #[handler]
-async fn put_role(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(crate::utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", ROLE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_role(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutRoleReq {
@@ -95,43 +135,85 @@ async fn put_role(req: &mut salvo::Request) -> RouterResult {
         id: u32,
         name: String,
         permission_ids: Vec<u32>,
+        // 为 None 说明不更改
+        parent_ids: Option<Vec<u32>>,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutRoleReq {
         id,
         name,
         permission_ids,
+        parent_ids,
+        updated_at,
     } = req.extract().await?;
-    let list = service::qnxg::role::get_role_list().await?;
-    if !list.iter().any(|r| r.id == id) {
+    let Some(old_role) = service::qnxg::role::get_role_list()
+        .await?
+        .into_iter()
+        .find(|r| r.id == id)
+    else {
         return Err(anyhow!("角色不存在").into());
-    }
-    service::qnxg::role::update_role(id, &name, &permission_ids).await?;
+    };
+    let before = role_snapshot(&old_role).await?;
+    service::qnxg::role::update_role(
+        id,
+        &name,
+        &permission_ids,
+        parent_ids.as_deref(),
+        updated_at,
+    )
+    .await?;
     let new_role = service::qnxg::role::get_role_list()
         .await?
         .into_iter()
         .find(|r| r.id == id)
         .ok_or(anyhow!("更新角色失败"))?;
+    let after = role_snapshot(&new_role).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("role", id, &before, &after),
+    )
+    .await;
     Ok(new_role.into())
 }
 
 #[handler]
-async fn delete_role(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(crate::utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", ROLE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_role_dependents(req: &mut salvo::Request) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
+    struct GetRoleDependentsReq {
+        id: u32,
+    }
+    let GetRoleDependentsReq { id } = req.extract().await?;
+    let res = service::qnxg::role::get_role_dependents(id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn delete_role(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "query")))]
     struct DeleteRoleReq {
+        #[salvo(extract(source(from = "param")))]
         id: u32,
+        // 角色仍被引用时的处理方式，默认拒绝删除
+        policy: Option<DeletePolicy>,
     }
-    let DeleteRoleReq { id } = req.extract().await?;
-    let list = service::qnxg::role::get_role_list().await?;
-    if !list.iter().any(|r| r.id == id) {
+    let DeleteRoleReq { id, policy } = req.extract().await?;
+    let policy = policy.unwrap_or_default();
+    let Some(old_role) = service::qnxg::role::get_role_list()
+        .await?
+        .into_iter()
+        .find(|r| r.id == id)
+    else {
         return Err(anyhow!("角色不存在").into());
-    }
-    service::qnxg::role::delete_role(id).await?;
+    };
+    let before = role_snapshot(&old_role).await?;
+    service::qnxg::role::delete_role(id, policy).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("role", id, &before).after(&json!({ "policy": policy })),
+    )
+    .await;
     Ok(().into())
 }
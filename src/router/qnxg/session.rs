@@ -0,0 +1,116 @@
+use anyhow::anyhow;
+use salvo::{handler, macros::Extractible};
+
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::session::Session;
+use crate::{result::RouterResult, service, utils};
+
+const SESSION_PERMISSION_PREFIX: &str = "system:session";
+
+pub fn routers() -> salvo::Router {
+    salvo::Router::with_path("sessions")
+        .push(
+            auth::account()
+                .get(get_session_list)
+                .delete(delete_all_sessions),
+        )
+        .push(
+            salvo::Router::with_path("user").push(
+                salvo::Router::with_path("{user_id}")
+                    .push(
+                        auth::require(format!("{}:query", SESSION_PERMISSION_PREFIX))
+                            .get(get_user_session_list),
+                    )
+                    .push(
+                        auth::require(format!("{}:delete", SESSION_PERMISSION_PREFIX))
+                            .delete(delete_user_sessions),
+                    ),
+            ),
+        )
+        .push(auth::account().path("{id}").delete(delete_session))
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SessionResp {
+    #[serde(flatten)]
+    session: Session,
+    // 是否为发起请求的会话
+    current: bool,
+}
+
+#[handler]
+async fn get_session_list(depot: &mut salvo::Depot) -> RouterResult {
+    let auth = utils::auth::current_auth(depot)?;
+    let res = service::qnxg::session::get_user_sessions(auth.user.id)
+        .await?
+        .into_iter()
+        .map(|session| SessionResp {
+            current: Some(session.id) == auth.session_id,
+            session,
+        })
+        .collect::<Vec<_>>();
+    Ok(res.into())
+}
+
+#[handler]
+async fn delete_all_sessions(depot: &mut salvo::Depot) -> RouterResult {
+    let user_id = utils::auth::current_user(depot)?.id;
+    service::qnxg::session::revoke_user_sessions(user_id, None).await?;
+    Ok(().into())
+}
+
+#[handler]
+async fn delete_session(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user_id = utils::auth::current_user(depot)?.id;
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct DeleteSessionReq {
+        id: u32,
+    }
+    let DeleteSessionReq { id } = req.extract().await?;
+    // 只能撤销自己的会话
+    if !service::qnxg::session::get_user_sessions(user_id)
+        .await?
+        .iter()
+        .any(|s| s.id == id)
+    {
+        return Err(anyhow!("会话不存在").into());
+    }
+    service::qnxg::session::revoke_session(id).await?;
+    Ok(().into())
+}
+
+#[handler]
+async fn get_user_session_list(req: &mut salvo::Request) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct GetUserSessionListReq {
+        user_id: u32,
+    }
+    let GetUserSessionListReq { user_id } = req.extract().await?;
+    let res = service::qnxg::session::get_user_sessions(user_id).await?;
+    Ok(res.into())
+}
+
+/// 管理员强制下线某个用户
+#[handler]
+async fn delete_user_sessions(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct DeleteUserSessionsReq {
+        user_id: u32,
+    }
+    let DeleteUserSessionsReq { user_id } = req.extract().await?;
+    if service::qnxg::user::get_user(user_id).await?.is_none() {
+        return Err(anyhow!("用户不存在").into());
+    }
+    service::qnxg::session::revoke_user_sessions(user_id, None).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("revokeSessions", "user", user_id),
+    )
+    .await;
+    Ok(().into())
+}
@@ -0,0 +1,111 @@
+use anyhow::anyhow;
+use salvo::{handler, macros::Extractible};
+
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::{result::RouterResult, service, utils};
+
+const TOTP_PERMISSION_PREFIX: &str = "system:totp";
+
+pub fn routers() -> salvo::Router {
+    salvo::Router::with_path("totp")
+        .push(auth::account().get(get_totp_status))
+        .push(auth::account().path("setup").post(setup_totp))
+        .push(auth::account().path("enable").post(enable_totp))
+        .push(auth::account().path("disable").post(disable_totp))
+        .push(
+            auth::account()
+                .path("recovery-codes")
+                .post(regenerate_recovery_codes),
+        )
+        // 丢失验证器时由管理员重置
+        .push(
+            auth::require(format!("{}:reset", TOTP_PERMISSION_PREFIX))
+                .path("user/{user_id}")
+                .delete(reset_user_totp),
+        )
+}
+
+#[derive(serde::Deserialize, Extractible, Debug)]
+#[salvo(extract(default_source(from = "body")))]
+struct TotpCodeReq {
+    // 验证码或恢复码
+    code: String,
+}
+
+#[handler]
+async fn get_totp_status(depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let status = service::qnxg::totp::get_status(user.id).await?;
+    Ok(status.into())
+}
+
+#[handler]
+async fn setup_totp(depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let setup = service::qnxg::totp::setup(user).await?;
+    Ok(setup.into())
+}
+
+#[handler]
+async fn enable_totp(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let TotpCodeReq { code } = req.extract().await?;
+    let recovery_codes = service::qnxg::totp::enable(user, &code).await?;
+    Ok(recovery_codes.into())
+}
+
+#[handler]
+async fn disable_totp(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let TotpCodeReq { code } = req.extract().await?;
+    if service::qnxg::totp::is_required(user.id).await? {
+        return Err(anyhow!("当前账号必须启用两步验证").into());
+    }
+    if !service::qnxg::totp::verify(user, &code).await? {
+        return Err(anyhow!("验证码错误").into());
+    }
+    let old_status = service::qnxg::totp::get_status(user.id).await?;
+    service::qnxg::totp::disable(user.id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("disableTotp", "user", user.id).before(&old_status),
+    )
+    .await;
+    Ok(().into())
+}
+
+#[handler]
+async fn regenerate_recovery_codes(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let TotpCodeReq { code } = req.extract().await?;
+    if !service::qnxg::totp::verify(user, &code).await? {
+        return Err(anyhow!("验证码错误").into());
+    }
+    let recovery_codes = service::qnxg::totp::regenerate_recovery_codes(user.id).await?;
+    Ok(recovery_codes.into())
+}
+
+#[handler]
+async fn reset_user_totp(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct ResetUserTotpReq {
+        user_id: u32,
+    }
+    let ResetUserTotpReq { user_id } = req.extract().await?;
+    if service::qnxg::user::get_user(user_id).await?.is_none() {
+        return Err(anyhow!("用户不存在").into());
+    }
+    let old_status = service::qnxg::totp::get_status(user_id).await?;
+    service::qnxg::totp::disable(user_id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("resetTotp", "user", user_id).before(&old_status),
+    )
+    .await;
+    Ok(().into())
+}
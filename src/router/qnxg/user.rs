@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 
-use crate::result::{AppError, RouterResult};
+use crate::middleware::auth;
+use crate::result::{AppError, AppResult, RouterResult};
+use crate::service::qnxg::audit_log::AuditEntry;
 use crate::service::qnxg::permission::PermissionItem;
+use crate::service::qnxg::permission_catalog::declared;
 use crate::service::qnxg::user::{User, UserBasicInfo, UserStatus};
 use crate::{service, utils};
 use anyhow::anyhow;
@@ -9,29 +12,63 @@ use salvo::handler;
 use salvo::macros::Extractible;
 use serde_json::json;
 
-const USER_PERMISSION_PREFIX: &str = "yq:user";
+// 在部门内检查的权限
+const QUERY_USER_PERMISSION: &str = declared("yq:user:query");
+const ADD_USER_PERMISSION: &str = declared("yq:user:add");
+const EDIT_USER_PERMISSION: &str = declared("yq:user:edit");
+const DELETE_USER_PERMISSION: &str = declared("yq:user:delete");
 
 pub fn routers() -> salvo::Router {
     salvo::Router::with_path("user")
-        .get(get_user_list)
-        .post(post_user)
-        .push(salvo::Router::with_path("pwd").put(put_pwd))
-        .push(salvo::Router::with_path("whoami").get(get_whoami))
+        .push(auth::require_in_department(QUERY_USER_PERMISSION).get(get_user_list))
+        .push(auth::require_in_department(ADD_USER_PERMISSION).post(post_user))
+        .push(auth::account().path("pwd").put(put_pwd))
+        .push(auth::login_required().path("whoami").get(get_whoami))
         .push(
             salvo::Router::with_path("{id}")
-                .get(get_user)
-                .put(put_user)
-                .delete(delete_user),
+                .push(auth::require_in_department(QUERY_USER_PERMISSION).get(get_user))
+                // 没有编辑权限时也可以修改自己的部分信息
+                .push(auth::login_required().put(put_user))
+                .push(auth::require_in_department(DELETE_USER_PERMISSION).delete(delete_user))
+                .push(
+                    salvo::Router::with_path("role-grant")
+                        .push(
+                            auth::require_in_department(QUERY_USER_PERMISSION)
+                                .get(get_role_grant_list),
+                        )
+                        .push(
+                            auth::require_in_department(EDIT_USER_PERMISSION).post(post_role_grant),
+                        )
+                        .push(
+                            auth::require_in_department(EDIT_USER_PERMISSION)
+                                .path("{role_id}")
+                                .delete(delete_role_grant),
+                        ),
+                )
+                // 只在某个部门内生效的角色
+                .push(
+                    salvo::Router::with_path("department-role")
+                        .push(
+                            auth::require_in_department(QUERY_USER_PERMISSION)
+                                .get(get_department_role_list),
+                        )
+                        .push(
+                            auth::require_in_department(EDIT_USER_PERMISSION)
+                                .post(post_department_role),
+                        )
+                        .push(
+                            auth::require_in_department(EDIT_USER_PERMISSION)
+                                .path("{role_id}/{department_id}")
+                                .delete(delete_department_role),
+                        ),
+                ),
         )
 }
 
 #[handler]
-async fn get_user_list(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    let permission = service::qnxg::user::get_user_permission(user.id).await?;
-    if !permission.has(&format!("{}:query", USER_PERMISSION_PREFIX)) {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_user_list(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetUserListReq {
@@ -52,33 +89,28 @@ async fn get_user_list(req: &mut salvo::Request) -> RouterResult {
     } = req.extract().await?;
     let page = page.unwrap_or(1);
     let page_size = page_size.unwrap_or(10);
-    // 管理员才能查看所有用户，非管理员只能看看自己所在部门的用户
-    let (count, rows) = if permission.is_admin() {
-        service::qnxg::user::get_user_list(
-            page,
-            page_size,
-            stu_id.as_deref(),
-            name.as_deref(),
-            department_id,
-            status,
-        )
-        .await?
-    } else {
-        let department_id = department_id.unwrap_or(user.info.department_id);
-        if department_id != user.info.department_id {
-            (0, Vec::new())
-        } else {
-            service::qnxg::user::get_user_list(
-                page,
-                page_size,
-                stu_id.as_deref(),
-                name.as_deref(),
-                Some(department_id),
-                status,
-            )
+    // 管理员可以查看所有用户，其他人只能查看有查询权限的部门的用户，包括部门角色生效的部门
+    let department_id =
+        match service::qnxg::permission::departments_with(user, permission, QUERY_USER_PERMISSION)
             .await?
-        }
-    };
+        {
+            None => department_id.map(|d| vec![d]),
+            Some(allowed) => Some(
+                allowed
+                    .into_iter()
+                    .filter(|d| department_id.is_none_or(|id| id == *d))
+                    .collect::<Vec<_>>(),
+            ),
+        };
+    let (count, rows) = service::qnxg::user::get_user_list(
+        page,
+        page_size,
+        stu_id.as_deref(),
+        name.as_deref(),
+        department_id.as_deref(),
+        status,
+    )
+    .await?;
     // 生成一个用户id及其角色列表的 map
     let user_roles_map = {
         let mut map = HashMap::new();
@@ -97,12 +129,9 @@ async fn get_user_list(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn get_user(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    let permission = service::qnxg::user::get_user_permission(user.id).await?;
-    if !permission.has(&format!("{}:query", USER_PERMISSION_PREFIX)) {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_user(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetUserReq {
@@ -112,19 +141,23 @@ async fn get_user(req: &mut salvo::Request) -> RouterResult {
     let Some(user_res) = service::qnxg::user::get_user(id).await? else {
         return Ok(().into());
     };
-    if !permission.is_admin() && user.info.department_id != user_res.info.department_id {
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        QUERY_USER_PERMISSION,
+        user_res.info.department_id,
+    )
+    .await?
+    {
         return Ok(().into());
     }
     Ok(user_res.into())
 }
 
 #[handler]
-async fn post_user(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    let permission = service::qnxg::user::get_user_permission(user.id).await?;
-    if !permission.has(&format!("{}:add", USER_PERMISSION_PREFIX)) {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_user(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostUserReq {
@@ -158,8 +191,15 @@ async fn post_user(req: &mut salvo::Request) -> RouterResult {
     {
         return Err(anyhow!("部门不存在").into());
     }
-    // 只能创建自己部门的用户，非管理员不能创建其他部门的用户
-    if !permission.is_admin() && user.info.department_id != param.department_id {
+    // 只能在有新增权限的部门内创建用户
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        ADD_USER_PERMISSION,
+        param.department_id,
+    )
+    .await?
+    {
         return Err(AppError::PermissionDenied);
     }
     // 创建的用户的角色比如是创建者的子集
@@ -187,13 +227,22 @@ async fn post_user(req: &mut salvo::Request) -> RouterResult {
     let new_user = service::qnxg::user::get_user(res)
         .await?
         .ok_or(anyhow!("新增用户失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create(
+            "user",
+            res,
+            &json!({ "user": new_user, "roleId": param.role_id }),
+        ),
+    )
+    .await;
     Ok(new_user.into())
 }
 
 #[handler]
-async fn put_user(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    let permission = service::qnxg::user::get_user_permission(user.id).await?;
+async fn put_user(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutUserReq {
@@ -221,22 +270,27 @@ async fn put_user(req: &mut salvo::Request) -> RouterResult {
         password: Option<String>,
         // 为 None 说明不更改，普通用户不能更改
         role_id: Option<Vec<u32>>,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let param: PutUserReq = req.extract().await?;
     let status = UserStatus::from(param.status);
-    // 没权限的话只能改自己的
-    if !permission.has(&format!("{}:edit", USER_PERMISSION_PREFIX)) && user.id != param.id {
-        return Err(AppError::PermissionDenied);
-    }
     let Some(res_user) = service::qnxg::user::get_user(param.id).await? else {
         return Err(anyhow!("用户不存在").into());
     };
-    // 非管理员只能改自己部门的用户
-    if !permission.is_admin() && user.info.department_id != res_user.info.department_id {
+    // 需要在用户所在部门内有编辑权限，没权限的话只能改自己的
+    let can_edit = service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        EDIT_USER_PERMISSION,
+        res_user.info.department_id,
+    )
+    .await?;
+    if !can_edit && user.id != param.id {
         return Err(AppError::PermissionDenied);
     }
     // 一些字段普通用户不能改
-    if !permission.has(&format!("{}:edit", USER_PERMISSION_PREFIX))
+    if !can_edit
         && (param.name != res_user.info.name
             || param.stu_id != res_user.info.stu_id
             || param.gangwei != res_user.info.gangwei
@@ -251,8 +305,16 @@ async fn put_user(req: &mut salvo::Request) -> RouterResult {
         return Err(AppError::PermissionDenied);
     }
     if !permission.is_admin() {
-        // 非管理员部门不能变更部门信息
-        if param.department_id != user.info.department_id {
+        // 非管理员只能把用户调到自己有编辑权限的部门
+        if param.department_id != res_user.info.department_id
+            && !service::qnxg::permission::has_in_department(
+                user,
+                permission,
+                EDIT_USER_PERMISSION,
+                param.department_id,
+            )
+            .await?
+        {
             return Err(AppError::PermissionDenied);
         }
         // 非管理员不能把用户的角色改成自己的子集之外
@@ -296,26 +358,38 @@ async fn put_user(req: &mut salvo::Request) -> RouterResult {
         status,
         department_id: param.department_id,
     };
-    service::qnxg::user::update_user(param.id, &info).await?;
-    if let Some(password) = param.password {
-        service::qnxg::user::update_user_password(param.id, &password).await?;
-    }
-    if let Some(role_id) = param.role_id {
-        service::qnxg::role::update_user_roles(param.id, &role_id).await?;
-    }
+    let old_roles = service::qnxg::role::get_user_roles(param.id).await?;
+    service::qnxg::user::update_user(
+        param.id,
+        &info,
+        param.updated_at,
+        param.password.as_deref(),
+        param.role_id.as_deref(),
+    )
+    .await?;
+    let password_changed = param.password.is_some();
     let new_user = service::qnxg::user::get_user(param.id)
         .await?
         .ok_or(anyhow!("更新用户失败"))?;
+    let new_roles = service::qnxg::role::get_user_roles(param.id).await?;
+    // 不记录密码本身，只记录是否修改过
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update(
+            "user",
+            param.id,
+            &json!({ "user": res_user, "roles": old_roles }),
+            &json!({ "user": new_user, "roles": new_roles, "passwordChanged": password_changed }),
+        ),
+    )
+    .await;
     Ok(new_user.into())
 }
 
 #[handler]
-async fn delete_user(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    let permission = service::qnxg::user::get_user_permission(user.id).await?;
-    if !permission.has(&format!("{}:delete", USER_PERMISSION_PREFIX)) {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_user(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteUserReq {
@@ -325,8 +399,15 @@ async fn delete_user(req: &mut salvo::Request) -> RouterResult {
     let Some(res_user) = service::qnxg::user::get_user(id).await? else {
         return Err(anyhow!("用户不存在").into());
     };
-    // 非管理员只能删自己部门的用户
-    if !permission.is_admin() && user.info.department_id != res_user.info.department_id {
+    // 需要在用户所在部门内有删除权限
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        DELETE_USER_PERMISSION,
+        res_user.info.department_id,
+    )
+    .await?
+    {
         return Err(AppError::PermissionDenied);
     }
     // 不能删自己
@@ -334,12 +415,254 @@ async fn delete_user(req: &mut salvo::Request) -> RouterResult {
         return Err(AppError::PermissionDenied);
     }
     service::qnxg::user::delete_user(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("user", id, &res_user),
+    )
+    .await;
     Ok(().into())
 }
 
+/// 非管理员需要在用户所在部门内有编辑权限，且只能授予或移除自己拥有的角色
+/// department_id 为部门角色生效的部门，此时自己在该部门内的部门角色也可以授予
+async fn ensure_can_grant_role(
+    depot: &salvo::Depot,
+    target: &User,
+    role_id: u32,
+    department_id: Option<u32>,
+) -> AppResult<()> {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
+    if permission.is_admin() {
+        return Ok(());
+    }
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        EDIT_USER_PERMISSION,
+        target.info.department_id,
+    )
+    .await?
+    {
+        return Err(AppError::PermissionDenied);
+    }
+    let mut own_roles = service::qnxg::role::get_user_roles(user.id)
+        .await?
+        .into_iter()
+        .map(|r| r.id)
+        .collect::<Vec<_>>();
+    if let Some(department_id) = department_id {
+        own_roles.extend(
+            service::qnxg::role::get_user_department_roles(user.id)
+                .await?
+                .into_iter()
+                .filter(|r| r.department_id == department_id)
+                .map(|r| r.role.id),
+        );
+    }
+    if !own_roles.contains(&role_id) {
+        return Err(AppError::PermissionDenied);
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize, Extractible, Debug)]
+#[salvo(extract(default_source(from = "param")))]
+struct UserIdReq {
+    id: u32,
+}
+
+#[handler]
+async fn get_role_grant_list(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
+    let UserIdReq { id } = req.extract().await?;
+    let Some(res_user) = service::qnxg::user::get_user(id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        QUERY_USER_PERMISSION,
+        res_user.info.department_id,
+    )
+    .await?
+    {
+        return Err(AppError::PermissionDenied);
+    }
+    let res = service::qnxg::role::get_user_role_grants(id).await?;
+    Ok(res.into())
+}
+
 #[handler]
-async fn put_pwd(req: &mut salvo::Request) -> RouterResult {
-    let user_id = utils::auth::parse_token(req).await?.id;
+async fn post_role_grant(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
+    struct PostRoleGrantReq {
+        role_id: u32,
+        // 为空时立即生效
+        valid_from: Option<chrono::NaiveDateTime>,
+        valid_until: chrono::NaiveDateTime,
+    }
+    let UserIdReq { id } = req.extract().await?;
+    let PostRoleGrantReq {
+        role_id,
+        valid_from,
+        valid_until,
+    } = req.extract().await?;
+    let Some(res_user) = service::qnxg::user::get_user(id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    ensure_can_grant_role(depot, &res_user, role_id, None).await?;
+    let before = service::qnxg::role::get_user_role_grants(id).await?;
+    service::qnxg::role::grant_role(id, role_id, valid_from, valid_until).await?;
+    let after = service::qnxg::role::get_user_role_grants(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("grantRole", "user", id)
+            .before(&before)
+            .after(&after),
+    )
+    .await;
+    Ok(().into())
+}
+
+#[handler]
+async fn delete_role_grant(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct DeleteRoleGrantReq {
+        id: u32,
+        role_id: u32,
+    }
+    let DeleteRoleGrantReq { id, role_id } = req.extract().await?;
+    let Some(res_user) = service::qnxg::user::get_user(id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    ensure_can_grant_role(depot, &res_user, role_id, None).await?;
+    let before = service::qnxg::role::get_user_role_grants(id).await?;
+    let Some(grant) = before.iter().find(|g| g.role.id == role_id) else {
+        return Err(anyhow!("用户没有该角色").into());
+    };
+    service::qnxg::role::revoke_role(id, role_id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("revokeRole", "user", id).before(grant),
+    )
+    .await;
+    Ok(().into())
+}
+
+/// 非管理员还需要在角色生效的部门内有编辑权限
+async fn ensure_can_grant_department_role(
+    depot: &salvo::Depot,
+    target: &User,
+    role_id: u32,
+    department_id: u32,
+) -> AppResult<()> {
+    ensure_can_grant_role(depot, target, role_id, Some(department_id)).await?;
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        EDIT_USER_PERMISSION,
+        department_id,
+    )
+    .await?
+    {
+        return Err(AppError::PermissionDenied);
+    }
+    Ok(())
+}
+
+#[handler]
+async fn get_department_role_list(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
+    let UserIdReq { id } = req.extract().await?;
+    let Some(res_user) = service::qnxg::user::get_user(id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        QUERY_USER_PERMISSION,
+        res_user.info.department_id,
+    )
+    .await?
+    {
+        return Err(AppError::PermissionDenied);
+    }
+    let res = service::qnxg::role::get_user_department_roles(id).await?;
+    Ok(res.into())
+}
+
+#[handler]
+async fn post_department_role(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
+    struct PostDepartmentRoleReq {
+        role_id: u32,
+        department_id: u32,
+    }
+    let UserIdReq { id } = req.extract().await?;
+    let PostDepartmentRoleReq {
+        role_id,
+        department_id,
+    } = req.extract().await?;
+    let Some(res_user) = service::qnxg::user::get_user(id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    ensure_can_grant_department_role(depot, &res_user, role_id, department_id).await?;
+    service::qnxg::role::add_user_department_role(id, role_id, department_id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("grantDepartmentRole", "user", id)
+            .after(&json!({ "roleId": role_id, "departmentId": department_id })),
+    )
+    .await;
+    Ok(().into())
+}
+
+#[handler]
+async fn delete_department_role(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
+    #[derive(serde::Deserialize, Extractible, Debug)]
+    #[salvo(extract(default_source(from = "param")))]
+    struct DeleteDepartmentRoleReq {
+        id: u32,
+        role_id: u32,
+        department_id: u32,
+    }
+    let DeleteDepartmentRoleReq {
+        id,
+        role_id,
+        department_id,
+    } = req.extract().await?;
+    let Some(res_user) = service::qnxg::user::get_user(id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    ensure_can_grant_department_role(depot, &res_user, role_id, department_id).await?;
+    service::qnxg::role::delete_user_department_role(id, role_id, department_id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("revokeDepartmentRole", "user", id)
+            .before(&json!({ "roleId": role_id, "departmentId": department_id })),
+    )
+    .await;
+    Ok(().into())
+}
+
+#[handler]
+async fn put_pwd(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let auth = utils::auth::current_auth(depot)?;
+    let user_id = auth.user.id;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutPwdReq {
@@ -353,29 +676,30 @@ async fn put_pwd(req: &mut salvo::Request) -> RouterResult {
     if service::qnxg::user::get_user(user_id).await?.is_none() {
         return Err(AppError::Unauthorized);
     };
-    let Some(pwd) = service::qnxg::user::get_user_password(user_id).await? else {
-        return Err(AppError::Unauthorized);
-    };
-    let old_password = utils::md5_hash(&old_password);
-    if pwd != old_password {
+    if !service::qnxg::user::verify_user_password(user_id, &old_password).await? {
         return Err(anyhow!("旧密码错误").into());
     }
-    service::qnxg::user::update_user_password(user_id, &new_password).await?;
+    // 保留当前会话，其他设备需要重新登录
+    service::qnxg::user::change_user_password(user_id, &new_password, auth.session_id).await?;
     Ok(().into())
 }
 
 #[handler]
-async fn get_whoami(req: &mut salvo::Request) -> RouterResult {
+async fn get_whoami(depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Serialize, Debug)]
     struct GetWhoamiResp {
         user: User,
         permissions: Vec<PermissionItem>,
+        // 模拟登录时为发起模拟的管理员
+        #[serde(skip_serializing_if = "Option::is_none")]
+        impersonator: Option<User>,
     }
-    let user = utils::auth::parse_token(req).await?;
-    let permissions = service::qnxg::user::get_user_permission(user.id).await?;
+    let auth = utils::auth::current_auth(depot)?;
+    let permissions = utils::auth::current_permission(depot)?.clone();
     Ok(GetWhoamiResp {
-        user,
+        user: auth.user.clone(),
         permissions: permissions.into_inner(),
+        impersonator: auth.impersonator.clone(),
     }
     .into())
 }
@@ -1,3 +1,6 @@
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::permission_catalog::declared;
 use crate::service::qnxg::work_hour::{
     WorkDesc, WorkHourRecordStatus, WorkHourStatus, WorkHourTableItem,
 };
@@ -10,47 +13,61 @@ use salvo::{handler, macros::Extractible};
 use serde_json::json;
 
 const WORK_HOUR_PERMISSION_PREFIX: &str = "yq:workHours";
+// 部门负责人审核
+const CHECK_DEPARTMENT_PERMISSION: &str = declared("yq:workHours:checkDepartment");
+// 财务负责人审核和发放
+const GENERATE_TABLE_PERMISSION: &str = declared("yq:workHours:generateTable");
 
 pub fn routers() -> salvo::Router {
+    let permission = |action: &str| format!("{}:{}", WORK_HOUR_PERMISSION_PREFIX, action);
     salvo::Router::new()
         .push(
             salvo::Router::with_path("work-hours")
-                .get(get_work_hour_list)
-                .post(post_work_hour)
-                .push(salvo::Router::with_path("statistics").get(get_work_hour_statistics))
+                .push(auth::require(permission("query")).get(get_work_hour_list))
+                .push(auth::require(permission("add")).post(post_work_hour))
+                .push(
+                    auth::require(permission("generateTable"))
+                        .path("statistics")
+                        .get(get_work_hour_statistics),
+                )
                 .push(
                     salvo::Router::with_path("{id}")
-                        .get(get_work_hour)
-                        .put(put_work_hour)
-                        .delete(delete_work_hour),
+                        .push(auth::require(permission("query")).get(get_work_hour))
+                        .push(auth::require(permission("edit")).put(put_work_hour))
+                        .push(auth::require(permission("delete")).delete(delete_work_hour)),
                 ),
         )
         .push(
             salvo::Router::with_path("work-hours-record")
-                .get(get_work_hour_record_list)
-                .put(put_work_hour_record)
+                .push(auth::require(permission("generateTable")).get(get_work_hour_record_list))
+                // 打回和批准需要根据记录的状态判断权限
+                .push(auth::login_required().put(put_work_hour_record))
                 .push(
-                    salvo::Router::with_path("department")
+                    auth::require_in_department(permission("checkDepartment"))
+                        .path("department")
                         .get(get_work_hour_record_department_list),
                 )
                 .push(
-                    salvo::Router::with_path("my")
+                    auth::require(permission("query"))
+                        .path("my")
                         .get(get_my_work_hour_record)
                         .put(put_my_work_hour_record),
                 )
-                .push(salvo::Router::with_path("save").put(save_work_hour_table))
-                .push(salvo::Router::with_path("one-key").get(one_key)),
+                .push(
+                    auth::require(permission("generateTable"))
+                        .path("save")
+                        .put(save_work_hour_table),
+                )
+                .push(
+                    auth::require(permission("generateTable"))
+                        .path("one-key")
+                        .get(one_key),
+                ),
         )
 }
 
 #[handler]
 async fn get_work_hour_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetWorkHourListReq {
@@ -70,12 +87,6 @@ async fn get_work_hour_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get_work_hour(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetWorkHourReq {
@@ -87,13 +98,7 @@ async fn get_work_hour(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn post_work_hour(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_work_hour(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostWorkHourReq {
@@ -116,17 +121,16 @@ async fn post_work_hour(req: &mut salvo::Request) -> RouterResult {
     let new_work_hour = service::qnxg::work_hour::get_work_hour(res)
         .await?
         .ok_or(anyhow!("新增工时记录失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("workHour", res, &new_work_hour),
+    )
+    .await;
     Ok(new_work_hour.into())
 }
 
 #[handler]
-async fn put_work_hour(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_work_hour(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutWorkHourReq {
@@ -136,6 +140,8 @@ async fn put_work_hour(req: &mut salvo::Request) -> RouterResult {
         end_time: String,
         status: u32,
         comment: Option<String>,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutWorkHourReq {
         id,
@@ -143,50 +149,56 @@ async fn put_work_hour(req: &mut salvo::Request) -> RouterResult {
         end_time,
         status,
         comment,
+        updated_at,
     } = req.extract().await?;
     let status = WorkHourStatus::from(status);
     let end_time = chrono::NaiveDateTime::parse_from_str(&end_time, "%Y-%m-%d %H:%M")
         .map_err(|_| AppError::ParamParseError)?;
-    if service::qnxg::work_hour::get_work_hour(id).await?.is_none() {
+    let Some(old_work_hour) = service::qnxg::work_hour::get_work_hour(id).await? else {
         return Err(anyhow!("工时记录不存在").into());
-    }
-    service::qnxg::work_hour::update_work_hour(id, &name, &end_time, status, comment.as_deref())
-        .await?;
+    };
+    service::qnxg::work_hour::update_work_hour(
+        id,
+        &name,
+        &end_time,
+        status,
+        comment.as_deref(),
+        updated_at,
+    )
+    .await?;
     let new_work_hour = service::qnxg::work_hour::get_work_hour(id)
         .await?
         .ok_or(anyhow!("更新工时记录失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("workHour", id, &old_work_hour, &new_work_hour),
+    )
+    .await;
     Ok(new_work_hour.into())
 }
 
 #[handler]
-async fn delete_work_hour(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_work_hour(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteWorkHourReq {
         id: u32,
     }
     let DeleteWorkHourReq { id } = req.extract().await?;
-    if service::qnxg::work_hour::get_work_hour(id).await?.is_none() {
+    let Some(old_work_hour) = service::qnxg::work_hour::get_work_hour(id).await? else {
         return Err(anyhow!("工时记录不存在").into());
-    }
+    };
     service::qnxg::work_hour::delete_work_hour(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("workHour", id, &old_work_hour),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
 async fn get_work_hour_record_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetWorkHourRecordListReq {
@@ -198,10 +210,10 @@ async fn get_work_hour_record_list(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
+async fn put_work_hour_record(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     // 主要是进行打回和批准
-    let user = utils::auth::parse_token(req).await?;
-    let permission = service::qnxg::user::get_user_permission(user.id).await?;
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutWorkHourRecordReq {
@@ -210,12 +222,15 @@ async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
         status: u32,
         // 打回的时候必须为 Some
         comment: Option<String>,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutWorkHourRecordReq {
         work_hour_id,
         user_id,
         status,
         comment,
+        updated_at,
     } = req.extract().await?;
     let status = WorkHourRecordStatus::from(status);
     let Some(record) =
@@ -223,27 +238,36 @@ async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
     else {
         return Err(anyhow!("工时记录不存在").into());
     };
+    if updated_at != record.info.updated_at {
+        return Err(AppError::Conflict);
+    }
     let Some(target_user) = service::qnxg::user::get_user(user_id).await? else {
         return Err(anyhow!("工时记录不存在").into());
     };
     let Some(work_hour) = service::qnxg::work_hour::get_work_hour(work_hour_id).await? else {
         return Err(anyhow!("工时记录不存在").into());
     };
+    // 是否为该用户所在部门的负责人
+    let is_department_checker = service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        CHECK_DEPARTMENT_PERMISSION,
+        target_user.info.department_id,
+    )
+    .await?;
     match status {
         WorkHourRecordStatus::Unsubmitted => {
             // 打回
             match record.info.status {
                 WorkHourRecordStatus::PendingApproval => {
                     // 需要是部门负责人打回
-                    if !permission.has(&format!("{}:checkDepartment", WORK_HOUR_PERMISSION_PREFIX))
-                        || target_user.info.department_id != user.info.department_id
-                    {
+                    if !is_department_checker {
                         return Err(AppError::PermissionDenied);
                     }
                 }
                 WorkHourRecordStatus::PendingFinance => {
                     // 需要是财务负责人打回
-                    if !permission.has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX)) {
+                    if !permission.has(GENERATE_TABLE_PERMISSION) {
                         return Err(AppError::PermissionDenied);
                     }
                 }
@@ -257,9 +281,7 @@ async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
         }
         WorkHourRecordStatus::PendingFinance => {
             // 部门负责人批准
-            if record.info.status != WorkHourRecordStatus::PendingApproval
-                || !permission.has(&format!("{}:checkDepartment", WORK_HOUR_PERMISSION_PREFIX))
-                || target_user.info.department_id != user.info.department_id
+            if record.info.status != WorkHourRecordStatus::PendingApproval || !is_department_checker
             {
                 return Err(AppError::PermissionDenied);
             }
@@ -272,7 +294,7 @@ async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
         WorkHourRecordStatus::PendingDistribution => {
             // 财务负责人批准
             if record.info.status != WorkHourRecordStatus::PendingFinance
-                || !permission.has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX))
+                || !permission.has(GENERATE_TABLE_PERMISSION)
             {
                 return Err(AppError::PermissionDenied);
             }
@@ -281,7 +303,7 @@ async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
         WorkHourRecordStatus::Closed => {
             // 财务负责人设置已发放
             if record.info.status != WorkHourRecordStatus::PendingDistribution
-                || !permission.has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX))
+                || !permission.has(GENERATE_TABLE_PERMISSION)
             {
                 return Err(AppError::PermissionDenied);
             }
@@ -295,41 +317,60 @@ async fn put_work_hour_record(req: &mut salvo::Request) -> RouterResult {
         service::qnxg::work_hour::get_work_hour_record(work_hour_id, user_id)
             .await?
             .ok_or(anyhow!("更新工时记录失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update(
+            "workHourRecord",
+            format!("{}:{}", work_hour_id, user_id),
+            &record,
+            &new_work_hour_record,
+        ),
+    )
+    .await;
     Ok(new_work_hour_record.into())
 }
 
 #[handler]
-async fn get_work_hour_record_department_list(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:checkDepartment", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_work_hour_record_department_list(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
+    let permission = utils::auth::current_permission(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetWorkHourRecordDepartmentListReq {
         work_hour_id: u32,
+        // 为空时为自己所在的部门
+        department_id: Option<u32>,
     }
-    let GetWorkHourRecordDepartmentListReq { work_hour_id } = req.extract().await?;
-    let res = service::qnxg::work_hour::get_work_hour_record_department_list(
+    let GetWorkHourRecordDepartmentListReq {
         work_hour_id,
-        user.info.department_id,
+        department_id,
+    } = req.extract().await?;
+    let department_id = department_id.unwrap_or(user.info.department_id);
+    if !service::qnxg::permission::has_in_department(
+        user,
+        permission,
+        CHECK_DEPARTMENT_PERMISSION,
+        department_id,
     )
-    .await?;
+    .await?
+    {
+        return Err(AppError::PermissionDenied);
+    }
+    let res =
+        service::qnxg::work_hour::get_work_hour_record_department_list(work_hour_id, department_id)
+            .await?;
     Ok(res.into())
 }
 
 #[handler]
-async fn get_my_work_hour_record(req: &mut salvo::Request) -> RouterResult {
-    let user_id = utils::auth::parse_token(req).await?.id;
-    if !service::qnxg::user::get_user_permission(user_id)
-        .await?
-        .has(&format!("{}:query", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_my_work_hour_record(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
+    let user_id = utils::auth::current_user(depot)?.id;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetMyWorkHourRecordReq {
@@ -341,23 +382,23 @@ async fn get_my_work_hour_record(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn put_my_work_hour_record(req: &mut salvo::Request) -> RouterResult {
-    let user_id = utils::auth::parse_token(req).await?.id;
-    if !service::qnxg::user::get_user_permission(user_id)
-        .await?
-        .has(&format!("{}:query", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_my_work_hour_record(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
+    let user_id = utils::auth::current_user(depot)?.id;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutMyWorkHourRecordReq {
         work_hour_id: u32,
         work_descs: Vec<WorkDesc>,
+        // 获取数据时的 updatedAt，还没有记录时为 None
+        updated_at: Option<chrono::NaiveDateTime>,
     }
     let PutMyWorkHourRecordReq {
         work_hour_id,
         work_descs,
+        updated_at,
     } = req.extract().await?;
     if work_descs.is_empty() {
         return Err(AppError::ParamParseError);
@@ -372,10 +413,22 @@ async fn put_my_work_hour_record(req: &mut salvo::Request) -> RouterResult {
     // 已经提交的就不能改了
     let last_record =
         service::qnxg::work_hour::get_my_work_hour_record(work_hour_id, user_id).await?;
-    if last_record.is_some_and(|r| r.info.status != WorkHourRecordStatus::Unsubmitted) {
+    if last_record
+        .as_ref()
+        .is_some_and(|r| r.info.status != WorkHourRecordStatus::Unsubmitted)
+    {
         return Err(anyhow!("已提交的工时记录不能修改").into());
     }
-    service::qnxg::work_hour::submit_work_hour_record(work_hour_id, user_id, &work_descs).await?;
+    let version = last_record.map(|r| r.info.updated_at);
+    // 已经有记录时必须传回读取时的版本
+    if version.is_some() && updated_at.is_none() {
+        return Err(AppError::ParamParseError);
+    }
+    if updated_at != version {
+        return Err(AppError::Conflict);
+    }
+    service::qnxg::work_hour::submit_work_hour_record(work_hour_id, user_id, &work_descs, version)
+        .await?;
     let new_work_hour_record =
         service::qnxg::work_hour::get_my_work_hour_record(work_hour_id, user_id)
             .await?
@@ -384,13 +437,7 @@ async fn put_my_work_hour_record(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn save_work_hour_table(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn save_work_hour_table(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body")))]
     struct SaveWorkHourTableReq {
@@ -398,17 +445,16 @@ async fn save_work_hour_table(req: &mut salvo::Request) -> RouterResult {
     }
     let SaveWorkHourTableReq { data } = req.extract().await?;
     service::qnxg::work_hour::save_work_hour_table(&data).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::batch("saveTable", "workHourRecord").after(&data),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
 async fn get_work_hour_statistics(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetWorkHourStatisticsReq {
@@ -420,13 +466,7 @@ async fn get_work_hour_statistics(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn one_key(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:generateTable", WORK_HOUR_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn one_key(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct OneKeyReq {
@@ -453,5 +493,10 @@ async fn one_key(req: &mut salvo::Request) -> RouterResult {
             return Err(AppError::ParamParseError);
         }
     }
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("oneKey", "workHour", work_hour_id).after(&json!({ "status": status })),
+    )
+    .await;
     Ok(().into())
 }
@@ -1,4 +1,5 @@
-use crate::result::AppError;
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
 use crate::utils;
 use anyhow::anyhow;
 use salvo::{handler, macros::Extractible};
@@ -9,25 +10,20 @@ use crate::{result::RouterResult, service};
 const ANNOUNCEMENT_PERMISSION_PREFIX: &str = "hdwsh:announcement";
 
 pub fn routers() -> salvo::Router {
+    let permission = |action: &str| format!("{}:{}", ANNOUNCEMENT_PERMISSION_PREFIX, action);
     salvo::Router::with_path("announcement")
-        .get(get_announcement_list)
-        .post(post_announcement)
+        .push(auth::require(permission("query")).get(get_announcement_list))
+        .push(auth::require(permission("add")).post(post_announcement))
         .push(
             salvo::Router::with_path("{id}")
-                .get(get_announcement)
-                .put(put_announcement)
-                .delete(delete_announcement),
+                .push(auth::require(permission("query")).get(get_announcement))
+                .push(auth::require(permission("edit")).put(put_announcement))
+                .push(auth::require(permission("delete")).delete(delete_announcement)),
         )
 }
 
 #[handler]
 async fn get_announcement_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", ANNOUNCEMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetAnnouncementListReq {
@@ -48,12 +44,6 @@ async fn get_announcement_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get_announcement(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", ANNOUNCEMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct GetAnnouncementReq {
@@ -65,13 +55,7 @@ async fn get_announcement(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn post_announcement(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", ANNOUNCEMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_announcement(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostAnnouncementReq {
@@ -87,17 +71,16 @@ async fn post_announcement(req: &mut salvo::Request) -> RouterResult {
     let res =
         service::weihuda::announcement::add_announcement(&title, &content, url.as_deref()).await?;
     let new_announcement = service::weihuda::announcement::get_announcement(res).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("announcement", res, &new_announcement),
+    )
+    .await;
     Ok(new_announcement.into())
 }
 
 #[handler]
-pub async fn put_announcement(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", ANNOUNCEMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+pub async fn put_announcement(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutAnnouncementReq {
@@ -106,44 +89,55 @@ pub async fn put_announcement(req: &mut salvo::Request) -> RouterResult {
         title: String,
         content: String,
         url: Option<String>,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutAnnouncementReq {
         id,
         title,
         content,
         url,
+        updated_at,
     } = req.extract().await?;
-    if service::weihuda::announcement::get_announcement(id)
-        .await?
-        .is_none()
-    {
+    let Some(old_announcement) = service::weihuda::announcement::get_announcement(id).await? else {
         return Err(anyhow!("公告不存在").into());
-    }
-    service::weihuda::announcement::update_announcement(id, &title, &content, url.as_deref())
-        .await?;
+    };
+    service::weihuda::announcement::update_announcement(
+        id,
+        &title,
+        &content,
+        url.as_deref(),
+        updated_at,
+    )
+    .await?;
+    let new_announcement = service::weihuda::announcement::get_announcement(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("announcement", id, &old_announcement, &new_announcement),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
-pub async fn delete_announcement(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", ANNOUNCEMENT_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+pub async fn delete_announcement(
+    req: &mut salvo::Request,
+    depot: &mut salvo::Depot,
+) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteAnnouncementReq {
         id: u32,
     }
     let DeleteAnnouncementReq { id } = req.extract().await?;
-    if service::weihuda::announcement::get_announcement(id)
-        .await?
-        .is_none()
-    {
+    let Some(old_announcement) = service::weihuda::announcement::get_announcement(id).await? else {
         return Err(anyhow!("公告不存在").into());
-    }
+    };
     service::weihuda::announcement::delete_announcement(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("announcement", id, &old_announcement),
+    )
+    .await;
     Ok(().into())
 }
@@ -1,32 +1,27 @@
 use salvo::{handler, macros::Extractible};
 
-use crate::{
-    result::{AppError, RouterResult},
-    service, utils,
-};
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::{result::RouterResult, service, utils};
 
 const MINI_CONFIG_PERMISSION_PREFIX: &str = "hdwsh:miniConfig";
 
 pub fn routers() -> salvo::Router {
     salvo::Router::with_path("mini-config")
-        .get(get_mini_config)
-        .put(put_mini_config)
+        .push(
+            auth::require(format!("{}:query", MINI_CONFIG_PERMISSION_PREFIX)).get(get_mini_config),
+        )
+        .push(auth::require(format!("{}:edit", MINI_CONFIG_PERMISSION_PREFIX)).put(put_mini_config))
 }
 
 #[handler]
-async fn get_mini_config(_req: &mut salvo::Request) -> RouterResult {
+async fn get_mini_config() -> RouterResult {
     let res = service::weihuda::config::get_mini_config().await?;
     Ok(res.into())
 }
 
 #[handler]
-async fn put_mini_config(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", MINI_CONFIG_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_mini_config(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body")))]
     struct UpdateMiniConfigReq {
@@ -34,6 +29,18 @@ async fn put_mini_config(req: &mut salvo::Request) -> RouterResult {
         value: String,
     }
     let UpdateMiniConfigReq { key, value } = req.extract().await?;
+    let old_value = service::weihuda::config::get_mini_config()
+        .await?
+        .into_iter()
+        .find(|c| c.key == key)
+        .map(|c| c.value);
     service::weihuda::config::update_mini_config(&key, &value).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("update", "miniConfig", &key)
+            .before(&old_value)
+            .after(&value),
+    )
+    .await;
     Ok(().into())
 }
@@ -2,6 +2,8 @@ use anyhow::anyhow;
 use salvo::{handler, macros::Extractible};
 use serde_json::json;
 
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
 use crate::{
     result::{AppError, RouterResult},
     service::{
@@ -14,30 +16,29 @@ use crate::{
 const PERMISSION_PREFIX: &str = "hdwsh:feedback";
 
 pub fn routers() -> salvo::Router {
+    let permission = |action: &str| format!("{}:{}", PERMISSION_PREFIX, action);
     salvo::Router::with_path("feedback")
-        .get(get_feedback_list)
+        .push(auth::require(permission("query")).get(get_feedback_list))
         .push(
             salvo::Router::with_path("{id}")
-                .get(get_feedback)
-                .put(put_feedback)
-                .delete(delete_feedback)
+                .push(auth::require(permission("query")).get(get_feedback))
+                .push(auth::require(permission("edit")).put(put_feedback))
+                .push(auth::require(permission("delete")).delete(delete_feedback))
                 .push(
                     salvo::Router::with_path("msg")
-                        .get(get_feedback_msg_list)
-                        .post(add_feedback_msg)
-                        .push(salvo::Router::with_path("{msg_id}").delete(delete_feedback_msg)),
+                        .push(auth::require(permission("query")).get(get_feedback_msg_list))
+                        .push(auth::require(permission("edit")).post(add_feedback_msg))
+                        .push(
+                            auth::require(permission("delete"))
+                                .path("{msg_id}")
+                                .delete(delete_feedback_msg),
+                        ),
                 ),
         )
 }
 
 #[handler]
 async fn get_feedback_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetFeedbackListReq {
@@ -71,12 +72,6 @@ async fn get_feedback_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get_feedback(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetFeedbackReq {
@@ -88,66 +83,62 @@ async fn get_feedback(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn put_feedback(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:edit", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_feedback(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
-    #[salvo(extract(default_source(from = "body")))]
+    #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutFeedbackReq {
         #[salvo(extract(source(from = "param")))]
         id: u32,
         status: u32,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
-    let PutFeedbackReq { id, status } = req.extract().await?;
+    let PutFeedbackReq {
+        id,
+        status,
+        updated_at,
+    } = req.extract().await?;
     if !matches!(status, 0..=3) {
         return Err(AppError::ParamParseError);
     }
-    let feedback = service::weihuda::feedback::get_feedback(id).await?;
-    if feedback.is_none() {
+    let Some(feedback) = service::weihuda::feedback::get_feedback(id).await? else {
         return Err(anyhow!("反馈不存在").into());
-    }
-    service::weihuda::feedback::update_feedback(id, FeedbackStatus::from(status)).await?;
+    };
+    service::weihuda::feedback::update_feedback(id, FeedbackStatus::from(status), updated_at)
+        .await?;
     let new_feedback = service::weihuda::feedback::get_feedback(id)
         .await?
         .ok_or(anyhow!("更新问题反馈失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("feedback", id, &feedback, &new_feedback),
+    )
+    .await;
     Ok(new_feedback.into())
 }
 
 #[handler]
-async fn delete_feedback(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_feedback(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteFeedbackReq {
         id: u32,
     }
     let DeleteFeedbackReq { id } = req.extract().await?;
-    let feedback = service::weihuda::feedback::get_feedback(id).await?;
-    if feedback.is_none() {
+    let Some(feedback) = service::weihuda::feedback::get_feedback(id).await? else {
         return Err(anyhow!("反馈不存在").into());
-    }
+    };
     service::weihuda::feedback::delete_feedback(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("feedback", id, &feedback),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
 async fn get_feedback_msg_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetFeedbackMsgListReq {
@@ -159,14 +150,8 @@ async fn get_feedback_msg_list(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn add_feedback_msg(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:edit", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn add_feedback_msg(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct AddFeedbackMsgReq {
@@ -180,24 +165,26 @@ async fn add_feedback_msg(req: &mut salvo::Request) -> RouterResult {
         return Err(anyhow!("反馈不存在").into());
     };
     let feedback_msg_id = service::weihuda::feedback::add_feedback_msg(
-        FeedbackMsgType::from(typ),
+        FeedbackMsgType::from(typ.clone()),
         msg.as_deref(),
         &feedback,
-        &user,
+        user,
     )
     .await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create(
+            "feedbackMsg",
+            feedback_msg_id,
+            &json!({ "feedbackId": id, "typ": typ, "msg": msg }),
+        ),
+    )
+    .await;
     Ok(feedback_msg_id.into())
 }
 
 #[handler]
-async fn delete_feedback_msg(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:delete", PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_feedback_msg(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteFeedbackMsgReq {
@@ -211,13 +198,18 @@ async fn delete_feedback_msg(req: &mut salvo::Request) -> RouterResult {
     {
         return Err(anyhow!("反馈不存在").into());
     }
-    if !service::weihuda::feedback::get_feedback_msg_list(id)
+    let Some(msg) = service::weihuda::feedback::get_feedback_msg_list(id)
         .await?
-        .iter()
-        .any(|msg| msg.id == msg_id)
-    {
+        .into_iter()
+        .find(|msg| msg.id == msg_id)
+    else {
         return Err(anyhow!("反馈消息不存在").into());
-    }
+    };
     service::weihuda::feedback::delete_feedback_msg(msg_id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("feedbackMsg", msg_id, &msg),
+    )
+    .await;
     Ok(().into())
 }
@@ -1,4 +1,6 @@
-use crate::result::{AppError, RouterResult};
+use crate::middleware::auth;
+use crate::result::RouterResult;
+use crate::service::qnxg::audit_log::AuditEntry;
 use crate::service::weihuda::jifen::{AddRecordBatchItem, GoodsRecordStatus};
 use crate::{service, utils};
 use anyhow::anyhow;
@@ -12,54 +14,60 @@ const JIFEN_RECORD_PERMISSION_PREFIX: &str = "hdwsh:jifenRecord";
 const JIFEN_RULE_PERMISSION_PREFIX: &str = "hdwsh:jifenRule";
 
 pub fn routers() -> salvo::Router {
+    let goods_record = |action: &str| format!("{}:{}", GOODS_RECORD_PERMISSION_PREFIX, action);
+    let goods = |action: &str| format!("{}:{}", JIFEN_GOODS_PERMISSION_PREFIX, action);
+    let record = |action: &str| format!("{}:{}", JIFEN_RECORD_PERMISSION_PREFIX, action);
+    let rule = |action: &str| format!("{}:{}", JIFEN_RULE_PERMISSION_PREFIX, action);
     salvo::Router::new()
         .push(
             salvo::Router::with_path("goods-record")
-                .get(get_goods_record_list)
+                .push(auth::require(goods_record("query")).get(get_goods_record_list))
                 .push(
                     salvo::Router::with_path("{id}")
-                        .get(get_goods_record)
-                        .delete(delete_goods_record)
-                        .push(salvo::Router::with_path("receive").get(get_goods_receive)),
+                        .push(auth::require(goods_record("query")).get(get_goods_record))
+                        .push(auth::require(goods_record("delete")).delete(delete_goods_record))
+                        .push(
+                            auth::require(goods_record("edit"))
+                                .path("receive")
+                                .get(get_goods_receive),
+                        ),
                 ),
         )
         .push(
             salvo::Router::with_path("jifen-goods")
-                .get(get_goods_list)
-                .post(post_goods)
+                .push(auth::require(goods("query")).get(get_goods_list))
+                .push(auth::require(goods("add")).post(post_goods))
                 .push(
                     salvo::Router::with_path("{id}")
-                        .put(put_goods)
-                        .delete(delete_goods),
+                        .push(auth::require(goods("edit")).put(put_goods))
+                        .push(auth::require(goods("delete")).delete(delete_goods)),
                 ),
         )
         .push(
             salvo::Router::with_path("jifen-record")
-                .get(get_record_list)
-                .post(post_record)
-                .push(salvo::Router::with_path("{id}").get(get_record))
-                .push(salvo::Router::with_path("batch").post(post_record_batch)),
+                .push(auth::require(record("query")).get(get_record_list))
+                .push(auth::require(record("add")).post(post_record))
+                .push(
+                    auth::require(record("add"))
+                        .path("batch")
+                        .post(post_record_batch),
+                )
+                .push(auth::require(record("query")).path("{id}").get(get_record)),
         )
         .push(
             salvo::Router::with_path("jifen-rule")
-                .get(get_rule_list)
-                .post(post_rule)
+                .push(auth::require(rule("query")).get(get_rule_list))
+                .push(auth::require(rule("add")).post(post_rule))
                 .push(
                     salvo::Router::with_path("{id}")
-                        .put(put_rule)
-                        .delete(delete_rule),
+                        .push(auth::require(rule("edit")).put(put_rule))
+                        .push(auth::require(rule("delete")).delete(delete_rule)),
                 ),
         )
 }
 
 #[handler]
 async fn get_goods_record_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", GOODS_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetGoodsRecordListReq {
@@ -96,12 +104,6 @@ async fn get_goods_record_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get_goods_record(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", GOODS_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetGoodsRecordReq {
@@ -113,72 +115,56 @@ async fn get_goods_record(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn get_goods_receive(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", GOODS_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_goods_receive(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetGoodsReceiveReq {
         id: u32,
     }
     let GetGoodsReceiveReq { id } = req.extract().await?;
-    let record = service::weihuda::jifen::get_goods_record(id).await?;
-    if record.is_none() {
+    let Some(record) = service::weihuda::jifen::get_goods_record(id).await? else {
         return Err(anyhow!("兑换记录不存在").into());
-    }
-    if record.unwrap().status == GoodsRecordStatus::Received {
+    };
+    if record.status == GoodsRecordStatus::Received {
         return Err(anyhow!("兑换记录已领取").into());
     }
     service::weihuda::jifen::receive_goods(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::new("receive", "goodsRecord", id).before(&record),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
-async fn delete_goods_record(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", GOODS_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_goods_record(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteGoodsRecordReq {
         id: u32,
     }
     let DeleteGoodsRecordReq { id } = req.extract().await?;
-    let record = service::weihuda::jifen::get_goods_record(id).await?;
-    if record.is_none() {
+    let Some(record) = service::weihuda::jifen::get_goods_record(id).await? else {
         return Err(anyhow!("兑换记录不存在").into());
-    }
+    };
     service::weihuda::jifen::delete_goods_record(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("goodsRecord", id, &record),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
-async fn get_goods_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", JIFEN_GOODS_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_goods_list() -> RouterResult {
     let goods = service::weihuda::jifen::get_goods_list().await?;
     Ok(goods.into())
 }
 
 #[handler]
-async fn post_goods(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", JIFEN_GOODS_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_goods(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostGoodsReq {
@@ -211,18 +197,16 @@ async fn post_goods(req: &mut salvo::Request) -> RouterResult {
         .into_iter()
         .find(|g| g.id == res)
         .ok_or(anyhow!("新增积分商品失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("goods", res, &new_goods),
+    )
+    .await;
     Ok(new_goods.into())
 }
 
 #[handler]
-async fn put_goods(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", JIFEN_GOODS_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
-
+async fn put_goods(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutGoodsReq {
@@ -234,6 +218,8 @@ async fn put_goods(req: &mut salvo::Request) -> RouterResult {
         enabled: bool,
         name: String,
         price: i32,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutGoodsReq {
         id,
@@ -243,12 +229,16 @@ async fn put_goods(req: &mut salvo::Request) -> RouterResult {
         enabled,
         name,
         price,
+        updated_at,
     } = req.extract().await?;
 
-    let goods = service::weihuda::jifen::get_goods_list().await?;
-    if !goods.iter().any(|v| v.id == id) {
+    let Some(old_goods) = service::weihuda::jifen::get_goods_list()
+        .await?
+        .into_iter()
+        .find(|v| v.id == id)
+    else {
         return Err(anyhow!("积分商品不存在").into());
-    }
+    };
 
     service::weihuda::jifen::update_goods(
         id,
@@ -258,6 +248,7 @@ async fn put_goods(req: &mut salvo::Request) -> RouterResult {
         price,
         description.as_deref(),
         enabled,
+        updated_at,
     )
     .await?;
     let new_goods = service::weihuda::jifen::get_goods_list()
@@ -265,19 +256,17 @@ async fn put_goods(req: &mut salvo::Request) -> RouterResult {
         .into_iter()
         .find(|g| g.id == id)
         .ok_or(anyhow!("更新积分商品失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("goods", id, &old_goods, &new_goods),
+    )
+    .await;
 
     Ok(new_goods.into())
 }
 
 #[handler]
-async fn delete_goods(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", JIFEN_GOODS_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
-
+async fn delete_goods(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteGoodsReq {
@@ -285,23 +274,25 @@ async fn delete_goods(req: &mut salvo::Request) -> RouterResult {
     }
     let DeleteGoodsReq { id } = req.extract().await?;
 
-    let goods = service::weihuda::jifen::get_goods_list().await?;
-    if !goods.iter().any(|v| v.id == id) {
+    let Some(old_goods) = service::weihuda::jifen::get_goods_list()
+        .await?
+        .into_iter()
+        .find(|v| v.id == id)
+    else {
         return Err(anyhow!("积分商品不存在").into());
-    }
+    };
 
     service::weihuda::jifen::delete_goods(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("goods", id, &old_goods),
+    )
+    .await;
     Ok(().into())
 }
 
 #[handler]
 async fn get_record_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", JIFEN_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetRecordListReq {
@@ -338,12 +329,6 @@ async fn get_record_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get_record(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", JIFEN_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetRecordReq {
@@ -355,14 +340,8 @@ async fn get_record(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn post_record(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:add", JIFEN_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_record(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostRecordReq {
@@ -375,40 +354,35 @@ async fn post_record(req: &mut salvo::Request) -> RouterResult {
         desc,
         jifen,
     } = req.extract().await?;
-    let res = service::weihuda::jifen::add_record(&user, &stu_id, jifen, &desc).await?;
+    let res = service::weihuda::jifen::add_record(user, &stu_id, jifen, &desc).await?;
     let record = service::weihuda::jifen::get_record(res)
         .await?
         .ok_or(anyhow!("新增积分记录失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("jifenRecord", res, &record),
+    )
+    .await;
     Ok(record.into())
 }
 
 #[handler]
-async fn post_record_batch(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:add", JIFEN_RECORD_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_record_batch(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostRecordBatchReq {
         items: Vec<AddRecordBatchItem>,
     }
     let PostRecordBatchReq { items } = req.extract().await?;
-    service::weihuda::jifen::add_record_batch(items, &user).await?;
+    let entry = AuditEntry::batch("create", "jifenRecord").after(&items);
+    service::weihuda::jifen::add_record_batch(items, user).await?;
+    service::qnxg::audit_log::record(utils::auth::current_auth(depot)?, entry).await;
     Ok(().into())
 }
 
 #[handler]
-async fn get_rule_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", JIFEN_RULE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn get_rule_list() -> RouterResult {
     let rules = service::weihuda::jifen::get_rule_list().await?;
     Ok(json!({
         "count": rules.len(),
@@ -418,13 +392,7 @@ async fn get_rule_list(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn post_rule(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", JIFEN_RULE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_rule(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostRuleReq {
@@ -454,17 +422,16 @@ async fn post_rule(req: &mut salvo::Request) -> RouterResult {
         .into_iter()
         .find(|r| r.id == res)
         .ok_or(anyhow!("新增积分规则失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("jifenRule", res, &new_rule),
+    )
+    .await;
     Ok(new_rule.into())
 }
 
 #[handler]
-async fn put_rule(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:edit", JIFEN_RULE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_rule(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutRuleReq {
@@ -476,6 +443,8 @@ async fn put_rule(req: &mut salvo::Request) -> RouterResult {
         key: String,
         max_count: u32,
         name: String,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let PutRuleReq {
         id,
@@ -485,34 +454,52 @@ async fn put_rule(req: &mut salvo::Request) -> RouterResult {
         key,
         max_count,
         name,
+        updated_at,
     } = req.extract().await?;
-    let list = service::weihuda::jifen::get_rule_list().await?;
-    if !list.iter().any(|r| r.id == id) {
+    let Some(old_rule) = service::weihuda::jifen::get_rule_list()
+        .await?
+        .into_iter()
+        .find(|r| r.id == id)
+    else {
         return Err(anyhow!("积分规则不存在").into());
-    }
-    service::weihuda::jifen::update_rule(id, &name, &key, jifen, cycle, max_count, is_show).await?;
+    };
+    service::weihuda::jifen::update_rule(
+        id, &key, &name, jifen, cycle, max_count, is_show, updated_at,
+    )
+    .await?;
     let new_rule = service::weihuda::jifen::get_rule_list()
         .await?
         .into_iter()
         .find(|r| r.id == id)
         .ok_or(anyhow!("更新积分规则失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("jifenRule", id, &old_rule, &new_rule),
+    )
+    .await;
     Ok(new_rule.into())
 }
 
 #[handler]
-async fn delete_rule(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", JIFEN_RULE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_rule(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteRuleReq {
         id: u32,
     }
     let DeleteRuleReq { id } = req.extract().await?;
+    let Some(old_rule) = service::weihuda::jifen::get_rule_list()
+        .await?
+        .into_iter()
+        .find(|r| r.id == id)
+    else {
+        return Err(anyhow!("积分规则不存在").into());
+    };
     service::weihuda::jifen::delete_rule(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("jifenRule", id, &old_rule),
+    )
+    .await;
     Ok(().into())
 }
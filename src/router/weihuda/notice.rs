@@ -1,8 +1,6 @@
-use crate::{
-    infra::mysql::notice::NoticeStatus,
-    result::{AppError, RouterResult},
-    service, utils,
-};
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::{infra::mysql::notice::NoticeStatus, result::RouterResult, service, utils};
 use anyhow::anyhow;
 use salvo::{handler, macros::Extractible};
 use serde_json::json;
@@ -10,20 +8,19 @@ use serde_json::json;
 const NOTICE_PERMISSION_PREFIX: &str = "hdwsh:notice";
 
 pub fn routers() -> salvo::Router {
+    let permission = |action: &str| format!("{}:{}", NOTICE_PERMISSION_PREFIX, action);
     salvo::Router::with_path("notice")
-        .get(get_list)
-        .post(post)
-        .push(salvo::Router::with_path("{id}").get(get).delete(delete))
+        .push(auth::require(permission("query")).get(get_list))
+        .push(auth::require(permission("add")).post(post))
+        .push(
+            salvo::Router::with_path("{id}")
+                .push(auth::require(permission("query")).get(get))
+                .push(auth::require(permission("delete")).delete(delete)),
+        )
 }
 
 #[handler]
 async fn get_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", NOTICE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetNoticeListReq {
@@ -58,12 +55,6 @@ async fn get_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", NOTICE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetNoticeReq {
@@ -75,13 +66,7 @@ async fn get(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn post(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:add", NOTICE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostNoticeReq {
@@ -101,23 +86,30 @@ async fn post(req: &mut salvo::Request) -> RouterResult {
     let new_notice = service::weihuda::notice::get_notice(notice_id)
         .await?
         .ok_or(anyhow!("添加消息失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("notice", notice_id, &new_notice),
+    )
+    .await;
     Ok(new_notice.into())
 }
 
 #[handler]
-async fn delete(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:delete", NOTICE_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteNoticeReq {
         id: u32,
     }
     let DeleteNoticeReq { id } = req.extract().await?;
+    let Some(old_notice) = service::weihuda::notice::get_notice(id).await? else {
+        return Err(anyhow!("消息不存在").into());
+    };
     service::weihuda::notice::delete_notice(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("notice", id, &old_notice),
+    )
+    .await;
     Ok(().into())
 }
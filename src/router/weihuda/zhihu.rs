@@ -1,6 +1,8 @@
+use crate::middleware::auth;
+use crate::service::qnxg::audit_log::AuditEntry;
 use crate::service::weihuda::zhihu::{ZhihuBasicInfo, ZhihuStatus, ZhihuType};
 use crate::{
-    result::{AppError, AppResult, RouterResult},
+    result::{AppResult, RouterResult},
     service, utils,
 };
 use anyhow::anyhow;
@@ -12,27 +14,32 @@ use serde_json::json;
 const ZHIHU_PERMISSION_PREFIX: &str = "hdwsh:zhihu";
 
 pub fn routers() -> salvo::Router {
+    let permission = |action: &str| format!("{}:{}", ZHIHU_PERMISSION_PREFIX, action);
     salvo::Router::with_path("zhihu")
-        .get(get_zhihu_list)
-        .post(post_zhihu)
-        .push(salvo::Router::with_path("url-resolve").get(get_url_resolve))
-        .push(salvo::Router::with_path("wx-img-proxy").get(get_wx_img_proxy))
+        .push(auth::require(permission("query")).get(get_zhihu_list))
+        .push(auth::require(permission("add")).post(post_zhihu))
+        // 解析文章链接用于新增文章
+        .push(
+            auth::require(permission("add"))
+                .path("url-resolve")
+                .get(get_url_resolve),
+        )
+        // 图片代理会请求任意地址，不能对外开放
+        .push(
+            auth::require(permission("query"))
+                .path("wx-img-proxy")
+                .get(get_wx_img_proxy),
+        )
         .push(
             salvo::Router::with_path("{id}")
-                .get(get_zhihu)
-                .put(put_zhihu)
-                .delete(delete_zhihu),
+                .push(auth::require(permission("query")).get(get_zhihu))
+                .push(auth::require(permission("edit")).put(put_zhihu))
+                .push(auth::require(permission("delete")).delete(delete_zhihu)),
         )
 }
 
 #[handler]
 async fn get_zhihu_list(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", ZHIHU_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "query"), rename_all = "camelCase"))]
     struct GetZhihuListReq {
@@ -72,12 +79,6 @@ async fn get_zhihu_list(req: &mut salvo::Request) -> RouterResult {
 
 #[handler]
 async fn get_zhihu(req: &mut salvo::Request) -> RouterResult {
-    if !service::qnxg::user::get_user_permission(utils::auth::parse_token(req).await?.id)
-        .await?
-        .has(&format!("{}:query", ZHIHU_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct GetZhihuReq {
@@ -89,14 +90,8 @@ async fn get_zhihu(req: &mut salvo::Request) -> RouterResult {
 }
 
 #[handler]
-async fn post_zhihu(req: &mut salvo::Request) -> RouterResult {
-    let user = utils::auth::parse_token(req).await?;
-    if !service::qnxg::user::get_user_permission(user.id)
-        .await?
-        .has(&format!("{}:add", ZHIHU_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn post_zhihu(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
+    let user = utils::auth::current_user(depot)?;
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PostZhihuReq {
@@ -118,7 +113,7 @@ async fn post_zhihu(req: &mut salvo::Request) -> RouterResult {
         tags: param.tags,
         cover: param.cover,
         status,
-        stu_id: user.info.stu_id,
+        stu_id: user.info.stu_id.clone(),
         top: param.top,
         created_at: utils::now_time(),
     };
@@ -126,18 +121,16 @@ async fn post_zhihu(req: &mut salvo::Request) -> RouterResult {
     let new_zhihu = service::weihuda::zhihu::get_zhihu(res)
         .await?
         .ok_or(anyhow!("新增知湖文章失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::create("zhihu", res, &new_zhihu),
+    )
+    .await;
     Ok(new_zhihu.into())
 }
 
 #[handler]
-async fn put_zhihu(req: &mut salvo::Request) -> RouterResult {
-    let user_id = utils::auth::parse_token(req).await?.id;
-    if !service::qnxg::user::get_user_permission(user_id)
-        .await?
-        .has(&format!("{}:edit", ZHIHU_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn put_zhihu(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "body"), rename_all = "camelCase"))]
     struct PutZhihuReq {
@@ -149,6 +142,8 @@ async fn put_zhihu(req: &mut salvo::Request) -> RouterResult {
         cover: Option<String>,
         status: u32,
         top: bool,
+        // 获取数据时的 updatedAt，数据在这之后被修改过时拒绝更新
+        updated_at: chrono::NaiveDateTime,
     }
     let param: PutZhihuReq = req.extract().await?;
     let status = ZhihuStatus::from(param.status);
@@ -164,34 +159,37 @@ async fn put_zhihu(req: &mut salvo::Request) -> RouterResult {
         status,
         top: param.top,
         created_at: utils::now_time(),
-        stu_id: zhihu.info.stu_id,
+        stu_id: zhihu.info.stu_id.clone(),
     };
-    service::weihuda::zhihu::update_zhihu(param.id, &info).await?;
+    service::weihuda::zhihu::update_zhihu(param.id, &info, param.updated_at).await?;
     let new_zhihu = service::weihuda::zhihu::get_zhihu(param.id)
         .await?
         .ok_or(anyhow!("更新知湖文章失败"))?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::update("zhihu", param.id, &zhihu, &new_zhihu),
+    )
+    .await;
     Ok(new_zhihu.into())
 }
 
 #[handler]
-async fn delete_zhihu(req: &mut salvo::Request) -> RouterResult {
-    let user_id = utils::auth::parse_token(req).await?.id;
-    if !service::qnxg::user::get_user_permission(user_id)
-        .await?
-        .has(&format!("{}:delete", ZHIHU_PERMISSION_PREFIX))
-    {
-        return Err(AppError::PermissionDenied);
-    }
+async fn delete_zhihu(req: &mut salvo::Request, depot: &mut salvo::Depot) -> RouterResult {
     #[derive(serde::Deserialize, Extractible, Debug)]
     #[salvo(extract(default_source(from = "param")))]
     struct DeleteZhihuReq {
         id: u32,
     }
     let DeleteZhihuReq { id } = req.extract().await?;
-    if service::weihuda::zhihu::get_zhihu(id).await?.is_none() {
+    let Some(old_zhihu) = service::weihuda::zhihu::get_zhihu(id).await? else {
         return Err(anyhow!("知湖文章不存在").into());
-    }
+    };
     service::weihuda::zhihu::delete_zhihu(id).await?;
+    service::qnxg::audit_log::record(
+        utils::auth::current_auth(depot)?,
+        AuditEntry::delete("zhihu", id, &old_zhihu),
+    )
+    .await;
     Ok(().into())
 }
 
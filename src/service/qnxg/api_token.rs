@@ -0,0 +1,80 @@
+pub use crate::infra::mysql::api_token::{
+    ApiToken, get_api_token, get_user_api_tokens, revoke_api_token,
+};
+
+use anyhow::anyhow;
+
+use crate::result::{AppError, AppResult};
+use crate::utils::ClientInfo;
+use crate::{infra, service, utils};
+
+/// API token 的前缀，用于和 JWT 区分
+const TOKEN_PREFIX: &str = "yqt_";
+const TOKEN_LEN: usize = 40;
+// 保存明文的前几位用于展示
+const DISPLAY_PREFIX_LEN: usize = TOKEN_PREFIX.len() + 6;
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub info: ApiToken,
+    // token 明文，只会返回这一次
+    pub token: String,
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// 为用户创建 API token，授权范围必须在用户自己的权限之内
+pub async fn create_api_token(
+    user_id: u32,
+    name: &str,
+    scopes: &[String],
+    expires_at: Option<chrono::NaiveDateTime>,
+) -> AppResult<CreatedApiToken> {
+    if scopes.is_empty() {
+        return Err(anyhow!("授权范围不能为空").into());
+    }
+    if expires_at.is_some_and(|t| t <= utils::now_time()) {
+        return Err(anyhow!("过期时间必须晚于当前时间").into());
+    }
+    let permission = service::qnxg::user::get_user_permission(user_id).await?;
+    if let Some(scope) = scopes.iter().find(|s| !permission.has(s)) {
+        return Err(anyhow!("没有权限 {}，不能授权给 token", scope).into());
+    }
+    let token = format!("{}{}", TOKEN_PREFIX, utils::random_token(TOKEN_LEN));
+    let id = infra::mysql::api_token::add_api_token(
+        user_id,
+        name,
+        &utils::sha256_hash(&token),
+        &token[..DISPLAY_PREFIX_LEN],
+        scopes,
+        expires_at,
+    )
+    .await?;
+    let info = get_api_token(id)
+        .await?
+        .ok_or(anyhow!("创建 API token 失败"))?;
+    Ok(CreatedApiToken { info, token })
+}
+
+/// 校验 API token，返回 token 信息
+pub async fn authenticate(token: &str, client: &ClientInfo) -> AppResult<ApiToken> {
+    let Some(api_token) =
+        infra::mysql::api_token::get_api_token_by_token(&utils::sha256_hash(token)).await?
+    else {
+        return Err(AppError::Unauthorized);
+    };
+    if !api_token.is_active() {
+        return Err(AppError::Unauthorized);
+    }
+    infra::mysql::api_token::touch_api_token(api_token.id, &client.ip).await?;
+    Ok(api_token)
+}
+
+/// 撤销用户的所有 API token，用户被删除或禁止登录时调用
+pub async fn revoke_user_api_tokens(user_id: u32) -> AppResult<()> {
+    infra::mysql::api_token::revoke_user_api_tokens(user_id).await
+}
@@ -0,0 +1,118 @@
+pub use crate::infra::mysql::audit_log::{AuditLogFilter, get_audit_log_list};
+
+use crate::infra;
+use crate::infra::mysql::audit_log::AuditLogBasicInfo;
+use crate::utils::auth::AuthInfo;
+
+/// 一条待记录的操作
+pub struct AuditEntry {
+    action: &'static str,
+    entity_type: &'static str,
+    entity_id: Option<String>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+fn snapshot<T: serde::Serialize>(value: &T) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
+
+impl AuditEntry {
+    pub fn new(action: &'static str, entity_type: &'static str, entity_id: impl ToString) -> Self {
+        Self {
+            action,
+            entity_type,
+            entity_id: Some(entity_id.to_string()),
+            before: None,
+            after: None,
+        }
+    }
+    /// 批量操作，不对应单个实体
+    pub fn batch(action: &'static str, entity_type: &'static str) -> Self {
+        Self {
+            action,
+            entity_type,
+            entity_id: None,
+            before: None,
+            after: None,
+        }
+    }
+    pub fn create<T: serde::Serialize>(
+        entity_type: &'static str,
+        entity_id: impl ToString,
+        after: &T,
+    ) -> Self {
+        Self::new("create", entity_type, entity_id).after(after)
+    }
+    pub fn update<B: serde::Serialize, A: serde::Serialize>(
+        entity_type: &'static str,
+        entity_id: impl ToString,
+        before: &B,
+        after: &A,
+    ) -> Self {
+        Self::new("update", entity_type, entity_id)
+            .before(before)
+            .after(after)
+    }
+    pub fn delete<T: serde::Serialize>(
+        entity_type: &'static str,
+        entity_id: impl ToString,
+        before: &T,
+    ) -> Self {
+        Self::new("delete", entity_type, entity_id).before(before)
+    }
+    pub fn before<T: serde::Serialize>(mut self, before: &T) -> Self {
+        self.before = snapshot(before);
+        self
+    }
+    pub fn after<T: serde::Serialize>(mut self, after: &T) -> Self {
+        self.after = snapshot(after);
+        self
+    }
+}
+
+/// 记录系统自动执行的操作，操作人 id 为 0
+pub async fn record_system(entry: AuditEntry) {
+    let info = AuditLogBasicInfo {
+        actor_id: 0,
+        actor_name: "系统".into(),
+        impersonated_user_id: None,
+        action: entry.action.to_string(),
+        entity_type: entry.entity_type.to_string(),
+        entity_id: entry.entity_id,
+        before: entry.before,
+        after: entry.after,
+        ip: String::new(),
+    };
+    save(info).await;
+}
+
+/// 记录操作日志，模拟登录时操作人记为发起模拟的管理员
+/// 写入失败只打日志，不影响已经完成的操作
+pub async fn record(auth: &AuthInfo, entry: AuditEntry) {
+    let actor = auth.impersonator.as_ref().unwrap_or(&auth.user);
+    let info = AuditLogBasicInfo {
+        actor_id: actor.id,
+        actor_name: actor.info.name.clone(),
+        impersonated_user_id: auth.impersonator.as_ref().map(|_| auth.user.id),
+        action: entry.action.to_string(),
+        entity_type: entry.entity_type.to_string(),
+        entity_id: entry.entity_id,
+        before: entry.before,
+        after: entry.after,
+        ip: auth.client.ip.clone(),
+    };
+    save(info).await;
+}
+
+async fn save(info: AuditLogBasicInfo) {
+    if let Err(e) = infra::mysql::audit_log::add_audit_log(&info).await {
+        tracing::error!(
+            "记录操作日志失败: {:?}, 操作: {} {} {:?}",
+            e,
+            info.action,
+            info.entity_type,
+            info.entity_id
+        );
+    }
+}
@@ -1,11 +1,14 @@
-use crate::{infra, result::AppResult, utils};
+use crate::service::qnxg::session::TokenPair;
+use crate::utils::ClientInfo;
+use crate::{infra, result::AppResult, service};
 
 pub use crate::infra::weihuda::auth::{
     get_auth_qrcode, get_auth_qrcode_info, get_auth_qrcode_status,
 };
 
-pub async fn login(user_id: u32) -> AppResult<String> {
+pub async fn login(user_id: u32, client: &ClientInfo) -> AppResult<TokenPair> {
     infra::mysql::user::update_user_last_login(user_id).await?;
-    let token = utils::auth::generate_token(user_id)?;
-    Ok(token)
+    service::qnxg::user::invalidate_user(user_id);
+    let tokens = service::qnxg::session::create_session(user_id, client).await?;
+    Ok(tokens)
 }
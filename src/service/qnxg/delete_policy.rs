@@ -0,0 +1,36 @@
+pub use crate::infra::mysql::Dependents;
+
+use anyhow::anyhow;
+
+use crate::result::AppResult;
+
+/// 删除仍被引用的数据时如何处理引用方
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletePolicy {
+    /// 有引用时拒绝删除
+    #[default]
+    Refuse,
+    /// 把用户转移到另一个部门，只适用于部门
+    Reassign,
+    /// 解除所有引用后删除
+    Detach,
+}
+
+/// 有引用时返回说明引用情况的错误
+pub fn ensure_no_dependents(dependents: &Dependents) -> AppResult<()> {
+    if dependents.is_empty() {
+        return Ok(());
+    }
+    let mut parts = Vec::new();
+    if dependents.users > 0 {
+        parts.push(format!("{} 个用户", dependents.users));
+    }
+    if dependents.department_roles > 0 {
+        parts.push(format!("{} 个部门角色授予", dependents.department_roles));
+    }
+    if dependents.roles > 0 {
+        parts.push(format!("{} 个角色", dependents.roles));
+    }
+    Err(anyhow!("仍被{}引用，请选择转移或解除关联后再删除", parts.join("、")).into())
+}
@@ -1,3 +1,59 @@
 pub use crate::infra::mysql::department::{
-    Department, add_department, delete_department, get_department_list, update_department,
+    Department, add_department, get_department_list, update_department,
 };
+
+use anyhow::anyhow;
+
+use crate::result::AppResult;
+use crate::service::qnxg::delete_policy::{self, DeletePolicy, Dependents};
+use crate::{infra, service};
+
+pub async fn get_department_dependents(id: u32) -> AppResult<Dependents> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    let res = infra::mysql::department::get_department_dependents(&mut tx, id).await?;
+    tx.rollback().await?;
+    Ok(res)
+}
+
+/// 删除部门，转移时 target_id 为用户转移到的部门
+/// 部门下还有用户时不能解除关联，只能转移
+pub async fn delete_department(
+    id: u32,
+    policy: DeletePolicy,
+    target_id: Option<u32>,
+) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    let dependents = infra::mysql::department::get_department_dependents(&mut tx, id).await?;
+    match policy {
+        DeletePolicy::Refuse => delete_policy::ensure_no_dependents(&dependents)?,
+        DeletePolicy::Reassign => {
+            let Some(target_id) = target_id.filter(|t| *t != id) else {
+                return Err(anyhow!("请选择用户要转移到的其他部门").into());
+            };
+            if !get_department_list()
+                .await?
+                .iter()
+                .any(|d| d.id == target_id)
+            {
+                return Err(anyhow!("转移到的部门不存在").into());
+            }
+            infra::mysql::department::reassign_department_users(&mut tx, id, target_id).await?;
+            infra::mysql::role::delete_department_roles(&mut tx, id).await?;
+        }
+        DeletePolicy::Detach => {
+            if dependents.users > 0 {
+                return Err(
+                    anyhow!("部门下还有 {} 个用户，只能转移到其他部门", dependents.users).into(),
+                );
+            }
+            infra::mysql::role::delete_department_roles(&mut tx, id).await?;
+        }
+    }
+    infra::mysql::department::delete_department(&mut tx, id).await?;
+    tx.commit().await?;
+    // 转移后用户的部门发生变化
+    if policy == DeletePolicy::Reassign {
+        service::qnxg::user::invalidate_all_user();
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use anyhow::anyhow;
+
+use crate::config::CFG;
+use crate::result::{AppError, AppResult};
+use crate::service::qnxg::user::User;
+use crate::utils::auth::AuthInfo;
+use crate::{service, utils};
+
+pub const IMPERSONATE_PERMISSION: &str =
+    service::qnxg::permission_catalog::declared("system:impersonate");
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpersonationToken {
+    pub access_token: String,
+    // 有效期，单位秒，不能刷新
+    pub expires_in: u64,
+    // 被模拟的用户
+    pub user: User,
+}
+
+/// 检查是否可以模拟该用户，管理员不能被模拟
+pub async fn ensure_can_impersonate(impersonator: &User, user: &User) -> AppResult<()> {
+    if impersonator.id == user.id {
+        return Err(anyhow!("不能模拟自己").into());
+    }
+    if !service::qnxg::user::get_user_permission(impersonator.id)
+        .await?
+        .has(IMPERSONATE_PERMISSION)
+    {
+        return Err(AppError::PermissionDenied);
+    }
+    if service::qnxg::user::get_user_permission(user.id)
+        .await?
+        .is_admin()
+    {
+        return Err(anyhow!("不能模拟管理员").into());
+    }
+    Ok(())
+}
+
+/// 以当前登录的管理员身份模拟另一个用户
+pub async fn impersonate(auth: &AuthInfo, user_id: u32) -> AppResult<ImpersonationToken> {
+    // 模拟登录和 API token 都没有自己的会话
+    let Some(session_id) = auth.session_id else {
+        return Err(anyhow!("只能在正常登录时发起模拟登录").into());
+    };
+    let Some(user) = service::qnxg::user::get_user(user_id).await? else {
+        return Err(anyhow!("用户不存在").into());
+    };
+    ensure_can_impersonate(&auth.user, &user).await?;
+    Ok(ImpersonationToken {
+        access_token: utils::auth::generate_impersonation_token(user.id, auth.user.id, session_id)?,
+        expires_in: CFG.jwt.impersonation_ttl,
+        user,
+    })
+}
@@ -0,0 +1,137 @@
+pub use crate::infra::mysql::login_attempt::{
+    LoginAttempt, LoginAttemptFilter, LoginMethod, delete_lockout, get_lockout, get_lockout_list,
+    get_login_attempt_list,
+};
+
+use crate::config::CFG;
+use crate::infra::mysql::login_attempt::LockoutScope;
+use crate::result::AppResult;
+use crate::utils::ClientInfo;
+use crate::{infra, utils};
+use anyhow::anyhow;
+
+/// 失败次数达到上限后的锁定时长，之后每多失败一次翻倍，不超过 max
+/// limit 为 0 时不锁定
+fn lockout_duration(failures: u32, limit: u32, base: u64, max: u64) -> Option<u64> {
+    if limit == 0 || failures < limit {
+        return None;
+    }
+    let exp = (failures - limit).min(32);
+    Some(base.saturating_mul(1 << exp).min(max))
+}
+
+fn scopes<'a>(stu_id: &'a str, client: &'a ClientInfo) -> [(LockoutScope, &'a str, u32); 2] {
+    [
+        (LockoutScope::StuId, stu_id, CFG.login.max_failures),
+        (
+            LockoutScope::Ip,
+            client.ip.as_str(),
+            CFG.login.ip_max_failures,
+        ),
+    ]
+}
+
+/// 学号或 IP 处于锁定中时拒绝登录
+pub async fn ensure_not_locked(stu_id: &str, client: &ClientInfo) -> AppResult<()> {
+    let now = utils::now_time();
+    for (scope, value, _) in scopes(stu_id, client) {
+        if let Some(until) = infra::mysql::login_attempt::get_locked_until(scope, value).await? {
+            let seconds = (until - now).num_seconds().max(1);
+            return Err(anyhow!("登录失败次数过多，请在 {} 秒后重试", seconds).into());
+        }
+    }
+    Ok(())
+}
+
+/// 只记录登录历史，不影响失败计数
+pub async fn record_attempt(
+    stu_id: &str,
+    user_id: Option<u32>,
+    method: LoginMethod,
+    client: &ClientInfo,
+    success: bool,
+) -> AppResult<()> {
+    infra::mysql::login_attempt::add_login_attempt(
+        stu_id,
+        user_id,
+        method,
+        &client.ip,
+        client.user_agent.as_deref(),
+        success,
+    )
+    .await?;
+    Ok(())
+}
+
+/// 记录一次失败并累加失败计数，达到上限后锁定
+pub async fn record_failure(
+    stu_id: &str,
+    user_id: Option<u32>,
+    method: LoginMethod,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    record_attempt(stu_id, user_id, method, client, false).await?;
+    let now = utils::now_time();
+    let window_start = now - chrono::Duration::seconds(CFG.login.failure_window as i64);
+    for (scope, value, limit) in scopes(stu_id, client) {
+        let failures = infra::mysql::login_attempt::add_failure(scope, value, window_start).await?;
+        if let Some(duration) = lockout_duration(
+            failures,
+            limit,
+            CFG.login.lockout_base,
+            CFG.login.lockout_max,
+        ) {
+            let until = now + chrono::Duration::seconds(duration as i64);
+            infra::mysql::login_attempt::set_locked_until(scope, value, until).await?;
+            tracing::warn!(
+                "登录失败 {} 次，锁定 {} {} 到 {}",
+                failures,
+                <&str>::from(scope),
+                value,
+                until
+            );
+        }
+    }
+    Ok(())
+}
+
+/// 登录成功后清除该学号的失败计数
+/// IP 的计数不清除，避免用自己的账号登录来重置对其他账号的尝试次数
+pub async fn record_success(
+    stu_id: &str,
+    user_id: u32,
+    method: LoginMethod,
+    client: &ClientInfo,
+) -> AppResult<()> {
+    record_attempt(stu_id, Some(user_id), method, client, true).await?;
+    infra::mysql::login_attempt::clear_failures(LockoutScope::StuId, stu_id).await?;
+    Ok(())
+}
+
+/// 用户自己的登录历史
+pub async fn get_user_login_history(
+    user_id: u32,
+    page: u32,
+    page_size: u32,
+) -> AppResult<(u32, Vec<LoginAttempt>)> {
+    let filter = LoginAttemptFilter {
+        user_id: Some(user_id),
+        ..Default::default()
+    };
+    get_login_attempt_list(page, page_size, &filter).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockout_duration() {
+        assert_eq!(lockout_duration(4, 5, 60, 3600), None);
+        assert_eq!(lockout_duration(5, 5, 60, 3600), Some(60));
+        assert_eq!(lockout_duration(6, 5, 60, 3600), Some(120));
+        assert_eq!(lockout_duration(8, 5, 60, 3600), Some(480));
+        assert_eq!(lockout_duration(100, 5, 60, 3600), Some(3600));
+        assert_eq!(lockout_duration(100, 0, 60, 3600), None);
+    }
+}
@@ -1,7 +1,17 @@
+pub mod api_token;
+pub mod audit_log;
 pub mod auth;
+pub mod delete_policy;
 pub mod department;
+pub mod impersonation;
+pub mod login_attempt;
+pub mod password_reset;
 pub mod permission;
+pub mod permission_catalog;
+pub mod recycle_bin;
 pub mod role;
+pub mod session;
 pub mod statistics;
+pub mod totp;
 pub mod user;
 pub mod work_hour;
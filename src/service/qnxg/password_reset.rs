@@ -0,0 +1,68 @@
+use anyhow::anyhow;
+
+use crate::config::CFG;
+use crate::result::AppResult;
+use crate::utils::ClientInfo;
+use crate::{infra, service, utils};
+
+const RESET_TOKEN_LEN: usize = 48;
+
+fn reset_mail_body(name: &str, url: &str) -> String {
+    format!(
+        "{}，你好：\n\n我们收到了重置易千工作台密码的申请，请在 {} 分钟内打开下面的链接设置新密码：\n\n{}\n\n链接只能使用一次。如果不是你本人操作，请忽略这封邮件，你的密码不会改变。\n",
+        name,
+        CFG.password_reset.token_ttl / 60,
+        url
+    )
+}
+
+/// 申请重置密码，向用户的邮箱发送重置链接
+/// 用户不存在、没有邮箱或申请过于频繁时同样返回成功，避免被用来探测账号
+pub async fn request_reset(stu_id: &str, client: &ClientInfo) -> AppResult<()> {
+    let Some(user) = service::qnxg::user::get_user_by_stu_id(stu_id).await? else {
+        return Ok(());
+    };
+    let Some(email) = user.info.email.filter(|e| !e.is_empty()) else {
+        tracing::info!("用户 {} 申请重置密码，但没有填写邮箱", user.id);
+        return Ok(());
+    };
+    let now = utils::now_time();
+    if infra::mysql::password_reset::get_last_request_time(user.id)
+        .await?
+        .is_some_and(|t| now - t < chrono::Duration::seconds(CFG.password_reset.cooldown as i64))
+    {
+        return Ok(());
+    }
+    // 只保留最新的一个重置链接
+    infra::mysql::password_reset::invalidate_user_password_resets(user.id).await?;
+    let token = utils::random_token(RESET_TOKEN_LEN);
+    infra::mysql::password_reset::add_password_reset(
+        user.id,
+        &utils::sha256_hash(&token),
+        &client.ip,
+        now + chrono::Duration::seconds(CFG.password_reset.token_ttl as i64),
+    )
+    .await?;
+    let url = CFG.password_reset.reset_url.replace("{token}", &token);
+    let body = reset_mail_body(&user.info.name, &url);
+    // 在后台发送，响应时间不会因为账号是否存在而不同
+    tokio::spawn(async move {
+        if let Err(e) = infra::mail::send_mail(&email, "易千工作台密码重置", &body).await {
+            tracing::error!("发送密码重置邮件失败: {:?}", e);
+        }
+    });
+    Ok(())
+}
+
+/// 使用重置凭据设置新密码，并撤销该用户的所有会话
+/// password 参数为明文
+pub async fn confirm_reset(token: &str, password: &str) -> AppResult<()> {
+    let Some(user_id) =
+        infra::mysql::password_reset::use_password_reset(&utils::sha256_hash(token)).await?
+    else {
+        return Err(anyhow!("重置链接无效或已过期").into());
+    };
+    service::qnxg::user::change_user_password(user_id, password, None).await?;
+    infra::mysql::password_reset::invalidate_user_password_resets(user_id).await?;
+    Ok(())
+}
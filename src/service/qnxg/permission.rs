@@ -1,4 +1,373 @@
 pub use crate::infra::mysql::permission::{
-    Permission, PermissionItem, add_permission, delete_permission, get_permission_list,
-    update_permission,
+    Permission, PermissionItem, add_permission, get_permission_list,
 };
+
+use anyhow::anyhow;
+use std::collections::HashMap;
+
+use crate::infra::mysql::permission::rule_matches;
+use crate::result::AppResult;
+use crate::service::qnxg::delete_policy::{self, DeletePolicy, Dependents};
+use crate::service::qnxg::role::{DepartmentRole, Role};
+use crate::service::qnxg::user::User;
+use crate::{infra, service};
+
+/// 判断用户在某个部门内是否拥有某个权限
+/// 管理员在所有部门都拥有，普通角色的权限只在用户自己的部门内生效，部门角色的权限只在对应的部门内生效
+/// permission 为用户当前的权限，使用 API token 时部门角色的权限同样受授权范围限制
+pub async fn has_in_department(
+    user: &User,
+    permission: &Permission,
+    name: &str,
+    department_id: u32,
+) -> AppResult<bool> {
+    if permission.is_admin() {
+        return Ok(true);
+    }
+    let department_permission = get_department_permission(user, permission).await?;
+    Ok(allowed_in_department(
+        user.info.department_id,
+        permission,
+        &department_permission,
+        name,
+        department_id,
+    ))
+}
+
+/// 判断用户是否在至少一个部门内拥有某个权限
+pub async fn has_in_any_department(
+    user: &User,
+    permission: &Permission,
+    name: &str,
+) -> AppResult<bool> {
+    Ok(departments_with(user, permission, name)
+        .await?
+        .is_none_or(|d| !d.is_empty()))
+}
+
+/// 用户拥有某个权限的部门，管理员返回 None，表示所有部门
+pub async fn departments_with(
+    user: &User,
+    permission: &Permission,
+    name: &str,
+) -> AppResult<Option<Vec<u32>>> {
+    if permission.is_admin() {
+        return Ok(None);
+    }
+    let department_permission = get_department_permission(user, permission).await?;
+    let mut res = department_permission
+        .keys()
+        .copied()
+        .chain([user.info.department_id])
+        .filter(|d| {
+            allowed_in_department(
+                user.info.department_id,
+                permission,
+                &department_permission,
+                name,
+                *d,
+            )
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    res.dedup();
+    Ok(Some(res))
+}
+
+/// 用户的部门角色在各部门内的权限，key 为部门 id
+async fn get_department_permission(
+    user: &User,
+    permission: &Permission,
+) -> AppResult<HashMap<u32, Permission>> {
+    let mut role_id: HashMap<u32, Vec<u32>> = HashMap::new();
+    for r in service::qnxg::role::get_user_department_roles(user.id).await? {
+        role_id.entry(r.department_id).or_default().push(r.role.id);
+    }
+    let mut res = HashMap::new();
+    for (department_id, role_id) in role_id {
+        let department_permission = service::qnxg::role::get_role_permission(&role_id)
+            .await?
+            .with_scopes_of(permission);
+        res.insert(department_id, department_permission);
+    }
+    Ok(res)
+}
+
+/// 不考虑管理员，由调用方先判断
+fn allowed_in_department(
+    user_department_id: u32,
+    permission: &Permission,
+    department_permission: &HashMap<u32, Permission>,
+    name: &str,
+    department_id: u32,
+) -> bool {
+    (user_department_id == department_id && permission.has(name))
+        || department_permission
+            .get(&department_id)
+            .is_some_and(|p| p.has(name))
+}
+
+/// 某个角色中覆盖了所查询权限的规则
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSource {
+    // 授予给用户的角色
+    pub role: Role,
+    // 规则所在的祖先角色，规则直接属于授予的角色时为空
+    pub inherited_from: Option<Role>,
+    // 部门角色生效的部门，普通角色为空
+    pub department_id: Option<u32>,
+    pub item: PermissionItem,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessExplanation {
+    // 不考虑部门时是否拥有该权限
+    pub granted: bool,
+    pub is_admin: bool,
+    // 通过部门角色拥有该权限的部门
+    pub granted_departments: Vec<u32>,
+    // 授予该权限的规则
+    pub grants: Vec<RuleSource>,
+    // 拒绝该权限的规则，优先于授予规则
+    pub denies: Vec<RuleSource>,
+    pub roles: Vec<Role>,
+    pub department_roles: Vec<DepartmentRole>,
+    // 指定 API token 时 token 的授权范围
+    pub scopes: Option<Vec<String>>,
+    // 所查询的权限是否在授权范围之内，没有指定 API token 时为 true
+    pub in_scope: bool,
+    // 用户最终拥有的全部权限规则
+    pub permissions: Vec<PermissionItem>,
+}
+
+/// 列出角色及其祖先角色中覆盖所查询权限的规则，own 为每个角色自身直接拥有的权限
+fn matched_sources(
+    permission: &str,
+    role: &Role,
+    department_id: Option<u32>,
+    all_roles: &[Role],
+    parents: &[(u32, u32)],
+    own: &HashMap<u32, Permission>,
+) -> (Vec<RuleSource>, Vec<RuleSource>) {
+    let mut grants = Vec::new();
+    let mut denies = Vec::new();
+    for ancestor in infra::mysql::role::expand_roles(&[role.id], parents) {
+        let Some(ancestor_permission) = own.get(&ancestor) else {
+            continue;
+        };
+        let inherited_from = all_roles
+            .iter()
+            .find(|r| r.id == ancestor && ancestor != role.id)
+            .cloned();
+        let source = |item: &PermissionItem| RuleSource {
+            role: role.clone(),
+            inherited_from: inherited_from.clone(),
+            department_id,
+            item: item.clone(),
+        };
+        let (role_grants, role_denies) = ancestor_permission.matched_rules(permission);
+        grants.extend(role_grants.into_iter().map(source));
+        denies.extend(role_denies.into_iter().map(source));
+    }
+    (grants, denies)
+}
+
+/// 解释用户是否拥有某个权限，以及由哪个角色的哪条规则决定
+/// 指定 api_token_id 时按该 token 的授权范围判断
+pub async fn explain_access(
+    user: &User,
+    permission: &str,
+    api_token_id: Option<u32>,
+) -> AppResult<AccessExplanation> {
+    let scopes = match api_token_id {
+        Some(id) => {
+            let Some(api_token) = service::qnxg::api_token::get_api_token(id)
+                .await?
+                .filter(|t| t.user_id == user.id)
+            else {
+                return Err(anyhow!("该用户没有这个 API token").into());
+            };
+            Some(api_token.scopes)
+        }
+        None => None,
+    };
+    let roles = service::qnxg::role::get_user_roles(user.id).await?;
+    let department_roles = service::qnxg::role::get_user_department_roles(user.id).await?;
+    let all_roles = service::qnxg::role::get_role_list().await?;
+    let parents = service::qnxg::role::get_role_parents().await?;
+    let granted_roles = roles
+        .iter()
+        .map(|r| (r, None))
+        .chain(
+            department_roles
+                .iter()
+                .map(|r| (&r.role, Some(r.department_id))),
+        )
+        .collect::<Vec<_>>();
+    let role_id = granted_roles.iter().map(|(r, _)| r.id).collect::<Vec<_>>();
+    let mut own = HashMap::new();
+    for id in infra::mysql::role::expand_roles(&role_id, &parents) {
+        own.insert(
+            id,
+            service::qnxg::role::get_role_own_permission(&[id]).await?,
+        );
+    }
+    let mut grants = Vec::new();
+    let mut denies = Vec::new();
+    for (role, department_id) in granted_roles {
+        let (role_grants, role_denies) =
+            matched_sources(permission, role, department_id, &all_roles, &parents, &own);
+        grants.extend(role_grants);
+        denies.extend(role_denies);
+    }
+    // 不走缓存，保证看到的是数据库中最新的结果
+    let role_id = roles.iter().map(|r| r.id).collect::<Vec<_>>();
+    let mut effective = service::qnxg::role::get_role_permission(&role_id).await?;
+    if let Some(scopes) = &scopes {
+        effective = effective.restrict(scopes.clone());
+    }
+    let mut granted_departments = Vec::new();
+    for r in &department_roles {
+        if !granted_departments.contains(&r.department_id)
+            && has_in_department(user, &effective, permission, r.department_id).await?
+        {
+            granted_departments.push(r.department_id);
+        }
+    }
+    let in_scope = scopes
+        .as_ref()
+        .is_none_or(|scopes| scopes.iter().any(|s| rule_matches(s, permission)));
+    Ok(AccessExplanation {
+        granted: effective.has(permission),
+        is_admin: effective.is_admin(),
+        granted_departments,
+        grants,
+        denies,
+        roles,
+        department_roles,
+        scopes,
+        in_scope,
+        permissions: effective.into_inner(),
+    })
+}
+
+pub async fn update_permission(
+    id: u32,
+    name: &str,
+    permission: &str,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    service::qnxg::role::ensure_admin_remains(&mut tx, |s| s.update_permission(id, permission))
+        .await?;
+    infra::mysql::permission::update_permission(&mut tx, id, name, permission, version).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_all_user_permission();
+    Ok(())
+}
+
+pub async fn get_permission_dependents(id: u32) -> AppResult<Dependents> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    let res = infra::mysql::permission::get_permission_dependents(&mut tx, id).await?;
+    tx.rollback().await?;
+    Ok(res)
+}
+
+/// 删除权限，解除关联时从所有角色中移除该权限
+pub async fn delete_permission(id: u32, policy: DeletePolicy) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    service::qnxg::role::ensure_admin_remains(&mut tx, |s| s.remove_permission(id)).await?;
+    let dependents = infra::mysql::permission::get_permission_dependents(&mut tx, id).await?;
+    match policy {
+        DeletePolicy::Refuse => delete_policy::ensure_no_dependents(&dependents)?,
+        DeletePolicy::Reassign => return Err(anyhow!("权限不支持转移，请选择解除关联").into()),
+        DeletePolicy::Detach => infra::mysql::permission::detach_permission(&mut tx, id).await?,
+    }
+    infra::mysql::permission::delete_permission(&mut tx, id).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_all_user_permission();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(id: u32, name: &str) -> Role {
+        Role {
+            id,
+            name: name.into(),
+            updated_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    fn permission(id: u32, rule: &str) -> Permission {
+        Permission::new(vec![PermissionItem {
+            id,
+            name: rule.into(),
+            permission: rule.into(),
+            updated_at: chrono::NaiveDateTime::default(),
+        }])
+    }
+
+    #[test]
+    fn test_department_role_grant() {
+        // 用户在部门 1，没有普通的查询权限，通过部门角色在部门 2 拥有查询权限
+        let own = permission(1, "yq:work");
+        let department_permission = HashMap::from([(2, permission(2, "yq:user:query"))]);
+        let allowed = |department_id| {
+            allowed_in_department(
+                1,
+                &own,
+                &department_permission,
+                "yq:user:query",
+                department_id,
+            )
+        };
+        assert!(!allowed(1));
+        assert!(allowed(2));
+        assert!(!allowed(3));
+        // API token 的授权范围同样限制部门角色的权限
+        let restricted = own.restrict(vec!["yq:work".into()]);
+        let department_permission = department_permission
+            .into_iter()
+            .map(|(d, p)| (d, p.with_scopes_of(&restricted)))
+            .collect::<HashMap<_, _>>();
+        assert!(!allowed_in_department(
+            1,
+            &restricted,
+            &department_permission,
+            "yq:user:query",
+            2
+        ));
+    }
+
+    #[test]
+    fn test_parent_deny_beats_child_grant() {
+        // 子角色 2 继承父角色 1，子角色授予 yq:user，父角色拒绝 yq:user:delete
+        let (parent, child) = (role(1, "父角色"), role(2, "子角色"));
+        let parents = vec![(2, 1)];
+        let own = HashMap::from([
+            (1, permission(1, "!yq:user:delete")),
+            (2, permission(2, "yq:user")),
+        ]);
+        let all_roles = vec![parent, child.clone()];
+        let (grants, denies) =
+            matched_sources("yq:user:delete", &child, None, &all_roles, &parents, &own);
+        assert_eq!(grants.len(), 1);
+        assert!(grants[0].inherited_from.is_none());
+        assert_eq!(denies.len(), 1);
+        assert_eq!(denies[0].role.id, 2);
+        assert_eq!(denies[0].inherited_from.as_ref().map(|r| r.id), Some(1));
+        let effective = Permission::new(
+            infra::mysql::role::expand_roles(&[2], &parents)
+                .iter()
+                .flat_map(|id| own[id].clone().into_inner())
+                .collect(),
+        );
+        assert!(!effective.has("yq:user:delete"));
+        assert!(effective.has("yq:user:edit"));
+    }
+}
@@ -0,0 +1,210 @@
+use crate::infra;
+use crate::infra::mysql::permission::rule_matches;
+use crate::result::AppResult;
+use crate::service::qnxg::permission::PermissionItem;
+
+/// 代码中检查的一个权限
+#[derive(serde::Serialize, Debug, Clone, Copy)]
+pub struct CatalogEntry {
+    // 权限标识
+    pub permission: &'static str,
+    // 权限名称
+    pub name: &'static str,
+}
+
+const fn entry(permission: &'static str, name: &'static str) -> CatalogEntry {
+    CatalogEntry { permission, name }
+}
+
+/// 代码中检查的所有权限，新增权限检查时需要在这里声明
+pub const CATALOG: &[CatalogEntry] = &[
+    // 系统管理
+    entry("system:role:query", "查询角色"),
+    entry("system:role:add", "新增角色"),
+    entry("system:role:edit", "编辑角色"),
+    entry("system:role:delete", "删除角色"),
+    entry("system:permission:query", "查询权限"),
+    entry("system:permission:add", "新增权限"),
+    entry("system:permission:edit", "编辑权限"),
+    entry("system:permission:delete", "删除权限"),
+    entry("system:permission:explain", "排查用户权限"),
+    entry("system:audit:query", "查询操作日志"),
+    entry("system:session:query", "查询登录会话"),
+    entry("system:session:delete", "注销登录会话"),
+    entry("system:loginAttempt:query", "查询登录记录"),
+    entry("system:loginLockout:query", "查询登录锁定"),
+    entry("system:loginLockout:delete", "解除登录锁定"),
+    entry("system:totp:reset", "重置两步验证"),
+    entry("system:apiToken:query", "查询 API token"),
+    entry("system:apiToken:add", "为服务账号创建 API token"),
+    entry("system:apiToken:delete", "撤销 API token"),
+    entry("system:serviceAccount:add", "新增服务账号"),
+    entry("system:impersonate", "模拟登录"),
+    entry("system:recycleBin:query", "查询回收站"),
+    entry("system:recycleBin:restore", "恢复已删除数据"),
+    entry("system:recycleBin:purge", "彻底删除数据"),
+    // 易千
+    entry("yq:user:query", "查询用户"),
+    entry("yq:user:add", "新增用户"),
+    entry("yq:user:edit", "编辑用户"),
+    entry("yq:user:delete", "删除用户"),
+    entry("yq:department:add", "新增部门"),
+    entry("yq:department:edit", "编辑部门"),
+    entry("yq:department:delete", "删除部门"),
+    entry("yq:workHours:query", "查询工时"),
+    entry("yq:workHours:add", "新增工时"),
+    entry("yq:workHours:edit", "编辑工时"),
+    entry("yq:workHours:delete", "删除工时"),
+    entry("yq:workHours:checkDepartment", "审核部门工时"),
+    entry("yq:workHours:generateTable", "生成工时表"),
+    // 微沪大
+    entry("hdwsh:statistics:query", "查询统计数据"),
+    entry("hdwsh:notice:query", "查询通知"),
+    entry("hdwsh:notice:add", "新增通知"),
+    entry("hdwsh:notice:delete", "删除通知"),
+    entry("hdwsh:announcement:query", "查询公告"),
+    entry("hdwsh:announcement:add", "新增公告"),
+    entry("hdwsh:announcement:edit", "编辑公告"),
+    entry("hdwsh:announcement:delete", "删除公告"),
+    entry("hdwsh:feedback:query", "查询问题反馈"),
+    entry("hdwsh:feedback:edit", "回复问题反馈"),
+    entry("hdwsh:feedback:delete", "删除问题反馈"),
+    entry("hdwsh:goodsRecord:query", "查询兑换记录"),
+    entry("hdwsh:goodsRecord:edit", "编辑兑换记录"),
+    entry("hdwsh:goodsRecord:delete", "删除兑换记录"),
+    entry("hdwsh:jifenGoods:query", "查询积分商品"),
+    entry("hdwsh:jifenGoods:add", "新增积分商品"),
+    entry("hdwsh:jifenGoods:edit", "编辑积分商品"),
+    entry("hdwsh:jifenGoods:delete", "删除积分商品"),
+    entry("hdwsh:jifenRecord:query", "查询积分记录"),
+    entry("hdwsh:jifenRecord:add", "新增积分记录"),
+    entry("hdwsh:jifenRule:query", "查询积分规则"),
+    entry("hdwsh:jifenRule:add", "新增积分规则"),
+    entry("hdwsh:jifenRule:edit", "编辑积分规则"),
+    entry("hdwsh:jifenRule:delete", "删除积分规则"),
+    entry("hdwsh:zhihu:query", "查询知乎"),
+    entry("hdwsh:zhihu:add", "新增知乎"),
+    entry("hdwsh:zhihu:edit", "编辑知乎"),
+    entry("hdwsh:zhihu:delete", "删除知乎"),
+    entry("hdwsh:miniConfig:query", "查询小程序配置"),
+    entry("hdwsh:miniConfig:edit", "编辑小程序配置"),
+];
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// 处理函数中直接检查的权限，用于定义常量，没有在目录中声明时编译失败
+pub const fn declared(permission: &'static str) -> &'static str {
+    let mut i = 0;
+    while i < CATALOG.len() {
+        if str_eq(CATALOG[i].permission, permission) {
+            return permission;
+        }
+        i += 1;
+    }
+    panic!("权限没有在权限目录中声明");
+}
+
+pub fn contains(permission: &str) -> bool {
+    CATALOG.iter().any(|e| e.permission == permission)
+}
+
+/// 数据库中的权限规则是否覆盖了目录中的任一权限，拒绝规则按去掉 `!` 后的规则判断
+fn is_checked(item: &PermissionItem) -> bool {
+    let rule = item.permission.trim_start_matches('!');
+    CATALOG.iter().any(|e| rule_matches(rule, e.permission))
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogStatus {
+    pub permission: &'static str,
+    pub name: &'static str,
+    // 数据库中是否已有对应的权限
+    pub synced: bool,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogReport {
+    pub catalog: Vec<CatalogStatus>,
+    // 数据库中不覆盖任何目录权限的规则，通常是写错了或者对应的功能已经删除
+    pub orphaned: Vec<PermissionItem>,
+}
+
+pub async fn get_catalog() -> AppResult<CatalogReport> {
+    let list = infra::mysql::permission::get_permission_list().await?;
+    let catalog = CATALOG
+        .iter()
+        .map(|e| CatalogStatus {
+            permission: e.permission,
+            name: e.name,
+            synced: list.iter().any(|p| p.permission == e.permission),
+        })
+        .collect();
+    let orphaned = list.into_iter().filter(|p| !is_checked(p)).collect();
+    Ok(CatalogReport { catalog, orphaned })
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub added: Vec<CatalogEntry>,
+    pub orphaned: Vec<PermissionItem>,
+}
+
+/// 把目录中缺少的权限写入数据库，已有的权限不会修改，可以重复执行
+pub async fn sync_catalog() -> AppResult<SyncReport> {
+    let list = infra::mysql::permission::get_permission_list().await?;
+    let mut added = Vec::new();
+    for e in CATALOG {
+        if list.iter().any(|p| p.permission == e.permission) {
+            continue;
+        }
+        infra::mysql::permission::add_permission(e.name, e.permission).await?;
+        added.push(*e);
+    }
+    let orphaned = list
+        .into_iter()
+        .filter(|p| !is_checked(p))
+        .collect::<Vec<_>>();
+    for p in &orphaned {
+        tracing::warn!("权限 {} ({}) 没有被代码检查", p.permission, p.name);
+    }
+    Ok(SyncReport { added, orphaned })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog() {
+        for (i, e) in CATALOG.iter().enumerate() {
+            assert!(
+                !CATALOG[..i].iter().any(|v| v.permission == e.permission),
+                "权限 {} 重复",
+                e.permission
+            );
+            assert!(
+                e.permission
+                    .split(':')
+                    .all(|seg| !seg.is_empty() && seg.chars().all(|c| c.is_ascii_alphanumeric())),
+                "权限 {} 格式不正确",
+                e.permission
+            );
+        }
+    }
+}
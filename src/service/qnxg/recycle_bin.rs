@@ -0,0 +1,46 @@
+pub use crate::infra::mysql::recycle_bin::{DeletedItem, RecycleEntity, get_deleted_list};
+
+use crate::config::CFG;
+use crate::infra;
+use crate::result::AppResult;
+use crate::service;
+use crate::utils;
+use anyhow::anyhow;
+
+/// 恢复已删除的数据，返回恢复前的数据
+pub async fn restore(entity: RecycleEntity, id: u32) -> AppResult<DeletedItem> {
+    let Some(item) = infra::mysql::recycle_bin::get_deleted(entity, id).await? else {
+        return Err(anyhow!("回收站中不存在该数据").into());
+    };
+    if infra::mysql::recycle_bin::count_unique_conflicts(entity, id).await? > 0 {
+        return Err(match entity {
+            RecycleEntity::User => anyhow!("学号已被其他用户使用"),
+            RecycleEntity::Role => anyhow!("角色名称已存在"),
+            RecycleEntity::Permission => anyhow!("权限标识已存在"),
+            RecycleEntity::Department => anyhow!("部门名称已存在"),
+            RecycleEntity::JifenRule => anyhow!("规则 key 已存在"),
+            _ => anyhow!("存在冲突的数据"),
+        }
+        .into());
+    }
+    if entity == RecycleEntity::User
+        && !infra::mysql::recycle_bin::user_department_exists(id).await?
+    {
+        return Err(anyhow!("用户所在部门已被删除，请先恢复部门").into());
+    }
+    if !infra::mysql::recycle_bin::restore(entity, id).await? {
+        return Err(anyhow!("回收站中不存在该数据").into());
+    }
+    // 角色和权限恢复后会影响已缓存的用户权限
+    if matches!(entity, RecycleEntity::Role | RecycleEntity::Permission) {
+        service::qnxg::user::invalidate_all_user_permission();
+    }
+    Ok(item)
+}
+
+/// 彻底删除超过保留期限的数据，返回删除的数量
+pub async fn purge(entity: RecycleEntity) -> AppResult<u64> {
+    let deleted_before =
+        utils::now_time() - chrono::Duration::days(CFG.recycle_bin.retention_days as i64);
+    infra::mysql::recycle_bin::purge(entity, deleted_before).await
+}
@@ -1,4 +1,401 @@
 pub use crate::infra::mysql::role::{
-    add_role, delete_role, get_role_list, get_role_permission, get_user_roles, update_role,
-    update_user_roles,
+    DepartmentRole, Role, get_role_list, get_role_own_permission, get_role_parents,
+    get_role_permission, get_user_department_roles, get_user_role_grants, get_user_roles,
 };
+
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::config::CFG;
+use crate::result::AppResult;
+use crate::service::qnxg::audit_log::AuditEntry;
+use crate::service::qnxg::delete_policy::{self, DeletePolicy, Dependents};
+use crate::service::qnxg::permission::{Permission, PermissionItem};
+use crate::{infra, service, utils};
+
+/// 紧急恢复时授予的角色
+const BREAK_GLASS_ROLE: &str = "超级管理员";
+
+/// 当前的角色和权限数据，用于检查变更后是否还有可以登录的管理员
+/// 只计入永久授予的角色，临时授予到期后会被自动移除
+/// 在执行变更的事务中加锁读取，并发的变更会等待这次事务提交后再检查
+pub struct AdminInvariant {
+    users: Vec<u32>,
+    user_roles: Vec<(u32, u32)>,
+    parents: Vec<(u32, u32)>,
+    role_permissions: HashMap<u32, Vec<PermissionItem>>,
+}
+
+impl AdminInvariant {
+    async fn load(tx: &mut sqlx::Transaction<'_, sqlx::MySql>) -> AppResult<Self> {
+        Ok(Self {
+            users: infra::mysql::user::lock_user_ids_by_status(tx, &CFG.login.allowed_status)
+                .await?,
+            user_roles: infra::mysql::role::lock_permanent_user_roles(tx).await?,
+            parents: infra::mysql::role::lock_role_parents(tx).await?,
+            role_permissions: infra::mysql::role::lock_role_own_permissions(tx).await?,
+        })
+    }
+
+    /// 拥有管理员权限的用户
+    fn admins(&self) -> Vec<u32> {
+        self.users
+            .iter()
+            .copied()
+            .filter(|user_id| {
+                let role_id = self
+                    .user_roles
+                    .iter()
+                    .filter(|(u, _)| u == user_id)
+                    .map(|(_, r)| *r)
+                    .collect::<Vec<_>>();
+                let items = infra::mysql::role::expand_roles(&role_id, &self.parents)
+                    .iter()
+                    .filter_map(|r| self.role_permissions.get(r))
+                    .flatten()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                Permission::new(items).is_admin()
+            })
+            .collect()
+    }
+
+    pub fn remove_user(&mut self, user_id: u32) {
+        self.users.retain(|u| *u != user_id);
+    }
+
+    pub fn set_user_roles(&mut self, user_id: u32, role_id: &[u32]) {
+        self.user_roles.retain(|(u, _)| *u != user_id);
+        self.user_roles
+            .extend(role_id.iter().map(|r| (user_id, *r)));
+    }
+
+    pub fn remove_user_role(&mut self, user_id: u32, role_id: u32) {
+        self.user_roles
+            .retain(|(u, r)| *u != user_id || *r != role_id);
+    }
+
+    pub fn remove_role(&mut self, role_id: u32) {
+        self.role_permissions.remove(&role_id);
+        self.user_roles.retain(|(_, r)| *r != role_id);
+        self.parents.retain(|(r, p)| *r != role_id && *p != role_id);
+    }
+
+    pub fn set_role(
+        &mut self,
+        role_id: u32,
+        permission: Vec<PermissionItem>,
+        parent_id: Option<&[u32]>,
+    ) {
+        self.role_permissions.insert(role_id, permission);
+        if let Some(parent_id) = parent_id {
+            self.parents.retain(|(r, _)| *r != role_id);
+            self.parents.extend(parent_id.iter().map(|p| (role_id, *p)));
+        }
+    }
+
+    pub fn remove_permission(&mut self, permission_id: u32) {
+        for items in self.role_permissions.values_mut() {
+            items.retain(|p| p.id != permission_id);
+        }
+    }
+
+    pub fn update_permission(&mut self, permission_id: u32, permission: &str) {
+        for item in self.role_permissions.values_mut().flatten() {
+            if item.id == permission_id {
+                item.permission = permission.to_string();
+            }
+        }
+    }
+}
+
+/// 模拟变更后检查是否还有可以登录的管理员，没有时拒绝这次变更
+/// 变更前就已经没有管理员时不做限制，以免无法修复
+/// tx 需要是执行这次变更的事务
+pub async fn ensure_admin_remains(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    change: impl FnOnce(&mut AdminInvariant),
+) -> AppResult<()> {
+    let mut state = AdminInvariant::load(tx).await?;
+    if state.admins().is_empty() {
+        return Ok(());
+    }
+    change(&mut state);
+    if state.admins().is_empty() {
+        return Err(anyhow!(
+            "该操作会导致系统中没有可以登录的管理员，已拒绝。如果确实需要恢复，请在服务器上执行 grant-admin 命令"
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// 紧急恢复，把用户加入拥有所有权限的角色，只能在命令行中使用
+pub async fn grant_admin(user_id: u32) -> AppResult<()> {
+    let permission_list = service::qnxg::permission::get_permission_list().await?;
+    let star_id = match permission_list.iter().find(|p| p.permission == "*") {
+        Some(p) => p.id,
+        None => service::qnxg::permission::add_permission("所有权限", "*").await?,
+    };
+    let role_id = match get_role_list()
+        .await?
+        .into_iter()
+        .find(|r| r.name == BREAK_GLASS_ROLE)
+    {
+        Some(role) => {
+            let mut permission_id = get_role_own_permission(&[role.id])
+                .await?
+                .into_inner()
+                .into_iter()
+                .map(|p| p.id)
+                .collect::<Vec<_>>();
+            if !permission_id.contains(&star_id) {
+                permission_id.push(star_id);
+                let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+                infra::mysql::role::update_role(
+                    &mut tx,
+                    role.id,
+                    &role.name,
+                    &permission_id,
+                    None,
+                    role.updated_at,
+                )
+                .await?;
+                tx.commit().await?;
+            }
+            role.id
+        }
+        None => infra::mysql::role::add_role(BREAK_GLASS_ROLE, &[star_id], &[]).await?,
+    };
+    let mut role_ids = get_user_role_grants(user_id)
+        .await?
+        .into_iter()
+        .filter(|g| g.valid_from.is_none() && g.valid_until.is_none())
+        .map(|g| g.role.id)
+        .collect::<Vec<_>>();
+    if !role_ids.contains(&role_id) {
+        role_ids.push(role_id);
+    }
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    infra::mysql::role::update_user_roles(&mut tx, user_id, &role_ids).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_all_user_permission();
+    let permission = get_role_permission(
+        &get_user_roles(user_id)
+            .await?
+            .iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>(),
+    )
+    .await?;
+    if !permission.is_admin() {
+        return Err(anyhow!(
+            "已授予角色 {}，但仍然不是管理员，请检查拒绝规则",
+            BREAK_GLASS_ROLE
+        )
+        .into());
+    }
+    service::qnxg::audit_log::record_system(
+        AuditEntry::new("grantAdmin", "user", user_id).after(&role_ids),
+    )
+    .await;
+    Ok(())
+}
+
+pub async fn update_user_roles(user_id: u32, role_id: &[u32]) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    ensure_admin_remains(&mut tx, |s| s.set_user_roles(user_id, role_id)).await?;
+    infra::mysql::role::update_user_roles(&mut tx, user_id, role_id).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_user_permission(user_id);
+    Ok(())
+}
+
+/// 临时授予角色，到期后自动移除
+pub async fn grant_role(
+    user_id: u32,
+    role_id: u32,
+    valid_from: Option<chrono::NaiveDateTime>,
+    valid_until: chrono::NaiveDateTime,
+) -> AppResult<()> {
+    if valid_until <= utils::now_time() || valid_from.is_some_and(|from| from >= valid_until) {
+        return Err(anyhow!("授予的有效期不正确").into());
+    }
+    if !get_role_list().await?.iter().any(|r| r.id == role_id) {
+        return Err(anyhow!("角色不存在").into());
+    }
+    if get_user_role_grants(user_id)
+        .await?
+        .iter()
+        .any(|g| g.role.id == role_id && g.valid_from.is_none() && g.valid_until.is_none())
+    {
+        return Err(anyhow!("用户已经永久拥有该角色").into());
+    }
+    infra::mysql::role::set_role_grant(user_id, role_id, valid_from, valid_until).await?;
+    service::qnxg::user::invalidate_user_permission(user_id);
+    Ok(())
+}
+
+/// 移除用户的一个角色，包括永久授予和临时授予
+pub async fn revoke_role(user_id: u32, role_id: u32) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    ensure_admin_remains(&mut tx, |s| s.remove_user_role(user_id, role_id)).await?;
+    infra::mysql::role::delete_role_grant(&mut tx, user_id, role_id).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_user_permission(user_id);
+    Ok(())
+}
+
+/// 授予只在某个部门内生效的角色
+pub async fn add_user_department_role(
+    user_id: u32,
+    role_id: u32,
+    department_id: u32,
+) -> AppResult<()> {
+    if !get_role_list().await?.iter().any(|r| r.id == role_id) {
+        return Err(anyhow!("角色不存在").into());
+    }
+    if !service::qnxg::department::get_department_list()
+        .await?
+        .iter()
+        .any(|d| d.id == department_id)
+    {
+        return Err(anyhow!("部门不存在").into());
+    }
+    infra::mysql::role::add_user_department_role(user_id, role_id, department_id).await
+}
+
+pub async fn delete_user_department_role(
+    user_id: u32,
+    role_id: u32,
+    department_id: u32,
+) -> AppResult<()> {
+    infra::mysql::role::delete_user_department_role(user_id, role_id, department_id).await
+}
+
+/// 清理过期的临时授予并记录到操作日志
+pub async fn sweep_expired_grants() -> AppResult<()> {
+    let expired = infra::mysql::role::delete_expired_role_grants().await?;
+    for grant in expired {
+        service::qnxg::user::invalidate_user_permission(grant.user_id);
+        service::qnxg::audit_log::record_system(
+            AuditEntry::new("expireRoleGrant", "user", grant.user_id).before(&grant),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// 在后台定期清理过期的临时授予
+pub fn spawn_grant_sweeper() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            CFG.permission.grant_sweep_interval.max(1),
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep_expired_grants().await {
+                tracing::error!("清理过期的临时角色授予失败: {:?}", e);
+            }
+        }
+    });
+}
+
+/// 设置父角色后是否会形成环，即角色成为自己的祖先
+fn creates_cycle(role_id: u32, parent_id: &[u32], parents: &[(u32, u32)]) -> bool {
+    // 角色原来的父角色会被替换，不参与判断
+    let others = parents
+        .iter()
+        .filter(|(r, _)| *r != role_id)
+        .copied()
+        .collect::<Vec<_>>();
+    infra::mysql::role::expand_roles(parent_id, &others).contains(&role_id)
+}
+
+async fn ensure_parents_exist(parent_id: &[u32]) -> AppResult<()> {
+    let roles = get_role_list().await?;
+    if !parent_id.iter().all(|id| roles.iter().any(|r| r.id == *id)) {
+        return Err(anyhow!("父角色不存在").into());
+    }
+    Ok(())
+}
+
+pub async fn add_role(name: &str, permission: &[u32], parent_id: &[u32]) -> AppResult<u32> {
+    ensure_parents_exist(parent_id).await?;
+    infra::mysql::role::add_role(name, permission, parent_id).await
+}
+
+/// parent_id 为 None 时不修改继承关系
+pub async fn update_role(
+    role_id: u32,
+    name: &str,
+    permission: &[u32],
+    parent_id: Option<&[u32]>,
+    version: chrono::NaiveDateTime,
+) -> AppResult<()> {
+    if let Some(parent_id) = parent_id {
+        ensure_parents_exist(parent_id).await?;
+    }
+    let items = service::qnxg::permission::get_permission_list()
+        .await?
+        .into_iter()
+        .filter(|p| permission.contains(&p.id))
+        .collect::<Vec<_>>();
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    // 加锁读取继承关系，并发修改继承关系时不会都通过检查而形成环
+    if let Some(parent_id) = parent_id {
+        let parents = infra::mysql::role::lock_role_parents(&mut tx).await?;
+        if creates_cycle(role_id, parent_id, &parents) {
+            return Err(anyhow!("角色继承关系不能形成环").into());
+        }
+    }
+    ensure_admin_remains(&mut tx, |s| s.set_role(role_id, items, parent_id)).await?;
+    infra::mysql::role::update_role(&mut tx, role_id, name, permission, parent_id, version).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_all_user_permission();
+    Ok(())
+}
+
+pub async fn get_role_dependents(id: u32) -> AppResult<Dependents> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    let res = infra::mysql::role::get_role_dependents(&mut tx, id).await?;
+    tx.rollback().await?;
+    Ok(res)
+}
+
+/// 删除角色，解除关联时移除用户的该角色和子角色的继承关系
+pub async fn delete_role(id: u32, policy: DeletePolicy) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    ensure_admin_remains(&mut tx, |s| s.remove_role(id)).await?;
+    let dependents = infra::mysql::role::get_role_dependents(&mut tx, id).await?;
+    match policy {
+        DeletePolicy::Refuse => delete_policy::ensure_no_dependents(&dependents)?,
+        DeletePolicy::Reassign => return Err(anyhow!("角色不支持转移，请选择解除关联").into()),
+        DeletePolicy::Detach => infra::mysql::role::detach_role(&mut tx, id).await?,
+    }
+    infra::mysql::role::delete_role(&mut tx, id).await?;
+    tx.commit().await?;
+    service::qnxg::user::invalidate_all_user_permission();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_creates_cycle() {
+        // 3 继承 2，2 继承 1
+        let parents = vec![(3, 2), (2, 1)];
+        assert_eq!(
+            infra::mysql::role::expand_roles(&[3], &parents),
+            vec![3, 2, 1]
+        );
+        assert!(creates_cycle(1, &[3], &parents));
+        assert!(creates_cycle(1, &[1], &parents));
+        assert!(!creates_cycle(3, &[1], &parents));
+        // 替换掉原来的父角色后不再形成环
+        assert!(!creates_cycle(2, &[4], &[(3, 2), (2, 1), (1, 2)]));
+    }
+}
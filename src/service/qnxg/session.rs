@@ -0,0 +1,136 @@
+pub use crate::infra::mysql::session::{Session, get_session, get_user_sessions};
+
+use once_cell::sync::Lazy;
+use std::time::Duration;
+
+use crate::config::CFG;
+use crate::result::{AppError, AppResult};
+use crate::utils::ClientInfo;
+use crate::utils::cache::TtlCache;
+use crate::{infra, service, utils};
+
+const REFRESH_TOKEN_LEN: usize = 48;
+
+/// 缓存的最大会话数
+const CACHE_CAPACITY: usize = 10_000;
+
+/// 认证时使用的会话缓存，刷新 token 或撤销会话时失效
+static SESSION_CACHE: Lazy<TtlCache<u32, Session>> = Lazy::new(|| {
+    TtlCache::new(
+        Duration::from_secs(CFG.login.auth_cache_ttl),
+        CACHE_CAPACITY,
+    )
+});
+
+/// 带缓存的 get_session，只用于认证
+/// generation 为 access token 中的值，和缓存不一致时说明缓存已经过时，重新读取
+pub async fn get_cached_session(id: u32, generation: u32) -> AppResult<Option<Session>> {
+    if let Some(session) = SESSION_CACHE
+        .get(&id)
+        .filter(|s| s.generation == generation)
+    {
+        return Ok(Some(session));
+    }
+    let version = SESSION_CACHE.version();
+    let session = get_session(id).await?;
+    if let Some(session) = &session {
+        SESSION_CACHE.insert(id, session.clone(), version);
+    }
+    Ok(session)
+}
+
+pub async fn revoke_session(id: u32) -> AppResult<()> {
+    infra::mysql::session::revoke_session(id).await?;
+    SESSION_CACHE.invalidate(&id);
+    Ok(())
+}
+
+/// 撤销用户的所有会话，except 为需要保留的会话
+pub async fn revoke_user_sessions(user_id: u32, except: Option<u32>) -> AppResult<()> {
+    infra::mysql::session::revoke_user_sessions(user_id, except).await?;
+    // 缓存按会话 id 保存，不好确定该用户的会话，直接清空
+    SESSION_CACHE.invalidate_all();
+    Ok(())
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    // access token 的有效期，单位秒
+    pub expires_in: u64,
+}
+
+fn refresh_expires_at() -> chrono::NaiveDateTime {
+    utils::now_time() + chrono::Duration::seconds(CFG.jwt.refresh_token_ttl as i64)
+}
+
+/// refresh token 的格式为 `{会话 id}.{随机字符串}`，数据库中只保存随机字符串的哈希
+fn token_pair(
+    user_id: u32,
+    session_id: u32,
+    generation: u32,
+    secret: &str,
+) -> AppResult<TokenPair> {
+    Ok(TokenPair {
+        access_token: utils::auth::generate_token(user_id, session_id, generation)?,
+        refresh_token: format!("{}.{}", session_id, secret),
+        expires_in: CFG.jwt.access_token_ttl,
+    })
+}
+
+/// 新建会话并签发 token
+pub async fn create_session(user_id: u32, client: &ClientInfo) -> AppResult<TokenPair> {
+    let secret = utils::random_token(REFRESH_TOKEN_LEN);
+    let session_id = infra::mysql::session::add_session(
+        user_id,
+        &utils::sha256_hash(&secret),
+        &client.ip,
+        client.user_agent.as_deref(),
+        refresh_expires_at(),
+    )
+    .await?;
+    token_pair(user_id, session_id, 0, &secret)
+}
+
+/// 使用 refresh token 换取新的 token，refresh token 每次使用后都会轮换
+pub async fn refresh_session(refresh_token: &str, client: &ClientInfo) -> AppResult<TokenPair> {
+    let (session_id, secret) = refresh_token
+        .split_once('.')
+        .and_then(|(id, secret)| id.parse::<u32>().ok().map(|id| (id, secret)))
+        .ok_or(AppError::Unauthorized)?;
+    let Some(session) = get_session(session_id).await? else {
+        return Err(AppError::Unauthorized);
+    };
+    if !session.is_active() {
+        return Err(AppError::Unauthorized);
+    }
+    let old_hash = utils::sha256_hash(secret);
+    if session.refresh_token != old_hash {
+        // 已经轮换过的 refresh token 被再次使用，说明 token 可能已经泄露，直接撤销整个会话
+        revoke_session(session_id).await?;
+        return Err(AppError::Unauthorized);
+    }
+    let Some(user) = service::qnxg::user::get_user(session.user_id).await? else {
+        revoke_session(session_id).await?;
+        return Err(AppError::Unauthorized);
+    };
+    service::qnxg::user::ensure_can_login(&user)?;
+    let secret = utils::random_token(REFRESH_TOKEN_LEN);
+    let rotated = infra::mysql::session::rotate_session(
+        session_id,
+        &old_hash,
+        &utils::sha256_hash(&secret),
+        &client.ip,
+        client.user_agent.as_deref(),
+        refresh_expires_at(),
+    )
+    .await?;
+    SESSION_CACHE.invalidate(&session_id);
+    // 并发刷新时只有一个请求能成功
+    if !rotated {
+        return Err(AppError::Unauthorized);
+    }
+    token_pair(session.user_id, session_id, session.generation + 1, &secret)
+}
@@ -0,0 +1,237 @@
+use anyhow::anyhow;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::config::CFG;
+use crate::result::AppResult;
+use crate::service::qnxg::user::User;
+use crate::{infra, service, utils};
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpSetup {
+    // base32 编码的密钥，无法扫码时手动输入
+    pub secret: String,
+    // otpauth:// 链接，用于生成二维码
+    pub uri: String,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpStatus {
+    pub enabled: bool,
+    // 是否因为拥有的权限必须启用
+    pub required: bool,
+    pub recovery_codes_left: usize,
+}
+
+/// 密码验证通过后需要继续两步验证时返回
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TotpChallenge {
+    pub totp_required: bool,
+    // 必须启用但还没有启用，需要先获取密钥再提交验证码
+    pub setup_required: bool,
+    pub ticket: String,
+}
+
+fn totp(secret: &str, user: &User) -> AppResult<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow!("两步验证密钥格式错误: {:?}", e))?;
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(CFG.totp.issuer.clone()),
+        user.info.stu_id.clone(),
+    )
+    .map_err(|e| anyhow!("两步验证密钥格式错误: {:?}", e))?;
+    Ok(totp)
+}
+
+/// 查找验证码所在的周期，允许前后各一个周期的时钟偏差
+fn match_step(totp: &TOTP, code: &str, time: u64) -> Option<u64> {
+    let step = time / totp.step;
+    [step.saturating_sub(1), step, step + 1]
+        .into_iter()
+        .find(|s| totp.generate(s * totp.step) == code)
+}
+
+/// TOTP 验证码的校验结果
+#[derive(Debug, PartialEq, Eq)]
+enum CodeCheck {
+    // 可以使用，值为验证码所在的周期
+    Fresh(u64),
+    // 周期不大于上次使用的周期，验证码已经用过
+    Reused,
+    NotMatched,
+}
+
+fn check_code(totp: &TOTP, code: &str, time: u64, last_used_step: u64) -> CodeCheck {
+    match match_step(totp, code, time) {
+        Some(step) if step > last_used_step => CodeCheck::Fresh(step),
+        Some(_) => CodeCheck::Reused,
+        None => CodeCheck::NotMatched,
+    }
+}
+
+fn unix_time() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes = (0..RECOVERY_CODE_COUNT)
+        .map(|_| utils::random_token(10).to_lowercase())
+        .collect::<Vec<_>>();
+    let hashes = codes.iter().map(|c| utils::sha256_hash(c)).collect();
+    (codes, hashes)
+}
+
+/// 是否因为拥有的权限必须启用两步验证
+pub async fn is_required(user_id: u32) -> AppResult<bool> {
+    let permission = service::qnxg::user::get_user_permission(user_id).await?;
+    Ok(permission.is_admin()
+        || CFG
+            .totp
+            .enforce
+            .iter()
+            .any(|prefix| permission.has_any_under(prefix)))
+}
+
+pub async fn get_status(user_id: u32) -> AppResult<TotpStatus> {
+    let totp = infra::mysql::totp::get_user_totp(user_id)
+        .await?
+        .filter(|t| t.enabled);
+    Ok(TotpStatus {
+        enabled: totp.is_some(),
+        required: is_required(user_id).await?,
+        recovery_codes_left: totp.map(|t| t.recovery_codes.len()).unwrap_or_default(),
+    })
+}
+
+/// 生成新的密钥，输入一次验证码后才会启用
+pub async fn setup(user: &User) -> AppResult<TotpSetup> {
+    if infra::mysql::totp::get_user_totp(user.id)
+        .await?
+        .is_some_and(|t| t.enabled)
+    {
+        return Err(anyhow!("已经启用两步验证").into());
+    }
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let uri = totp(&secret, user)?.get_url();
+    infra::mysql::totp::set_pending_secret(user.id, &secret).await?;
+    Ok(TotpSetup { secret, uri })
+}
+
+/// 校验验证码并启用，返回恢复码明文，只会返回这一次
+pub async fn enable(user: &User, code: &str) -> AppResult<Vec<String>> {
+    let Some(pending) = infra::mysql::totp::get_user_totp(user.id).await? else {
+        return Err(anyhow!("请先获取两步验证密钥").into());
+    };
+    if pending.enabled {
+        return Err(anyhow!("已经启用两步验证").into());
+    }
+    let Some(step) = match_step(&totp(&pending.secret, user)?, code, unix_time()) else {
+        return Err(anyhow!("验证码错误").into());
+    };
+    infra::mysql::totp::use_step(user.id, step).await?;
+    let (codes, hashes) = generate_recovery_codes();
+    infra::mysql::totp::enable_totp(user.id, &hashes).await?;
+    Ok(codes)
+}
+
+/// 校验验证码或恢复码，恢复码只能使用一次
+pub async fn verify(user: &User, code: &str) -> AppResult<bool> {
+    let Some(current) = infra::mysql::totp::get_user_totp(user.id)
+        .await?
+        .filter(|t| t.enabled)
+    else {
+        return Ok(false);
+    };
+    let totp = totp(&current.secret, user)?;
+    match check_code(&totp, code, unix_time(), current.last_used_step) {
+        CodeCheck::Fresh(step) => infra::mysql::totp::use_step(user.id, step).await,
+        CodeCheck::Reused => Ok(false),
+        CodeCheck::NotMatched => {
+            infra::mysql::totp::use_recovery_code(
+                user.id,
+                &utils::sha256_hash(&code.to_lowercase()),
+            )
+            .await
+        }
+    }
+}
+
+pub async fn regenerate_recovery_codes(user_id: u32) -> AppResult<Vec<String>> {
+    let (codes, hashes) = generate_recovery_codes();
+    infra::mysql::totp::enable_totp(user_id, &hashes).await?;
+    Ok(codes)
+}
+
+pub async fn disable(user_id: u32) -> AppResult<()> {
+    infra::mysql::totp::delete_user_totp(user_id).await
+}
+
+/// 密码验证通过后判断是否需要两步验证
+pub async fn challenge(user_id: u32) -> AppResult<Option<TotpChallenge>> {
+    let enabled = infra::mysql::totp::get_user_totp(user_id)
+        .await?
+        .is_some_and(|t| t.enabled);
+    if !enabled && !is_required(user_id).await? {
+        return Ok(None);
+    }
+    Ok(Some(TotpChallenge {
+        totp_required: true,
+        setup_required: !enabled,
+        ticket: utils::auth::generate_totp_ticket(user_id)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_totp() -> TOTP {
+        TOTP::new_unchecked(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            b"12345678901234567890".to_vec(),
+            None,
+            "test".into(),
+        )
+    }
+
+    #[test]
+    fn test_match_step() {
+        let totp = test_totp();
+        let time = 1_700_000_000;
+        let code = totp.generate(time);
+        assert_eq!(match_step(&totp, &code, time), Some(time / 30));
+        assert_eq!(match_step(&totp, &code, time + 30), Some(time / 30));
+        assert_eq!(match_step(&totp, &code, time + 90), None);
+    }
+
+    #[test]
+    fn test_check_code_rejects_reused_step() {
+        let totp = test_totp();
+        let time = 1_700_000_000;
+        let step = time / 30;
+        let code = totp.generate(time);
+        assert_eq!(
+            check_code(&totp, &code, time, step - 1),
+            CodeCheck::Fresh(step)
+        );
+        // 同一个验证码在有效期内再次提交
+        assert_eq!(check_code(&totp, &code, time, step), CodeCheck::Reused);
+        assert_eq!(check_code(&totp, &code, time + 30, step), CodeCheck::Reused);
+        // 已经使用过更新的验证码后，旧的验证码也不能使用
+        assert_eq!(check_code(&totp, &code, time, step + 1), CodeCheck::Reused);
+        assert_eq!(check_code(&totp, "abcdef", time, 0), CodeCheck::NotMatched);
+    }
+}
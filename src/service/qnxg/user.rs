@@ -1,27 +1,184 @@
+use crate::config::CFG;
+use crate::infra;
 pub use crate::infra::mysql::user::{
-    User, UserBasicInfo, UserStatus, delete_user, get_user, get_user_by_stu_id, get_user_list,
-    get_user_password, update_user,
+    User, UserBasicInfo, UserStatus, get_user, get_user_by_stu_id, get_user_list, get_user_password,
 };
+use crate::result::{AppError, AppResult};
 pub use crate::service::qnxg::permission::Permission;
 use crate::service::qnxg::role::get_role_permission;
 pub use crate::service::qnxg::role::get_user_roles;
-use crate::{infra, result::AppResult};
+use crate::utils::cache::TtlCache;
+use crate::utils::password::PasswordVerification;
 use crate::{service, utils};
+use anyhow::anyhow;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+
+/// 缓存的最大用户数
+const CACHE_CAPACITY: usize = 10_000;
+
+/// 用户权限缓存，角色或权限变更时失效
+/// 多实例部署时其他实例上的缓存只能等待过期
+static PERMISSION_CACHE: Lazy<TtlCache<u32, Permission>> = Lazy::new(|| {
+    TtlCache::new(
+        Duration::from_secs(CFG.permission.cache_ttl),
+        CACHE_CAPACITY,
+    )
+});
+
+/// 认证时使用的用户缓存，修改用户数据时失效
+static USER_CACHE: Lazy<TtlCache<u32, User>> = Lazy::new(|| {
+    TtlCache::new(
+        Duration::from_secs(CFG.login.auth_cache_ttl),
+        CACHE_CAPACITY,
+    )
+});
+
+/// 带缓存的 get_user，只用于认证，不存在的用户不缓存
+pub async fn get_cached_user(user_id: u32) -> AppResult<Option<User>> {
+    if let Some(user) = USER_CACHE.get(&user_id) {
+        return Ok(Some(user));
+    }
+    let version = USER_CACHE.version();
+    let user = get_user(user_id).await?;
+    if let Some(user) = &user {
+        USER_CACHE.insert(user_id, user.clone(), version);
+    }
+    Ok(user)
+}
+
+/// 用户数据变更后调用
+pub fn invalidate_user(user_id: u32) {
+    USER_CACHE.invalidate(&user_id);
+}
+
+/// 批量修改用户后调用
+pub fn invalidate_all_user() {
+    USER_CACHE.invalidate_all();
+}
 
 pub async fn get_user_permission(user_id: u32) -> AppResult<Permission> {
+    if let Some(permission) = PERMISSION_CACHE.get(&user_id) {
+        return Ok(permission);
+    }
+    let version = PERMISSION_CACHE.version();
     let role_id = get_user_roles(user_id)
         .await?
         .into_iter()
         .map(|r| r.id)
         .collect::<Vec<_>>();
     let permission = get_role_permission(&role_id).await?;
+    PERMISSION_CACHE.insert(user_id, permission.clone(), version);
     Ok(permission)
 }
 
+/// 用户的角色变更后调用
+pub fn invalidate_user_permission(user_id: u32) {
+    PERMISSION_CACHE.invalidate(&user_id);
+}
+
+/// 角色或权限本身变更后调用，影响的用户不好确定，直接清空
+pub fn invalidate_all_user_permission() {
+    PERMISSION_CACHE.invalidate_all();
+}
+
+/// 用户状态是否允许登录
+pub fn can_login(status: UserStatus) -> bool {
+    CFG.login.allowed_status.contains(&u32::from(status))
+}
+
+/// 用户状态不允许访问时返回具体原因，API token 访问时也需要检查
+pub fn ensure_active(user: &User) -> AppResult<()> {
+    if !can_login(user.info.status) {
+        return Err(AppError::LoginBlocked(format!(
+            "账号状态为{}，不能登录",
+            user.info.status.label()
+        )));
+    }
+    Ok(())
+}
+
+/// 用户不能登录时返回具体原因，服务账号只能使用 API token
+pub fn ensure_can_login(user: &User) -> AppResult<()> {
+    ensure_active(user)?;
+    if user.service_account {
+        return Err(AppError::LoginBlocked(
+            "服务账号不能登录，请使用 API token".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// 更新用户信息，可以同时修改密码和永久角色，所有修改在同一个事务中完成
+/// 状态变为不允许登录或者修改了密码时撤销其所有会话，不允许登录时同时撤销 API token
+/// version 为读取时的 updatedAt，password 为明文，为 None 时不修改
+pub async fn update_user(
+    user_id: u32,
+    info: &UserBasicInfo,
+    version: chrono::NaiveDateTime,
+    password: Option<&str>,
+    role_id: Option<&[u32]>,
+) -> AppResult<()> {
+    // 哈希比较耗时，放在事务之外
+    let password = match password {
+        Some(password) => Some(hash_password(password).await?),
+        None => None,
+    };
+    let blocked = !can_login(info.status);
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    // 合并后的变更一起检查，被拒绝时不会写入任何数据
+    if blocked || role_id.is_some() {
+        service::qnxg::role::ensure_admin_remains(&mut tx, |s| {
+            if blocked {
+                s.remove_user(user_id);
+            }
+            if let Some(role_id) = role_id {
+                s.set_user_roles(user_id, role_id);
+            }
+        })
+        .await?;
+    }
+    infra::mysql::user::update_user(&mut tx, user_id, info, version).await?;
+    if let Some(password) = &password {
+        infra::mysql::user::update_user_password(&mut tx, user_id, password).await?;
+    }
+    if let Some(role_id) = role_id {
+        infra::mysql::role::update_user_roles(&mut tx, user_id, role_id).await?;
+    }
+    tx.commit().await?;
+    invalidate_user(user_id);
+    if role_id.is_some() {
+        invalidate_user_permission(user_id);
+    }
+    if blocked || password.is_some() {
+        service::qnxg::session::revoke_user_sessions(user_id, None).await?;
+    }
+    if blocked {
+        service::qnxg::api_token::revoke_user_api_tokens(user_id).await?;
+    }
+    Ok(())
+}
+
+/// 哈希比较耗时，放到阻塞线程池中执行
+async fn hash_password(password: &str) -> AppResult<String> {
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || utils::password::hash_password(&password))
+        .await
+        .map_err(|e| anyhow!("密码哈希失败: {}", e))?
+}
+
 /// password 参数为明文
 pub async fn add_user(info: &UserBasicInfo, password: &str, role_id: &[u32]) -> AppResult<u32> {
-    let password = utils::md5_hash(password);
-    let user_id = infra::mysql::user::add_user(info, &password).await?;
+    let password = hash_password(password).await?;
+    let user_id = infra::mysql::user::add_user(info, &password, false).await?;
+    service::qnxg::role::update_user_roles(user_id, role_id).await?;
+    Ok(user_id)
+}
+
+/// 添加服务账号，密码是随机生成的，不会告诉任何人
+pub async fn add_service_account(info: &UserBasicInfo, role_id: &[u32]) -> AppResult<u32> {
+    let password = hash_password(&utils::random_token(64)).await?;
+    let user_id = infra::mysql::user::add_user(info, &password, true).await?;
     service::qnxg::role::update_user_roles(user_id, role_id).await?;
     Ok(user_id)
 }
@@ -29,11 +186,64 @@ pub async fn add_user(info: &UserBasicInfo, password: &str, role_id: &[u32]) ->
 /// 更新用户密码
 /// password 参数为明文
 pub async fn update_user_password(user_id: u32, password: &str) -> AppResult<()> {
-    let password = utils::md5_hash(password);
-    infra::mysql::user::update_user_password(user_id, &password).await?;
+    let password = hash_password(password).await?;
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    infra::mysql::user::update_user_password(&mut tx, user_id, &password).await?;
+    tx.commit().await?;
+    invalidate_user(user_id);
+    Ok(())
+}
+
+/// 修改用户密码并撤销该用户的其他会话
+/// password 参数为明文，keep_session 为需要保留的会话（例如用户自己修改密码时的当前会话）
+pub async fn change_user_password(
+    user_id: u32,
+    password: &str,
+    keep_session: Option<u32>,
+) -> AppResult<()> {
+    update_user_password(user_id, password).await?;
+    service::qnxg::session::revoke_user_sessions(user_id, keep_session).await?;
     Ok(())
 }
 
+/// 删除用户并撤销其所有会话和 API token
+pub async fn delete_user(user_id: u32) -> AppResult<()> {
+    let mut tx = infra::mysql::get_yqwork_pool().await.begin().await?;
+    service::qnxg::role::ensure_admin_remains(&mut tx, |s| s.remove_user(user_id)).await?;
+    infra::mysql::user::delete_user(&mut tx, user_id).await?;
+    tx.commit().await?;
+    invalidate_user(user_id);
+    service::qnxg::session::revoke_user_sessions(user_id, None).await?;
+    service::qnxg::api_token::revoke_user_api_tokens(user_id).await?;
+    invalidate_user_permission(user_id);
+    Ok(())
+}
+
+/// 校验用户密码，password 参数为明文
+/// 旧的 MD5 密码或者哈希参数已经过时的密码在校验通过后会被透明地重新哈希
+pub async fn verify_user_password(user_id: u32, password: &str) -> AppResult<bool> {
+    let Some(stored) = get_user_password(user_id).await? else {
+        return Ok(false);
+    };
+    let verification = {
+        let password = password.to_string();
+        let stored = stored.clone();
+        tokio::task::spawn_blocking(move || utils::password::verify_password(&password, &stored))
+            .await
+            .map_err(|e| anyhow!("密码校验失败: {}", e))?
+    };
+    match verification {
+        PasswordVerification::Mismatch => Ok(false),
+        PasswordVerification::Match => Ok(true),
+        PasswordVerification::MatchNeedsRehash => {
+            let password = hash_password(password).await?;
+            infra::mysql::user::rehash_user_password(user_id, &stored, &password).await?;
+            invalidate_user(user_id);
+            Ok(true)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -27,10 +27,13 @@ pub struct WorkInclude {
     pub user: User,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct WorkHourTableItem {
     id: u32,
     includes: Vec<infra::mysql::work_hour::WorkInclude>,
+    // 获取工时表时记录的 updatedAt，记录在这之后被修改过时拒绝保存
+    updated_at: Option<chrono::NaiveDateTime>,
 }
 
 impl WorkInclude {
@@ -102,10 +105,12 @@ pub async fn get_my_work_hour_record(
     }))
 }
 
+/// version 为已有记录的 updatedAt，还没有记录时为 None
 pub async fn submit_work_hour_record(
     work_hour_id: u32,
     user_id: u32,
     descs: &Vec<WorkDesc>,
+    version: Option<chrono::NaiveDateTime>,
 ) -> AppResult<u32> {
     let res = infra::mysql::work_hour::update_work_hour_record(
         work_hour_id,
@@ -114,6 +119,7 @@ pub async fn submit_work_hour_record(
         None,
         None,
         WorkHourRecordStatus::PendingApproval,
+        version,
     )
     .await?;
     Ok(res)
@@ -181,6 +187,7 @@ pub async fn accept_work_hour_record(record: &WorkHourRecord) -> AppResult<()> {
         ),
         None,
         next_status,
+        Some(record.info.updated_at),
     )
     .await?;
     Ok(())
@@ -224,6 +231,7 @@ pub async fn reject_work_hour_record(record: &WorkHourRecord, comment: &str) ->
         ),
         Some(comment),
         WorkHourRecordStatus::Unsubmitted,
+        Some(record.info.updated_at),
     )
     .await?;
     Ok(())
@@ -245,6 +253,7 @@ pub async fn close_work_hour_record(record: &WorkHourRecord) -> AppResult<()> {
         ),
         None,
         WorkHourRecordStatus::Closed,
+        Some(record.info.updated_at),
     )
     .await?;
     Ok(())
@@ -264,6 +273,7 @@ pub async fn save_work_hour_table(delta: &Vec<WorkHourTableItem>) -> AppResult<(
             Some(item.includes.as_ref()),
             record.comment.as_deref(),
             record.status,
+            Some(item.updated_at.unwrap_or(record.updated_at)),
         )
         .await?;
     }
@@ -32,7 +32,7 @@ pub async fn add_record(
     delta: i32,
     reason: &str,
 ) -> AppResult<u32> {
-    let conn = infra::mysql::get_db_pool().await;
+    let conn = infra::mysql::get_weihuda_pool().await;
     let mut tx = conn.begin().await?;
     let res = add_record_with_tx(&mut tx, update_by, stu_id, delta, reason).await?;
     tx.commit().await?;
@@ -49,7 +49,7 @@ pub async fn receive_goods(id: u32) -> AppResult<()> {
     Ok(())
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AddRecordBatchItem {
     pub stu_id: String,
@@ -57,7 +57,7 @@ pub struct AddRecordBatchItem {
     pub desc: String,
 }
 pub async fn add_record_batch(items: Vec<AddRecordBatchItem>, update_by: &User) -> AppResult<()> {
-    let conn = infra::mysql::get_db_pool().await;
+    let conn = infra::mysql::get_weihuda_pool().await;
     let mut tx = conn.begin().await?;
     for item in items {
         add_record_with_tx(&mut tx, update_by, &item.stu_id, item.delta, &item.desc).await?;
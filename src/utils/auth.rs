@@ -1,50 +1,199 @@
 use crate::config::CFG;
 use crate::result::{AppError, AppResult};
-use crate::service;
+use crate::service::qnxg::api_token::ApiToken;
+use crate::service::qnxg::permission::Permission;
 use crate::service::qnxg::user::User;
-use anyhow::anyhow;
+use crate::utils::ClientInfo;
+use crate::{service, utils};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
 struct Payload {
     pub id: u32,
     pub exp: usize,
+    // 会话 id
+    pub sid: u32,
+    // 会话的 generation，刷新 token 后旧的 access token 会失效
+    pub generation: u32,
+    // 模拟登录时为发起模拟的管理员 id，id 为被模拟的用户，sid 为管理员的会话
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub imp: Option<u32>,
 }
 
-pub async fn parse_token(req: &mut salvo::Request) -> AppResult<User> {
+/// 密码验证通过、等待两步验证的登录凭据
+#[derive(serde::Deserialize, serde::Serialize, Debug)]
+struct TicketPayload {
+    pub id: u32,
+    pub exp: usize,
+    // 凭据用途，避免和其他 token 混用
+    pub purpose: String,
+}
+
+const TOTP_TICKET_PURPOSE: &str = "totp";
+
+/// 通过认证的请求信息
+pub struct AuthInfo {
+    pub user: User,
+    // 使用 API token 或模拟登录时为空
+    pub session_id: Option<u32>,
+    pub api_token: Option<ApiToken>,
+    // 模拟登录时为发起模拟的管理员，user 为被模拟的用户
+    pub impersonator: Option<User>,
+    pub client: ClientInfo,
+}
+
+async fn authenticate_api_token(token: &str, client: ClientInfo) -> AppResult<AuthInfo> {
+    let api_token = service::qnxg::api_token::authenticate(token, &client).await?;
+    let Some(user) = service::qnxg::user::get_cached_user(api_token.user_id).await? else {
+        return Err(AppError::Unauthorized);
+    };
+    service::qnxg::user::ensure_active(&user)?;
+    Ok(AuthInfo {
+        user,
+        session_id: None,
+        api_token: Some(api_token),
+        impersonator: None,
+        client,
+    })
+}
+
+pub async fn authenticate(req: &mut salvo::Request) -> AppResult<AuthInfo> {
     let token = req
         .headers()
         .get("Authorization")
         .ok_or(AppError::Unauthorized)?
         .to_str()
         .map_err(|_| AppError::Unauthorized)?;
-    let res = jsonwebtoken::decode::<Payload>(
-        token,
-        &jsonwebtoken::DecodingKey::from_secret(CFG.jwt.secret.as_bytes()),
-        &jsonwebtoken::Validation::default(),
-    )
-    .map_err(|_| AppError::Unauthorized)?;
-    let user_id = res.claims.id;
-    let Some(user) = service::qnxg::user::get_user(user_id).await? else {
+    if service::qnxg::api_token::is_api_token(token) {
+        return authenticate_api_token(token, utils::client_info(req)).await;
+    }
+    let Payload {
+        id,
+        sid,
+        generation,
+        imp,
+        ..
+    } = utils::jwt::decode(token)?;
+    if let Some(imp) = imp {
+        return authenticate_impersonation(id, imp, sid, utils::client_info(req)).await;
+    }
+    // 会话被撤销、过期或者 token 已经被刷新取代时拒绝
+    let Some(session) = service::qnxg::session::get_cached_session(sid, generation).await? else {
+        return Err(AppError::Unauthorized);
+    };
+    if session.user_id != id || !session.is_active() || session.generation != generation {
+        return Err(AppError::Unauthorized);
+    }
+    let Some(user) = service::qnxg::user::get_cached_user(id).await? else {
+        return Err(AppError::Unauthorized);
+    };
+    service::qnxg::user::ensure_can_login(&user)?;
+    Ok(AuthInfo {
+        user,
+        session_id: Some(sid),
+        api_token: None,
+        impersonator: None,
+        client: utils::client_info(req),
+    })
+}
+
+/// 模拟登录的 token 依附于管理员的会话，管理员退出登录后同时失效
+/// 每次请求都重新检查管理员是否仍然可以模拟该用户
+async fn authenticate_impersonation(
+    user_id: u32,
+    impersonator_id: u32,
+    session_id: u32,
+    client: ClientInfo,
+) -> AppResult<AuthInfo> {
+    let Some(session) = service::qnxg::session::get_session(session_id).await? else {
+        return Err(AppError::Unauthorized);
+    };
+    if session.user_id != impersonator_id || !session.is_active() {
+        return Err(AppError::Unauthorized);
+    }
+    let (Some(impersonator), Some(user)) = (
+        service::qnxg::user::get_user(impersonator_id).await?,
+        service::qnxg::user::get_user(user_id).await?,
+    ) else {
         return Err(AppError::Unauthorized);
     };
-    Ok(user)
+    service::qnxg::user::ensure_can_login(&impersonator)?;
+    service::qnxg::impersonation::ensure_can_impersonate(&impersonator, &user).await?;
+    Ok(AuthInfo {
+        user,
+        session_id: None,
+        api_token: None,
+        impersonator: Some(impersonator),
+        client,
+    })
+}
+
+/// 获取访问策略检查时写入 depot 的认证信息，公开路由中不存在
+pub fn current_auth(depot: &salvo::Depot) -> AppResult<&AuthInfo> {
+    depot
+        .obtain::<AuthInfo>()
+        .map_err(|_| AppError::Unauthorized)
+}
+
+pub fn current_user(depot: &salvo::Depot) -> AppResult<&User> {
+    Ok(&current_auth(depot)?.user)
 }
 
-pub fn generate_token(id: u32) -> AppResult<String> {
-    let now = SystemTime::now()
+pub fn current_permission(depot: &salvo::Depot) -> AppResult<&Permission> {
+    depot
+        .obtain::<Permission>()
+        .map_err(|_| AppError::Unauthorized)
+}
+
+fn timestamp() -> usize {
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards")
-        .as_secs() as usize;
+        .as_secs() as usize
+}
+
+pub fn generate_token(id: u32, session_id: u32, generation: u32) -> AppResult<String> {
+    let now = timestamp();
     let payload = Payload {
         id,
-        exp: now + 60 * 60 * 24, // 1 天过期
-    };
-    let token = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &payload,
-        &jsonwebtoken::EncodingKey::from_secret(CFG.jwt.secret.as_bytes()),
-    )
-    .map_err(|e| AppError::from(anyhow!("生成 token 失败: {}", e)))?;
-    Ok(token)
+        exp: now + CFG.jwt.access_token_ttl as usize,
+        sid: session_id,
+        generation,
+        imp: None,
+    };
+    utils::jwt::encode(&payload)
+}
+
+/// 生成模拟登录的 token，session_id 为管理员的会话
+pub fn generate_impersonation_token(
+    user_id: u32,
+    impersonator_id: u32,
+    session_id: u32,
+) -> AppResult<String> {
+    let payload = Payload {
+        id: user_id,
+        exp: timestamp() + CFG.jwt.impersonation_ttl as usize,
+        sid: session_id,
+        generation: 0,
+        imp: Some(impersonator_id),
+    };
+    utils::jwt::encode(&payload)
+}
+
+pub fn generate_totp_ticket(user_id: u32) -> AppResult<String> {
+    let payload = TicketPayload {
+        id: user_id,
+        exp: timestamp() + CFG.totp.ticket_ttl as usize,
+        purpose: TOTP_TICKET_PURPOSE.to_string(),
+    };
+    utils::jwt::encode(&payload)
+}
+
+/// 校验两步验证的登录凭据，返回用户 id
+pub fn parse_totp_ticket(ticket: &str) -> AppResult<u32> {
+    let claims: TicketPayload = utils::jwt::decode(ticket)?;
+    if claims.purpose != TOTP_TICKET_PURPOSE {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(claims.id)
 }
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// 带过期时间和容量上限的进程内缓存
+/// 每次失效都会递增版本号，加载开始之后发生过失效的数据不会被写入，避免把旧数据重新放回缓存
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    inner: RwLock<Inner<K, V>>,
+}
+
+struct Inner<K, V> {
+    version: u64,
+    entries: HashMap<K, (Instant, V)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            inner: RwLock::new(Inner {
+                version: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        inner
+            .entries
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < self.ttl)
+            .map(|(_, v)| v.clone())
+    }
+
+    /// 当前版本号，在加载数据之前获取，写入时传给 insert
+    pub fn version(&self) -> u64 {
+        self.inner.read().unwrap_or_else(|e| e.into_inner()).version
+    }
+
+    /// 缓存已满时先清理过期的数据，仍然满时淘汰写入最早的一条
+    pub fn insert(&self, key: K, value: V, version: u64) {
+        if self.ttl.is_zero() || self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        if inner.version != version {
+            return;
+        }
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            let ttl = self.ttl;
+            inner.entries.retain(|_, (at, _)| at.elapsed() < ttl);
+        }
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (at, _))| *at)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+            }
+        }
+        inner.entries.insert(key, (Instant::now(), value));
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        inner.version += 1;
+        inner.entries.remove(key);
+    }
+
+    pub fn invalidate_all(&self) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        inner.version += 1;
+        inner.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_insert_is_dropped() {
+        let cache = TtlCache::new(Duration::from_secs(60), 10);
+        let version = cache.version();
+        cache.invalidate_all();
+        cache.insert(1, "stale", version);
+        assert_eq!(cache.get(&1), None);
+        cache.insert(1, "fresh", cache.version());
+        assert_eq!(cache.get(&1), Some("fresh"));
+        cache.invalidate(&1);
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_oldest_entry_is_evicted_when_full() {
+        let cache = TtlCache::new(Duration::from_secs(60), 2);
+        cache.insert(1, "a", cache.version());
+        std::thread::sleep(Duration::from_millis(2));
+        cache.insert(2, "b", cache.version());
+        // 更新已有的数据不会淘汰其他数据
+        cache.insert(2, "c", cache.version());
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "d", cache.version());
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some("c"));
+        assert_eq!(cache.get(&3), Some("d"));
+    }
+
+    #[test]
+    fn test_expired_entries_are_purged_first() {
+        let cache = TtlCache::new(Duration::from_millis(20), 2);
+        cache.insert(1, "a", cache.version());
+        cache.insert(2, "b", cache.version());
+        std::thread::sleep(Duration::from_millis(30));
+        cache.insert(3, "c", cache.version());
+        assert_eq!(cache.inner.read().unwrap().entries.len(), 1);
+        assert_eq!(cache.get(&3), Some("c"));
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use once_cell::sync::Lazy;
+
+use crate::config::{CFG, Jwt, JwtKey};
+use crate::result::{AppError, AppResult};
+
+/// 配置中的所有 JWT 密钥
+struct JwtKeys {
+    // 签发使用的密钥，kid 为空说明使用旧的 secret
+    signing: (Option<String>, Algorithm, EncodingKey),
+    verifying: HashMap<String, (Algorithm, DecodingKey)>,
+    // 旧的 secret，用于校验没有 kid 的 token
+    legacy: Option<DecodingKey>,
+}
+
+fn read_pem(path: &str) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).map_err(|e| anyhow!("读取密钥文件 {} 失败: {}", path, e))
+}
+
+fn encoding_key(key: &JwtKey) -> anyhow::Result<EncodingKey> {
+    let res = match key.algorithm {
+        Algorithm::HS256 => EncodingKey::from_secret(key.secret.as_bytes()),
+        Algorithm::RS256 => EncodingKey::from_rsa_pem(&read_pem(&key.private_key)?)?,
+        Algorithm::EdDSA => EncodingKey::from_ed_pem(&read_pem(&key.private_key)?)?,
+        alg => return Err(anyhow!("不支持的 JWT 算法: {:?}", alg)),
+    };
+    Ok(res)
+}
+
+fn decoding_key(key: &JwtKey) -> anyhow::Result<DecodingKey> {
+    let res = match key.algorithm {
+        Algorithm::HS256 => DecodingKey::from_secret(key.secret.as_bytes()),
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(&read_pem(&key.public_key)?)?,
+        Algorithm::EdDSA => DecodingKey::from_ed_pem(&read_pem(&key.public_key)?)?,
+        alg => return Err(anyhow!("不支持的 JWT 算法: {:?}", alg)),
+    };
+    Ok(res)
+}
+
+impl JwtKeys {
+    fn from_config(cfg: &Jwt) -> anyhow::Result<Self> {
+        let mut verifying = HashMap::new();
+        for key in &cfg.keys {
+            if key.algorithm == Algorithm::HS256 && key.secret.is_empty() {
+                return Err(anyhow!("JWT 密钥 {} 没有配置 secret", key.kid));
+            }
+            if verifying
+                .insert(key.kid.clone(), (key.algorithm, decoding_key(key)?))
+                .is_some()
+            {
+                return Err(anyhow!("JWT 密钥 {} 重复", key.kid));
+            }
+        }
+        let signing = if cfg.active_kid.is_empty() {
+            if cfg.secret.is_empty() {
+                return Err(anyhow!("没有配置 JWT 签发密钥"));
+            }
+            (
+                None,
+                Algorithm::HS256,
+                EncodingKey::from_secret(cfg.secret.as_bytes()),
+            )
+        } else {
+            let key = cfg
+                .keys
+                .iter()
+                .find(|k| k.kid == cfg.active_kid)
+                .ok_or(anyhow!("JWT 密钥 {} 不存在", cfg.active_kid))?;
+            (Some(key.kid.clone()), key.algorithm, encoding_key(key)?)
+        };
+        let legacy =
+            (!cfg.secret.is_empty()).then(|| DecodingKey::from_secret(cfg.secret.as_bytes()));
+        Ok(Self {
+            signing,
+            verifying,
+            legacy,
+        })
+    }
+
+    fn encode<T: serde::Serialize>(&self, claims: &T) -> anyhow::Result<String> {
+        let (kid, alg, key) = &self.signing;
+        let mut header = Header::new(*alg);
+        header.kid = kid.clone();
+        Ok(jsonwebtoken::encode(&header, claims, key)?)
+    }
+
+    /// 按 header 中的 kid 选择密钥，算法必须和密钥一致
+    fn decode<T: serde::de::DeserializeOwned>(&self, token: &str) -> Option<T> {
+        let header = jsonwebtoken::decode_header(token).ok()?;
+        let (alg, key) = match &header.kid {
+            Some(kid) => {
+                let (alg, key) = self.verifying.get(kid)?;
+                (*alg, key)
+            }
+            None => (Algorithm::HS256, self.legacy.as_ref()?),
+        };
+        if header.alg != alg {
+            return None;
+        }
+        jsonwebtoken::decode::<T>(token, key, &Validation::new(alg))
+            .ok()
+            .map(|data| data.claims)
+    }
+}
+
+static KEYS: Lazy<JwtKeys> =
+    Lazy::new(|| JwtKeys::from_config(&CFG.jwt).expect("加载 JWT 密钥失败"));
+
+/// 启动时加载密钥，配置错误时直接退出
+pub fn init() {
+    Lazy::force(&KEYS);
+}
+
+/// 使用当前的签发密钥生成 token
+pub fn encode<T: serde::Serialize>(claims: &T) -> AppResult<String> {
+    KEYS.encode(claims)
+        .map_err(|e| AppError::from(anyhow!("生成 token 失败: {}", e)))
+}
+
+/// 校验 token 的签名和有效期，失败时返回 Unauthorized
+pub fn decode<T: serde::de::DeserializeOwned>(token: &str) -> AppResult<T> {
+    KEYS.decode(token).ok_or(AppError::Unauthorized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Claims {
+        id: u32,
+        exp: usize,
+    }
+
+    fn hs256(kid: &str, secret: &str) -> JwtKey {
+        JwtKey {
+            kid: kid.into(),
+            algorithm: Algorithm::HS256,
+            secret: secret.into(),
+            private_key: String::new(),
+            public_key: String::new(),
+        }
+    }
+
+    fn config(secret: &str, active_kid: &str, keys: Vec<JwtKey>) -> Jwt {
+        Jwt {
+            secret: secret.into(),
+            active_kid: active_kid.into(),
+            keys,
+            access_token_ttl: 0,
+            refresh_token_ttl: 0,
+            impersonation_ttl: 0,
+        }
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let claims = Claims {
+            id: 1,
+            exp: 4_000_000_000,
+        };
+        let legacy = JwtKeys::from_config(&config("old", "", vec![])).unwrap();
+        let legacy_token = legacy.encode(&claims).unwrap();
+        let first =
+            JwtKeys::from_config(&config("old", "a", vec![hs256("a", "secret-a")])).unwrap();
+        let first_token = first.encode(&claims).unwrap();
+        let second = JwtKeys::from_config(&config(
+            "old",
+            "b",
+            vec![hs256("a", "secret-a"), hs256("b", "secret-b")],
+        ))
+        .unwrap();
+        let second_token = second.encode(&claims).unwrap();
+
+        // 轮换后旧密钥签发的 token 仍然有效
+        assert!(second.decode::<Claims>(&legacy_token).is_some());
+        assert_eq!(second.decode::<Claims>(&first_token), Some(claims));
+        assert!(second.decode::<Claims>(&second_token).is_some());
+        // 移除旧密钥后失效
+        let removed = JwtKeys::from_config(&config("", "b", vec![hs256("b", "secret-b")])).unwrap();
+        assert!(removed.decode::<Claims>(&legacy_token).is_none());
+        assert!(removed.decode::<Claims>(&first_token).is_none());
+        assert!(removed.decode::<Claims>(&second_token).is_some());
+        // 不认识的 kid 不能通过校验
+        assert!(first.decode::<Claims>(&second_token).is_none());
+    }
+}
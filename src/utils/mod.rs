@@ -1,15 +1,130 @@
 pub mod auth;
+pub mod cache;
+pub mod jwt;
+pub mod password;
 
-/// 获得当前时间（UTC+8）
+use std::net::IpAddr;
+
+use crate::config::CFG;
+
+/// 获得当前时间（UTC+8），精确到微秒，和数据库中 DATETIME(6) 的精度一致
 pub fn now_time() -> chrono::NaiveDateTime {
+    use chrono::SubsecRound;
     let utc_now = chrono::Utc::now();
-    utc_now.naive_utc() + chrono::Duration::hours(8)
+    (utc_now.naive_utc() + chrono::Duration::hours(8)).trunc_subsecs(6)
 }
 
 pub fn md5_hash(input: &str) -> String {
     format!("{:x}", md5::compute(input.as_bytes()))
 }
 
+pub fn sha256_hash(input: &str) -> String {
+    use sha2::Digest;
+    format!("{:x}", sha2::Sha256::digest(input.as_bytes()))
+}
+
+/// 生成指定长度的随机字符串（字母和数字），用于各类不透明的 token
+pub fn random_token(len: usize) -> String {
+    use rand::Rng;
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// 请求方的客户端信息
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: Option<String>,
+}
+
+/// 获取请求方的 IP 和 User-Agent
+/// 转发头可以被客户端伪造，只有直接来源是受信任的反向代理时才使用
+pub fn client_info(req: &salvo::Request) -> ClientInfo {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let addr = req.remote_addr();
+    let remote = addr
+        .as_ipv4()
+        .map(|a| IpAddr::V4(*a.ip()))
+        .or_else(|| addr.as_ipv6().map(|a| IpAddr::V6(*a.ip())));
+    let ip = resolve_client_ip(
+        remote,
+        header("X-Forwarded-For").as_deref(),
+        header("X-Real-IP").as_deref(),
+        &CFG.server.trusted_proxies,
+    );
+    ClientInfo {
+        ip,
+        user_agent: header("User-Agent"),
+    }
+}
+
+/// 从右向左跳过受信任的代理，第一个不受信任的地址即为客户端
+/// 遇到无法解析的地址时停止，全部是受信任的代理或者无法确定时使用连接地址
+fn resolve_client_ip(
+    remote: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    real_ip: Option<&str>,
+    trusted: &[String],
+) -> String {
+    let Some(remote) = remote else {
+        return String::new();
+    };
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|net| ip_in_net(ip, net));
+    if !is_trusted(remote) {
+        return remote.to_string();
+    }
+    if let Some(forwarded_for) = forwarded_for {
+        let hops = forwarded_for
+            .split(',')
+            .map(|v| v.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+        for hop in hops.into_iter().rev() {
+            match hop {
+                Some(ip) if is_trusted(ip) => continue,
+                Some(ip) => return ip.to_string(),
+                None => break,
+            }
+        }
+        return remote.to_string();
+    }
+    real_ip
+        .and_then(|v| v.trim().parse::<IpAddr>().ok())
+        .unwrap_or(remote)
+        .to_string()
+}
+
+/// net 为单个 IP 或者 CIDR，格式错误时视为不匹配
+fn ip_in_net(ip: IpAddr, net: &str) -> bool {
+    let (addr, prefix) = match net.split_once('/') {
+        Some((addr, prefix)) => (addr, prefix.parse::<u32>().ok()),
+        None => (net, None),
+    };
+    let Ok(addr) = addr.trim().parse::<IpAddr>() else {
+        return false;
+    };
+    match (ip, addr) {
+        (IpAddr::V4(ip), IpAddr::V4(addr)) => {
+            let prefix = prefix.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(addr) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(addr)) => {
+            let prefix = prefix.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(addr) & mask
+        }
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -19,4 +134,63 @@ mod tests {
         let hash = md5_hash(input);
         assert_eq!(hash, "098f6bcd4621d373cade4e832627b4f6");
     }
+
+    #[test]
+    fn test_sha256_hash() {
+        let hash = sha256_hash("test");
+        assert_eq!(
+            hash,
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let client: IpAddr = "203.0.113.7".parse().unwrap();
+        let trusted = vec!["10.0.0.0/8".to_string()];
+        // 直接连接时忽略伪造的转发头
+        assert_eq!(
+            resolve_client_ip(Some(client), Some("1.2.3.4"), Some("5.6.7.8"), &trusted),
+            "203.0.113.7"
+        );
+        // 经过代理时取最右边不受信任的地址，客户端自己加在左边的地址无效
+        assert_eq!(
+            resolve_client_ip(
+                Some(proxy),
+                Some("1.2.3.4, 203.0.113.7, 10.0.0.2"),
+                None,
+                &trusted
+            ),
+            "203.0.113.7"
+        );
+        assert_eq!(
+            resolve_client_ip(Some(proxy), None, Some("203.0.113.7"), &trusted),
+            "203.0.113.7"
+        );
+        // 客户端在右边加上无法解析的内容时不能跳过它去取左边伪造的地址
+        assert_eq!(
+            resolve_client_ip(Some(proxy), Some("1.2.3.4, garbage"), None, &trusted),
+            "10.0.0.1"
+        );
+        assert_eq!(
+            resolve_client_ip(
+                Some(proxy),
+                Some("1.2.3.4, garbage, 203.0.113.7, 10.0.0.2"),
+                None,
+                &trusted
+            ),
+            "203.0.113.7"
+        );
+        // 全部是受信任的代理时使用连接地址
+        assert_eq!(
+            resolve_client_ip(Some(proxy), Some("10.0.0.3, 10.0.0.2"), None, &trusted),
+            "10.0.0.1"
+        );
+        // 没有配置受信任的代理时只使用连接地址
+        assert_eq!(
+            resolve_client_ip(Some(proxy), Some("1.2.3.4"), None, &[]),
+            "10.0.0.1"
+        );
+    }
 }
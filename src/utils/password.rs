@@ -0,0 +1,95 @@
+use anyhow::anyhow;
+use argon2::password_hash::{
+    PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use crate::config::CFG;
+use crate::result::AppResult;
+use crate::utils;
+
+/// 密码校验结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordVerification {
+    /// 密码错误
+    Mismatch,
+    /// 密码正确
+    Match,
+    /// 密码正确，但存储的哈希是旧格式（MD5）或者参数已经过时，需要重新哈希
+    MatchNeedsRehash,
+}
+
+fn hasher() -> AppResult<Argon2<'static>> {
+    let params = Params::new(
+        CFG.password.memory_cost,
+        CFG.password.time_cost,
+        CFG.password.parallelism,
+        None,
+    )
+    .map_err(|e| anyhow!("密码哈希参数错误: {}", e))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// 旧版本使用不加盐的 MD5 存储密码，格式为 32 位十六进制字符串
+fn is_legacy_md5(stored: &str) -> bool {
+    stored.len() == 32 && stored.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// 使用 Argon2id 和随机盐哈希密码，返回 PHC 格式的字符串
+pub fn hash_password(password: &str) -> AppResult<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = hasher()?
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow!("密码哈希失败: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// 校验明文密码和存储的哈希是否匹配，同时兼容旧的 MD5 哈希
+pub fn verify_password(password: &str, stored: &str) -> PasswordVerification {
+    if is_legacy_md5(stored) {
+        return if utils::md5_hash(password).eq_ignore_ascii_case(stored) {
+            PasswordVerification::MatchNeedsRehash
+        } else {
+            PasswordVerification::Mismatch
+        };
+    }
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        return PasswordVerification::Mismatch;
+    };
+    // 校验时使用哈希中记录的参数，而不是当前配置的参数
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        return PasswordVerification::Mismatch;
+    }
+    let outdated = parsed.algorithm != Algorithm::Argon2id.ident()
+        || Params::try_from(&parsed).is_ok_and(|params| {
+            params.m_cost() != CFG.password.memory_cost
+                || params.t_cost() != CFG.password.time_cost
+                || params.p_cost() != CFG.password.parallelism
+        });
+    if outdated {
+        PasswordVerification::MatchNeedsRehash
+    } else {
+        PasswordVerification::Match
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_legacy_md5() {
+        let stored = "098f6bcd4621d373cade4e832627b4f6";
+        assert_eq!(
+            verify_password("test", stored),
+            PasswordVerification::MatchNeedsRehash
+        );
+        assert_eq!(
+            verify_password("wrong", stored),
+            PasswordVerification::Mismatch
+        );
+    }
+}